@@ -17,6 +17,11 @@ fn main() {
         .write_to_file(out_path.join("mujoco-bindings.rs"))
         .expect("Couldn't write mujoco bindings!");
 
+    // TA-Lib is only linked with the talib feature, the indicators have native implementations otherwise
+    if std::env::var("CARGO_FEATURE_TALIB").is_err() {
+        return;
+    }
+
     println!("cargo:rustc-link-lib=ta_lib");
     println!("cargo:rerun-if-changed=src/wrappers/talib_wrapper.h");
 
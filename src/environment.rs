@@ -42,6 +42,12 @@ pub trait Mujoco: Environment {
     fn data(&mut self) -> &mut crate::wrappers::mujoco::mjData;
 
     fn observation(&self) -> Vec<f64>;
+
+    // named terms that made up the reward of the last step, costs are negative
+    fn reward_info(&self) -> Vec<(String, f64)>;
+
+    // resets draw their noise from seed from now on, so the same seed goes through the same episodes
+    fn seed(&mut self, seed: u64);
 }
 
 impl Trajectory for Transition {
@@ -19,6 +19,9 @@ pub struct HalfCheetahEnv {
     pub episode_length: u32,
     pub step: u32,
     pub episode_ended: bool,
+    pub reward_info: Vec<(String, f64)>,
+    // draws the reset noise, from entropy unless the env was seeded
+    pub rng: rand::prelude::StdRng,
 }
 
 impl Environment for HalfCheetahEnv {
@@ -56,6 +59,11 @@ impl Environment for HalfCheetahEnv {
 
         let obs = self.observation();
 
+        self.reward_info = vec![
+            (String::from("forward_reward"), forward_reward),
+            (String::from("ctrl_cost"), -ctrl_cost),
+        ];
+
         if self.step >= self.episode_length {
             self.episode_ended = true;
             return Box::new(Terminate {
@@ -77,7 +85,7 @@ impl Environment for HalfCheetahEnv {
         let noise_low = -self.reset_noise_scale;
         let noise_high = self.reset_noise_scale;
 
-        let mut rng = <rand::prelude::StdRng as rand::prelude::SeedableRng>::from_entropy();
+        let rng = &mut self.rng;
         let uniform = rand::distributions::Uniform::from(noise_low..noise_high);
         let normal =
             rand_distr::Normal::new(0f64, 1f64).expect("Failed to make normal distribution");
@@ -85,14 +93,14 @@ impl Environment for HalfCheetahEnv {
         let qpos = (0..self.model.nq)
             .map(|idx| {
                 self.init_qpos[idx as usize]
-                    + rand::prelude::Distribution::sample(&uniform, &mut rng)
+                    + rand::prelude::Distribution::sample(&uniform, rng)
             })
             .collect::<Vec<f64>>();
 
         let qvel = (0..self.model.nv)
             .map(|idx| {
                 self.init_qvel[idx as usize]
-                    + rand::prelude::Distribution::sample(&normal, &mut rng)
+                    + rand::prelude::Distribution::sample(&normal, rng)
             })
             .collect::<Vec<f64>>();
 
@@ -109,6 +117,7 @@ impl Environment for HalfCheetahEnv {
 
         self.step = 0;
         self.episode_ended = false;
+        self.reward_info = Vec::new();
         Box::new(Restart {
             observation: self.observation(),
         })
@@ -141,6 +150,14 @@ impl Mujoco for HalfCheetahEnv {
 
         [pos, velocity].concat()
     }
+
+    fn reward_info(&self) -> Vec<(String, f64)> {
+        self.reward_info.clone()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = <rand::prelude::StdRng as rand::prelude::SeedableRng>::seed_from_u64(seed);
+    }
 }
 
 impl Drop for HalfCheetahEnv {
@@ -240,6 +257,8 @@ impl HalfCheetahEnv {
                 episode_length,
                 step: 0,
                 episode_ended: true,
+                reward_info: Vec::new(),
+                rng: <rand::prelude::StdRng as rand::prelude::SeedableRng>::from_entropy(),
             }
         }
     }
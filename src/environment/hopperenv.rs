@@ -24,6 +24,9 @@ pub struct HopperEnv {
     pub episode_length: u32,
     pub step: u32,
     pub episode_ended: bool,
+    pub reward_info: Vec<(String, f64)>,
+    // draws the reset noise, from entropy unless the env was seeded
+    pub rng: rand::prelude::StdRng,
 }
 
 impl Environment for HopperEnv {
@@ -59,6 +62,12 @@ impl Environment for HopperEnv {
         let obs = self.observation();
         let reward = self.get_reward(x_velocity, action.clone());
 
+        self.reward_info = vec![
+            (String::from("forward_reward"), self.forward_reward_weight * x_velocity),
+            (String::from("healthy_reward"), self.health_reward()),
+            (String::from("ctrl_cost"), -self.control_cost(action.clone())),
+        ];
+
         if self.step >= self.episode_length || (!self.is_healthy() && self.terminate_when_unhealthy){
             self.episode_ended = true;
             return Box::new(Terminate {
@@ -80,7 +89,7 @@ impl Environment for HopperEnv {
         let noise_low = -self.reset_noise_scale;
         let noise_high = self.reset_noise_scale;
 
-        let mut rng = <rand::prelude::StdRng as rand::prelude::SeedableRng>::from_entropy();
+        let rng = &mut self.rng;
         let uniform = rand::distributions::Uniform::from(noise_low..noise_high);
         let normal =
             rand_distr::Normal::new(0f64, 1f64).expect("Failed to make normal distribution");
@@ -88,14 +97,14 @@ impl Environment for HopperEnv {
         let qpos = (0..self.model.nq)
             .map(|idx| {
                 self.init_qpos[idx as usize]
-                    + rand::prelude::Distribution::sample(&uniform, &mut rng)
+                    + rand::prelude::Distribution::sample(&uniform, rng)
             })
             .collect::<Vec<f64>>();
 
         let qvel = (0..self.model.nv)
             .map(|idx| {
                 self.init_qvel[idx as usize]
-                    + rand::prelude::Distribution::sample(&normal, &mut rng)
+                    + rand::prelude::Distribution::sample(&normal, rng)
             })
             .collect::<Vec<f64>>();
 
@@ -112,6 +121,7 @@ impl Environment for HopperEnv {
 
         self.step = 0;
         self.episode_ended = false;
+        self.reward_info = Vec::new();
 
         Box::new(Restart {
             observation: self.observation(),
@@ -145,6 +155,14 @@ impl Mujoco for HopperEnv {
 
         [pos, velocity].concat()
     }
+
+    fn reward_info(&self) -> Vec<(String, f64)> {
+        self.reward_info.clone()
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = <rand::prelude::StdRng as rand::prelude::SeedableRng>::seed_from_u64(seed);
+    }
 }
 
 impl Drop for HopperEnv {
@@ -264,6 +282,8 @@ impl HopperEnv {
                 episode_length,
                 step: 0,
                 episode_ended: true,
+                reward_info: Vec::new(),
+                rng: <rand::prelude::StdRng as rand::prelude::SeedableRng>::from_entropy(),
             }
         }
     }
@@ -1,12 +1,243 @@
+extern crate anyhow;
 extern crate polars;
-
+extern crate serde_json;
+
+pub mod bars;
+pub mod benchmarks;
+pub mod costs;
+pub mod margin;
+pub mod orders;
+pub mod report;
+pub mod rewards;
+pub mod splits;
+
+use crate::environment::stockenv::bars::BarArray;
+use crate::environment::stockenv::costs::{CostModel, Fill, StandardCostModel, TradeCost, ZeroCost};
+use crate::environment::stockenv::margin::MarginConfig;
+use crate::environment::stockenv::orders::{Bar, Order, OrderBook, OrderConfig, OrderKind};
+use crate::environment::stockenv::rewards::WeightedReward;
+use crate::environment::stockenv::splits::{DateWindow, Splits, WalkForward};
 use crate::environment::{Environment, Restart, Spec, Terminate, Trajectory, Transition};
-use crate::stockframe::StockFrame;
+use crate::stockframe::indicators::Indicator;
+use crate::stockframe::timeframe::Timeframe;
+use crate::stockframe::{BarError, StockFrame};
+
+// I selected these from s&p 500 index but didnt want these to be all tech stocks so I hand picked them, gotta have some portfolio diversity
+const DEFAULT_TICKERS: [&str; 25] = [
+    "AAPL", "AMD", "AMGN", "BA", "BAC", "BRK.B", "COST", "CRM", "DIS", "GOOG", "JNJ", "JPM", "MA",
+    "MRK", "MSFT", "NKE", "NVDA", "PEP", "RTX", "SPY", "TSLA", "UNH", "UPS", "V", "WMT",
+];
+
+#[derive(Debug, Clone)]
+pub struct StockEnvConfig {
+    pub tickers: Vec<String>,
+    pub initial_balance: f64,
+    pub start: polars::export::chrono::NaiveDateTime,
+    pub end: polars::export::chrono::NaiveDateTime,
+    pub timeframe: Timeframe,
+    // build the bars from cached minute bars instead of fetching the timeframe itself
+    pub resample: bool,
+    // coarser timeframes whose bars and indicators are added to every observation
+    pub feature_timeframes: Vec<Timeframe>,
+    pub indicators: Vec<Indicator>,
+    pub cost_model: std::rc::Rc<dyn CostModel>,
+    // short selling is only allowed with a margin account
+    pub margin: Option<MarginConfig>,
+    // without it every action is a market order at the close
+    pub orders: Option<OrderConfig>,
+    // how start..end is cut into train, validation and test
+    pub splits: Splits,
+    pub reward: WeightedReward,
+}
+
+// the hand picked tickers over the last 15 days with 10k to spend
+impl Default for StockEnvConfig {
+    fn default() -> Self {
+        let end = polars::export::chrono::Utc::now()
+            .date_naive()
+            .and_hms_micro_opt(0, 0, 0, 0)
+            .unwrap();
+
+        StockEnvConfig {
+            tickers: DEFAULT_TICKERS.iter().map(|s| String::from(*s)).collect(),
+            initial_balance: 10000f64,
+            start: end - polars::export::chrono::Duration::days(15),
+            end,
+            timeframe: Timeframe::Minute,
+            resample: false,
+            feature_timeframes: vec![],
+            indicators: Indicator::defaults(),
+            cost_model: std::rc::Rc::new(ZeroCost),
+            margin: None,
+            orders: None,
+            splits: Splits::default(),
+            reward: WeightedReward::default(),
+        }
+    }
+}
+
+impl StockEnvConfig {
+    // {"tickers": ["AAPL", "SPY"], "initial_balance": 25000, "start": "2024-05-01T00:00:00", "end": "2024-06-01T00:00:00",
+    //  "timeframe": "5Min", "resample": true, "feature_timeframes": ["1Hour", "1Day"],
+    //  "indicators": ["atr(14)", "rsi(14)", "bbands(20,2)", "ema(50)"],
+    //  "costs": {"commission": {"percent": 0.0005}, "spread": {"fixed": 0.01}},
+    //  "margin": {"initial_margin": 0.5, "maintenance_margin": 0.3, "borrow_rate": 0.03},
+    //  "orders": {"max_limit_offset": 0.01, "max_stop_distance": 0.05, "max_take_profit": 0.1, "limit_bars": 30},
+    //  "validation_start": "2024-05-20", "test_start": "2024-05-25",
+    //  "walk_forward": {"train_days": 10, "validation_days": 3, "test_days": 5, "step_days": 5, "retrain": true},
+    //  "reward": [{"type": "log_return"}, {"type": "drawdown", "weight": 0.1}]}
+    // anything that is left out keeps its default, no costs means trading is free and no margin means long only
+    pub fn from_file(filename: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(filename)?;
+        let json: serde_json::Value = serde_json::from_str(data.as_str())?;
+
+        let mut config = StockEnvConfig::default();
+
+        let parse_datetime = |value: &serde_json::Value| -> anyhow::Result<polars::export::chrono::NaiveDateTime> {
+            let value = value
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Dates must be strings: {}", value))?;
+
+            match polars::export::chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+                Ok(datetime) => Ok(datetime),
+                Err(_) => Ok(polars::export::chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")?
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()),
+            }
+        };
+
+        let parse_timeframe = |value: &serde_json::Value| -> anyhow::Result<Timeframe> {
+            value
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Timeframes must be strings: {}", value))?
+                .parse()
+        };
+
+        for (key, value) in json
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Stock env config must be a json object"))?
+        {
+            match key.as_str() {
+                "tickers" => {
+                    config.tickers = value
+                        .as_array()
+                        .ok_or_else(|| anyhow::anyhow!("tickers must be a list: {}", value))?
+                        .iter()
+                        .map(|ticker| {
+                            ticker
+                                .as_str()
+                                .map(String::from)
+                                .ok_or_else(|| anyhow::anyhow!("Ticker must be a string: {}", ticker))
+                        })
+                        .collect::<anyhow::Result<Vec<String>>>()?
+                }
+                "initial_balance" => {
+                    config.initial_balance = value
+                        .as_f64()
+                        .ok_or_else(|| anyhow::anyhow!("initial_balance must be a number: {}", value))?
+                }
+                "timeframe" => config.timeframe = parse_timeframe(value)?,
+                "resample" => {
+                    config.resample = value
+                        .as_bool()
+                        .ok_or_else(|| anyhow::anyhow!("resample must be true or false: {}", value))?
+                }
+                "indicators" => {
+                    config.indicators = value
+                        .as_array()
+                        .ok_or_else(|| anyhow::anyhow!("indicators must be a list: {}", value))?
+                        .iter()
+                        .map(|indicator| {
+                            indicator
+                                .as_str()
+                                .ok_or_else(|| anyhow::anyhow!("Indicator must be a string: {}", indicator))?
+                                .parse()
+                        })
+                        .collect::<anyhow::Result<Vec<Indicator>>>()?
+                }
+                "feature_timeframes" => {
+                    config.feature_timeframes = value
+                        .as_array()
+                        .ok_or_else(|| anyhow::anyhow!("feature_timeframes must be a list: {}", value))?
+                        .iter()
+                        .map(parse_timeframe)
+                        .collect::<anyhow::Result<Vec<Timeframe>>>()?
+                }
+                "start" => config.start = parse_datetime(value)?,
+                "end" => config.end = parse_datetime(value)?,
+                "costs" => config.cost_model = std::rc::Rc::new(StandardCostModel::from_json(value)?),
+                "margin" => config.margin = Some(MarginConfig::from_json(value)?),
+                "orders" => config.orders = Some(OrderConfig::from_json(value)?),
+                "validation_start" => config.splits.validation_start = Some(parse_datetime(value)?),
+                "test_start" => config.splits.test_start = Some(parse_datetime(value)?),
+                "walk_forward" => config.splits.walk_forward = Some(WalkForward::from_json(value)?),
+                "reward" => config.reward = WeightedReward::from_json(value)?,
+                _ => anyhow::bail!("Unknown key in stock env config: {}", key),
+            }
+        }
+
+        if config.tickers.is_empty() {
+            anyhow::bail!("Stock env config needs at least one ticker");
+        }
+
+        // stops and volatility scaled spreads are measured in atr
+        if !config
+            .indicators
+            .iter()
+            .any(|indicator| matches!(indicator, Indicator::Atr(_)))
+        {
+            anyhow::bail!("indicators must include an atr");
+        }
+
+        for (idx, indicator) in config.indicators.iter().enumerate() {
+            if config.indicators[..idx].contains(indicator) {
+                anyhow::bail!("Indicator {} is listed twice", indicator);
+            }
+        }
+
+        if let Some(timeframe) = config
+            .feature_timeframes
+            .iter()
+            .find(|timeframe| **timeframe <= config.timeframe)
+        {
+            anyhow::bail!(
+                "Feature timeframe {} must be coarser than the env timeframe {}",
+                timeframe,
+                config.timeframe
+            );
+        }
+
+        if config.start >= config.end {
+            anyhow::bail!("Stock env start {} must be before end {}", config.start, config.end);
+        }
+
+        match config.splits.walk_forward {
+            Some(walk_forward) => {
+                walk_forward.folds(config.start, config.end)?;
+            }
+            None => {
+                config.splits.windows(config.start, config.end)?;
+            }
+        }
+
+        Ok(config)
+    }
+}
 
 #[derive(Clone)]
 pub struct StockEnv {
     pub stockframe: Box<StockFrame>,
-    pub data: polars::prelude::DataFrame,
+    // shared between clones, nothing writes to it after construction
+    pub bars: std::rc::Rc<BarArray>,
+    // index of the current bar in bars
+    pub cursor: usize,
+
+    pub tickers: Vec<String>,
+    pub initial_balance: f64,
+    pub cost_model: std::rc::Rc<dyn CostModel>,
+    pub margin: Option<MarginConfig>,
+    pub order_config: Option<OrderConfig>,
+    pub reward_function: WeightedReward,
 
     pub iteration: u32,
     pub feature_length: u32,
@@ -21,8 +252,27 @@ pub struct StockEnv {
     pub portfolio_asset: Vec<f64>,
     pub buy_price: Vec<f64>,
     pub unrealized_pnl: Vec<f64>,
+    // what trading cost in every step so far
+    pub cost_history: Vec<TradeCost>,
+    // what holding shorts cost in every step so far
+    pub borrow_fees: Vec<f64>,
+    // value of all fills and the gross exposure after every step
+    pub traded_value: Vec<f64>,
+    pub gross_exposures: Vec<f64>,
+    // realized pnl of every sell or cover, costs included
+    pub closed_trades: Vec<f64>,
+    // times every short got bought back for falling under maintenance margin
+    pub liquidations: u32,
+    // every resting order that filled, with when and at what price
+    pub filled_orders: Vec<(polars::export::chrono::NaiveDateTime, Order, f64)>,
 
     pub portfolio_value: f64,
+    pub step_costs: TradeCost,
+    pub step_traded: f64,
+    // summed position values over equity, absolute for gross
+    pub gross_exposure: f64,
+    pub net_exposure: f64,
+    pub order_book: OrderBook,
     pub state: Vec<f64>,
     pub reward: f64,
 }
@@ -33,7 +283,7 @@ fn calc_returns(series: polars::prelude::Series) -> polars::prelude::Series {
 }
 
 fn calc_gain_to_pain(series: polars::prelude::Series) -> f64 {
-    let returns = calc_returns(series.drop_nulls().tail(Some(30)));
+    let returns = calc_returns(series.drop_nulls());
     let sum_returns: f64 = returns.sum().unwrap();
 
     let sum_neg_returns: f64 = returns
@@ -95,12 +345,35 @@ fn calc_lake_ratio(series: polars::prelude::Series) -> f64 {
     water / earth
 }
 
+// fills the gaps between bars of a session and adds the indicators
+fn prepare(stockframe: &mut StockFrame, indicators: &[Indicator]) -> Result<(), BarError> {
+    stockframe.fill_date_range();
+    stockframe.fill_nulls();
+    stockframe.calc_technical_indicators(indicators)?;
+
+    // fill volume, vwap, trade_count and the indicators' warm up with zeros
+    stockframe.frame = std::cell::RefCell::new(
+        stockframe
+            .clone()
+            .frame
+            .borrow_mut()
+            .fill_null(polars::prelude::FillNullStrategy::Zero)
+            .unwrap(),
+    );
+
+    Ok(())
+}
+
 impl Environment for StockEnv {
     fn action_spec(&self) -> Spec {
         Spec {
             min: -1.0,
             max: 1.0,
-            shape: tickers.len() as u32,
+            // sizes, limit offsets, stop distances and take profits when orders can rest
+            shape: match self.order_config {
+                Some(_) => 4 * self.tickers.len() as u32,
+                None => self.tickers.len() as u32,
+            },
         }
     }
 
@@ -117,142 +390,77 @@ impl Environment for StockEnv {
             return self.reset();
         }
 
-        let mut new_ts = self.timestamp + polars::export::chrono::Duration::minutes(1);
-        let mut data: polars::prelude::DataFrame;
-
-        loop {
-            data = polars::prelude::IntoLazy::lazy(self.stockframe.frame.borrow().clone())
-                .filter(
-                    polars::prelude::col("timestamp")
-                        .dt()
-                        .timestamp(polars::datatypes::TimeUnit::Milliseconds)
-                        .eq(new_ts.timestamp_millis()),
-                )
-                .collect()
-                .unwrap();
+        let next = self.cursor + 1;
 
-            if data.shape().0 != 0 {
-                break;
-            } else {
-                new_ts += polars::export::chrono::Duration::minutes(1);
-
-                if new_ts.timestamp_millis() > self.train_end.timestamp_millis() {
-                    self.episode_ended = true;
-                    return Box::new(Terminate {
-                        observation: self.state.clone(),
-                        reward: 0.0,
-                    });
-                }
-            }
+        if next >= self.bars.len() || self.bars.timestamps[next] > self.train_end {
+            self.episode_ended = true;
+            return Box::new(Terminate {
+                observation: self.state.clone(),
+                reward: 0.0,
+            });
         }
 
-        let flat_data: Vec<f64> = data
-            .clone()
-            .drop_many(&[String::from("symbol"), String::from("timestamp")])
-            .to_ndarray::<polars::prelude::Float64Type>(polars::prelude::IndexOrder::C)
-            .unwrap()
-            .iter()
-            .copied()
-            .collect();
-        self.data = data.clone();
+        let new_ts = self.bars.timestamps[next];
+        let flat_data = self.bars.row(next).to_vec();
+        let elapsed = new_ts - self.timestamp;
+        self.cursor = next;
         self.timestamp = new_ts;
 
-        self.portfolio_value = (0..tickers.len())
-            .collect::<Vec<usize>>()
-            .iter()
-            .map(|idx| {
-                let symbol = tickers[*idx];
-                let df = self.data.clone();
-                let ticker_df = polars::prelude::IntoLazy::lazy(df)
-                    .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(symbol)))
-                    .collect()
-                    .unwrap();
-
-                assert_ne!(ticker_df.shape().0, 0); // data must exist nulls are bad
-
-                ticker_df["close"].f64().unwrap().get(0).unwrap()
-            })
-            .collect::<Vec<f64>>()
-            .iter()
-            .sum();
+        self.step_costs = TradeCost::default();
+        self.step_traded = 0f64;
+
+        // borrowed shares cost something for as long as they are held
+        let borrow_fee = match self.margin {
+            Some(margin) => margin.borrow_fee(self.short_value(), elapsed),
+            None => 0f64,
+        };
+        self.state[0] -= borrow_fee;
 
-        let total_asset_starting = self.state[0] + self.portfolio_value;
+        // orders left from earlier bars get their chance before anything new is placed
+        self.fill_orders();
 
         // we do all the sell order before buy orders to free up cash
-        let mut indices: Vec<usize> = (0..action.len()).collect();
+        let mut indices: Vec<usize> = (0..self.tickers.len()).collect();
         indices.sort_by(|&i, &j| action[i].partial_cmp(&action[j]).unwrap());
 
         for idx in indices {
-            if action[idx] < 0f64 {
-                self.sell(idx as u32, action[idx]);
-            } else if action[idx] > 0f64 {
-                self.buy(idx as u32, action[idx]);
-            }
+            self.place_order(idx, &action);
         }
 
-        self.unrealized_pnl = (0..tickers.len())
-            .collect::<Vec<usize>>()
-            .iter()
-            .map(|idx| {
-                let symbol = tickers[*idx];
-                let df = self.data.clone();
-                let ticker_df = polars::prelude::IntoLazy::lazy(df)
-                    .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(symbol)))
-                    .collect()
-                    .unwrap();
-
-                assert_ne!(ticker_df.shape().0, 0); // data must exist nulls are bad
-
-                (ticker_df["close"].f64().unwrap().get(0).unwrap() - self.buy_price[*idx])
-                    * self.state[*idx + self.feature_length as usize]
-            })
+        self.check_maintenance_margin();
+
+        // buy_price is the average entry of shorts too, so negative holdings make money when the price drops
+        self.unrealized_pnl = (0..self.tickers.len())
+            .map(|idx| (self.close_price(idx) - self.buy_price[idx]) * self.holding(idx))
             .collect::<Vec<f64>>();
 
+        self.portfolio_value = (0..self.tickers.len())
+            .map(|idx| self.position_value(idx))
+            .sum();
+
+        let total_asset_ending = self.equity();
+
+        (self.gross_exposure, self.net_exposure) = self.exposure();
+
         self.state = [
             vec![self.state[0]],
             self.unrealized_pnl.clone(),
+            vec![self.gross_exposure, self.net_exposure],
             flat_data,
             self.state[(self.feature_length as usize)..].to_vec(),
         ]
         .concat();
 
-        self.portfolio_value = (0..tickers.len())
-            .collect::<Vec<usize>>()
-            .iter()
-            .map(|idx| {
-                let symbol = tickers[*idx];
-                let df = self.data.clone();
-                let ticker_df = polars::prelude::IntoLazy::lazy(df)
-                    .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(symbol)))
-                    .collect()
-                    .unwrap();
-
-                assert_ne!(ticker_df.shape().0, 0); // data must exist nulls are bad
-
-                ticker_df["close"].f64().unwrap().get(0).unwrap()
-            })
-            .collect::<Vec<f64>>()
-            .iter()
-            .sum();
-
-        let total_asset_ending = self.state[0] + self.portfolio_value;
-
         self.acc_balance.push(self.state[0]);
         self.portfolio_asset.push(self.portfolio_value);
         self.total_asset.push(total_asset_ending);
         self.timeline.push(self.timestamp);
+        self.cost_history.push(self.step_costs);
+        self.borrow_fees.push(borrow_fee);
+        self.traded_value.push(self.step_traded);
+        self.gross_exposures.push(self.gross_exposure);
 
-        if self.total_asset.len() > 29 {
-            let total_asset = <polars::prelude::Series as polars::prelude::NamedFrom<
-                Vec<f64>,
-                _,
-            >>::new("_", self.total_asset.clone());
-            self.reward = total_asset_ending - total_asset_starting
-                + (100f64 * calc_gain_to_pain(total_asset.clone()))
-                - (500f64 * calc_lake_ratio(total_asset.clone()));
-        } else {
-            self.reward = total_asset_ending - total_asset_starting;
-        }
+        self.reward = self.reward_function.reward(&self.total_asset);
 
         Box::new(Transition {
             observation: self.state.clone(),
@@ -262,40 +470,37 @@ impl Environment for StockEnv {
 
     fn reset(&mut self) -> Box<dyn Trajectory> {
         self.episode_ended = false;
-        self.acc_balance = vec![10000f64];
-        self.total_asset = vec![10000f64];
+        self.acc_balance = vec![self.initial_balance];
+        self.total_asset = vec![self.initial_balance];
         self.portfolio_asset = vec![0f64];
-        self.buy_price = vec![0f64; tickers.len()];
-        self.unrealized_pnl = vec![0f64];
+        self.reward_function.reset();
+        self.buy_price = vec![0f64; self.tickers.len()];
+        self.unrealized_pnl = vec![0f64; self.tickers.len()];
+        self.cost_history = vec![];
+        self.borrow_fees = vec![];
+        self.traded_value = vec![];
+        self.gross_exposures = vec![];
+        self.closed_trades = vec![];
+        self.step_traded = 0.0;
+        self.liquidations = 0;
+        self.filled_orders = vec![];
+        self.order_book = OrderBook::new(self.tickers.len());
         self.portfolio_value = 0.0;
+        self.step_costs = TradeCost::default();
+        self.gross_exposure = 0.0;
+        self.net_exposure = 0.0;
 
-        self.timestamp = self.train_start;
+        self.cursor = self.bars.position(self.train_start).unwrap_or(0);
+        self.timestamp = self.bars.timestamps[self.cursor];
         self.timeline = vec![self.timestamp];
 
-        self.data = polars::prelude::IntoLazy::lazy(self.stockframe.frame.borrow().clone())
-            .filter(
-                polars::prelude::col("timestamp")
-                    .dt()
-                    .timestamp(polars::datatypes::TimeUnit::Milliseconds)
-                    .eq(self.timestamp.timestamp_millis()),
-            )
-            .collect()
-            .unwrap();
-
-        let flat_data: Vec<f64> = self
-            .data
-            .clone()
-            .drop_many(&[String::from("symbol"), String::from("timestamp")])
-            .to_ndarray::<polars::prelude::Float64Type>(polars::prelude::IndexOrder::C)
-            .unwrap()
-            .iter()
-            .copied()
-            .collect();
+        let flat_data = self.bars.row(self.cursor).to_vec();
         self.state = [
             self.acc_balance.clone(),
             self.unrealized_pnl.clone(),
+            vec![self.gross_exposure, self.net_exposure],
             flat_data,
-            vec![0f64; tickers.len()],
+            vec![0f64; self.tickers.len()],
         ]
         .concat();
         self.iteration += 1;
@@ -306,41 +511,57 @@ impl Environment for StockEnv {
     }
 }
 
-// I selected these from s&p 500 index but didnt want these to be all tech stocks so I hand picked them, gotta have some portfolio diversity
-const tickers: [&str; 25] = [
-    "AAPL", "AMD", "AMGN", "BA", "BAC", "BRK.B", "COST", "CRM", "DIS", "GOOG", "JNJ", "JPM", "MA",
-    "MRK", "MSFT", "NKE", "NVDA", "PEP", "RTX", "SPY", "TSLA", "UNH", "UPS", "V", "WMT",
-];
-
 impl StockEnv {
-    pub fn new(
-        start: polars::export::chrono::NaiveDateTime,
-        end: polars::export::chrono::NaiveDateTime,
-    ) -> Self {
-        let mut stockframe = StockFrame::new(
-            Some(tickers.iter().map(|s| String::from(*s)).collect()),
-            Some(start),
-            Some(end),
-        );
+    pub fn new(config: StockEnvConfig) -> Result<Self, BarError> {
+        let fetch_timeframe = match config.resample {
+            true => Timeframe::Minute,
+            false => config.timeframe,
+        };
 
+        let stockframe = StockFrame::new(
+            Some(config.tickers.clone()),
+            Some(config.start),
+            Some(config.end),
+            Some(fetch_timeframe),
+        )?;
+
+        StockEnv::from_stockframe(stockframe, config)
+    }
+
+    // bars that were already loaded, e.g. from a local file, they are cleaned and prepared like fetched ones
+    pub fn from_stockframe(mut stockframe: StockFrame, config: StockEnvConfig) -> Result<Self, BarError> {
         stockframe.parse_dt_column();
-        stockframe.fill_date_range();
-        stockframe.fill_nulls();
 
-        unsafe {
-            stockframe.calc_technical_indicators();
+        // extended hours and holiday bars go before anything is resampled from them
+        stockframe.clean();
+
+        if stockframe.timeframe != config.timeframe {
+            stockframe = stockframe.resample(config.timeframe)?;
         }
 
-        // fill volume, vwap, and trade_count with zeros
-        stockframe.frame = std::cell::RefCell::new(
-            stockframe
-                .clone()
-                .frame
-                .borrow_mut()
-                .fill_null(polars::prelude::FillNullStrategy::Zero)
-                .unwrap(),
-        );
-        stockframe.clean();
+        // other timeframes are resampled from the bars before anything got filled in
+        let raw = stockframe.clone();
+
+        // only fills in bars of sessions, so everything stepped through is a bar the exchange was open for
+        prepare(&mut stockframe, &config.indicators)?;
+
+        for timeframe in config.feature_timeframes.iter() {
+            let mut coarse = raw.resample(*timeframe)?;
+            prepare(&mut coarse, &config.indicators)?;
+
+            stockframe.join_timeframe(&coarse)?;
+        }
+
+        // rows from before the first coarser bar closed
+        if !config.feature_timeframes.is_empty() {
+            stockframe.frame = std::cell::RefCell::new(
+                stockframe
+                    .frame
+                    .borrow()
+                    .fill_null(polars::prelude::FillNullStrategy::Zero)
+                    .unwrap(),
+            );
+        }
 
         // sort
         stockframe.update_symbol_groups();
@@ -353,50 +574,40 @@ impl StockEnv {
                 .unwrap(),
         );
 
-        let acc_balance = vec![10000f64];
-        let total_asset = vec![10000f64];
+        let acc_balance = vec![config.initial_balance];
+        let total_asset = vec![config.initial_balance];
         let portfolio_asset = vec![0f64];
-        let buy_price = vec![0f64; tickers.len()];
-        let unrealized_pnl = vec![0f64];
-
-        let mut df_start = stockframe.get_min_timestamp();
-        let df_end = stockframe.get_min_timestamp();
-
-        // search for next valid timestamp
-        let mut data: polars::prelude::DataFrame;
-
-        loop {
-            data = polars::prelude::IntoLazy::lazy(stockframe.frame.borrow().clone())
-                .filter(
-                    polars::prelude::col("timestamp")
-                        .dt()
-                        .timestamp(polars::datatypes::TimeUnit::Milliseconds)
-                        .eq(df_start.timestamp_millis()),
-                )
-                .collect()
-                .unwrap();
-
-            if data.shape().0 != 0 {
-                break;
-            } else {
-                df_start += polars::export::chrono::Duration::minutes(1);
-            }
+        let buy_price = vec![0f64; config.tickers.len()];
+        let unrealized_pnl = vec![0f64; config.tickers.len()];
+
+        let bars = BarArray::from_frame(&stockframe.frame.borrow(), &config.tickers)?;
+
+        if bars.is_empty() {
+            return Err(BarError::NoData(format!(
+                "No timestamp has bars for all of {}",
+                config.tickers.join(", ")
+            )));
         }
 
+        let df_start = bars.timestamps[0];
+        let df_end = stockframe.get_max_timestamp();
+
         let timeline = vec![df_start];
 
-        let flat_data: Vec<f64> = data
-            .clone()
-            .drop_many(&[String::from("symbol"), String::from("timestamp")])
-            .to_ndarray::<polars::prelude::Float64Type>(polars::prelude::IndexOrder::C)
-            .unwrap()
-            .iter()
-            .copied()
-            .collect();
-        let feature_length = 2 + flat_data.len();
+        let flat_data = bars.row(0).to_vec();
+        // cash, unrealized pnl per ticker, gross and net exposure and the bars, holdings come after
+        let feature_length = 1 + unrealized_pnl.len() + 2 + flat_data.len();
 
-        StockEnv {
+        Ok(StockEnv {
             stockframe: Box::new(stockframe),
+            bars: std::rc::Rc::new(bars),
+            cursor: 0,
+            tickers: config.tickers.clone(),
+            initial_balance: config.initial_balance,
+            cost_model: config.cost_model.clone(),
+            margin: config.margin,
+            order_config: config.orders,
+            reward_function: config.reward.clone(),
             iteration: 0,
             feature_length: feature_length as u32,
             train_start: df_start,
@@ -409,34 +620,162 @@ impl StockEnv {
             portfolio_asset: portfolio_asset.clone(),
             buy_price: buy_price.clone(),
             unrealized_pnl: unrealized_pnl.clone(),
+            cost_history: vec![],
+            borrow_fees: vec![],
+            traded_value: vec![],
+            gross_exposures: vec![],
+            closed_trades: vec![],
+            step_traded: 0.0,
+            liquidations: 0,
+            filled_orders: vec![],
             portfolio_value: 0.0,
-            data: data.clone(),
+            step_costs: TradeCost::default(),
+            gross_exposure: 0.0,
+            net_exposure: 0.0,
+            order_book: OrderBook::new(config.tickers.len()),
             state: [
                 acc_balance,
                 unrealized_pnl,
+                vec![0f64, 0f64],
                 flat_data,
-                vec![0f64; tickers.len()],
+                vec![0f64; config.tickers.len()],
             ]
             .concat(),
             reward: 0.0,
+        })
+    }
+
+    // the same bars limited to one window, episodes then start at its first bar and end at its last
+    pub fn window(&self, window: DateWindow) -> Result<Self, BarError> {
+        match self.bars.position(window.start) {
+            Some(start) if self.bars.timestamps[start] <= window.end => {
+                let mut env = self.clone();
+                env.train_start = window.start;
+                env.train_end = window.end;
+                env.episode_ended = true;
+
+                Ok(env)
+            }
+            _ => Err(BarError::NoData(format!("No bars between {}", window))),
         }
     }
 
-    pub fn buy(&mut self, idx: u32, action: f64) {
-        let symbol = tickers[idx as usize];
-        let df = self.data.clone();
-        let ticker_df = polars::prelude::IntoLazy::lazy(df)
-            .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(symbol)))
-            .collect()
-            .unwrap();
+    // what the account made this episode as a fraction of the initial balance
+    pub fn total_return(&self) -> f64 {
+        self.total_asset.last().unwrap_or(&self.initial_balance) / self.initial_balance - 1f64
+    }
+
+    // close of the current bar for the ticker at idx
+    pub fn close_price(&self, idx: usize) -> f64 {
+        self.bar_value(idx, self.bars.columns.close)
+    }
+
+    // feature of the current bar for the ticker at idx, see bars.columns for where things are
+    pub fn bar_value(&self, idx: usize, feature: usize) -> f64 {
+        self.bars.value(self.cursor, idx, feature)
+    }
+
+    fn trade_cost(&self, idx: usize, shares: f64, price: f64) -> TradeCost {
+        self.cost_model.cost(&Fill {
+            shares,
+            price,
+            bar_volume: self.bar_value(idx, self.bars.columns.volume),
+            atr: self.bar_value(idx, self.bars.columns.atr),
+        })
+    }
+
+    // shares held of the ticker at idx, negative when short
+    pub fn holding(&self, idx: usize) -> f64 {
+        self.state[idx + self.feature_length as usize]
+    }
+
+    pub fn position_value(&self, idx: usize) -> f64 {
+        self.holding(idx) * self.close_price(idx)
+    }
 
-        assert_ne!(ticker_df.shape().0, 0); // data must exist nulls are bad
+    // cash plus what every position is worth, shorts count against it
+    pub fn equity(&self) -> f64 {
+        self.state[0]
+            + (0..self.tickers.len())
+                .map(|idx| self.position_value(idx))
+                .sum::<f64>()
+    }
+
+    // value of all borrowed shares as a positive number
+    pub fn short_value(&self) -> f64 {
+        (0..self.tickers.len())
+            .map(|idx| f64::min(self.position_value(idx), 0f64).abs())
+            .sum()
+    }
+
+    // gross and net position value as a fraction of equity, nothing is exposed once equity is gone
+    pub fn exposure(&self) -> (f64, f64) {
+        let equity = self.equity();
+
+        if equity <= 0f64 {
+            return (0f64, 0f64);
+        }
+
+        let values: Vec<f64> = (0..self.tickers.len())
+            .map(|idx| self.position_value(idx))
+            .collect();
+
+        (
+            values.iter().map(|value| value.abs()).sum::<f64>() / equity,
+            values.iter().sum::<f64>() / equity,
+        )
+    }
+
+    // cash that isn't held back to cover shorts and their initial margin
+    pub fn free_cash(&self) -> f64 {
+        match self.margin {
+            Some(margin) => self.state[0] - self.short_value() * (1f64 + margin.initial_margin),
+            None => self.state[0],
+        }
+    }
+
+    fn check_maintenance_margin(&mut self) {
+        let margin = match self.margin {
+            Some(margin) => margin,
+            None => return,
+        };
+
+        let short_value = self.short_value();
+
+        if short_value > 0f64 && self.equity() < margin.maintenance_margin * short_value {
+            for idx in 0..self.tickers.len() {
+                if self.holding(idx) < 0f64 {
+                    self.cover(idx, 1f64, self.close_price(idx));
+                }
+            }
+
+            self.liquidations += 1;
+        }
+    }
+
+    pub fn buy(&mut self, idx: u32, action: f64, price: f64) {
+        if self.holding(idx as usize) < 0f64 {
+            return self.cover(idx as usize, action, price);
+        }
+
+        let cash = self.free_cash();
+        let available_unit = f64::max((cash / price).floor(), 0f64);
+        let mut num_share = (action * available_unit).floor();
+        let mut cost = self.trade_cost(idx as usize, num_share, price);
+
+        // costs come out of the same cash, so buy fewer shares until the whole thing is affordable
+        while num_share > 0f64 && num_share * price + cost.total() > cash {
+            num_share = (num_share * cash / (num_share * price + cost.total())).floor();
+            cost = self.trade_cost(idx as usize, num_share, price);
+        }
 
-        let price = ticker_df["close"].f64().unwrap().get(0).unwrap();
-        let available_unit = (self.state[0] / price).floor();
-        let num_share = (action * available_unit).floor();
+        if num_share <= 0f64 {
+            return;
+        }
 
-        self.state[0] -= num_share * price;
+        self.state[0] -= num_share * price + cost.total();
+        self.step_costs += cost;
+        self.step_traded += num_share * price;
 
         // if theres existing holdings take average price
         if self.state[(idx + self.feature_length) as usize] > 0f64 {
@@ -452,22 +791,25 @@ impl StockEnv {
         self.state[(idx + self.feature_length) as usize] += num_share;
     }
 
-    pub fn sell(&mut self, idx: u32, action: f64) {
-        let num_share = (action.abs() * self.state[(idx + self.feature_length) as usize]).floor();
+    pub fn sell(&mut self, idx: u32, action: f64, price: f64) {
+        if self.holding(idx as usize) <= 0f64 {
+            if let Some(margin) = self.margin {
+                self.short(idx as usize, action.abs(), price, margin);
+            }
 
-        let symbol = tickers[idx as usize];
-        let df = self.data.clone();
-        let ticker_df = polars::prelude::IntoLazy::lazy(df)
-            .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(symbol)))
-            .collect()
-            .unwrap();
+            return;
+        }
 
-        assert_ne!(ticker_df.shape().0, 0); // data must exist nulls are bad
+        let num_share = (action.abs() * self.state[(idx + self.feature_length) as usize]).floor();
 
-        let price = ticker_df["close"].f64().unwrap().get(0).unwrap();
+        if self.state[(idx + self.feature_length) as usize] > 0f64 && num_share > 0f64 {
+            let cost = self.trade_cost(idx as usize, num_share, price);
 
-        if self.state[(idx + self.feature_length) as usize] > 0f64 {
-            self.state[0] += price * num_share;
+            self.state[0] += price * num_share - cost.total();
+            self.step_costs += cost;
+            self.step_traded += num_share * price;
+            self.closed_trades
+                .push((price - self.buy_price[idx as usize]) * num_share - cost.total());
             self.state[(idx + self.feature_length) as usize] -= num_share;
 
             // reset price if thats the last share
@@ -476,4 +818,174 @@ impl StockEnv {
             }
         }
     }
+
+    // borrows and sells shares, the proceeds stay in cash but are held back together with the initial margin
+    fn short(&mut self, idx: usize, action: f64, price: f64, margin: MarginConfig) {
+        let cash = self.free_cash();
+        let available_unit = f64::max((cash / (price * margin.initial_margin)).floor(), 0f64);
+        let mut num_share = (action * available_unit).floor();
+        let mut cost = self.trade_cost(idx, num_share, price);
+
+        while num_share > 0f64 && num_share * price * margin.initial_margin + cost.total() > cash {
+            num_share = (num_share * cash / (num_share * price * margin.initial_margin + cost.total())).floor();
+            cost = self.trade_cost(idx, num_share, price);
+        }
+
+        if num_share <= 0f64 {
+            return;
+        }
+
+        self.state[0] += num_share * price - cost.total();
+        self.step_costs += cost;
+        self.step_traded += num_share * price;
+
+        // average entry price of the short
+        let existing_short = self.holding(idx).abs();
+        self.buy_price[idx] = ((existing_short * self.buy_price[idx]) + (price * num_share)) / (existing_short + num_share);
+        self.state[idx + self.feature_length as usize] -= num_share;
+    }
+
+    // buys back part of a short, action of 1 closes it completely
+    fn cover(&mut self, idx: usize, action: f64, price: f64) {
+        let num_share = (action * self.holding(idx).abs()).floor();
+
+        if num_share <= 0f64 {
+            return;
+        }
+
+        let cost = self.trade_cost(idx, num_share, price);
+
+        self.state[0] -= num_share * price + cost.total();
+        self.step_costs += cost;
+        self.step_traded += num_share * price;
+        self.closed_trades.push((self.buy_price[idx] - price) * num_share - cost.total());
+        self.state[idx + self.feature_length as usize] += num_share;
+
+        if self.holding(idx) == 0f64 {
+            self.buy_price[idx] = 0.0;
+        }
+    }
+
+    fn execute(&mut self, idx: usize, action: f64, price: f64) {
+        if action < 0f64 {
+            self.sell(idx as u32, action, price);
+        } else if action > 0f64 {
+            self.buy(idx as u32, action, price);
+        }
+    }
+
+    // market order at the close, or a limit order that rests until the price comes to it
+    fn place_order(&mut self, idx: usize, action: &[f64]) {
+        let config = match self.order_config {
+            Some(config) => config,
+            None => return self.execute(idx, action[idx], self.close_price(idx)),
+        };
+
+        let intent = config.intent(action, idx, self.tickers.len());
+        let price = self.close_price(idx);
+
+        match intent.limit_offset {
+            _ if intent.size == 0f64 => {}
+            None => self.execute(idx, intent.size, price),
+            // buy below and sell above the close, the bracket waits for the fill
+            Some(offset) => {
+                self.order_book.limit[idx] = Some(Order {
+                    ticker: idx,
+                    kind: OrderKind::Limit,
+                    action: intent.size,
+                    price: price * (1f64 - intent.size.signum() * offset),
+                    stop_distance: intent.stop_distance,
+                    take_profit: intent.take_profit,
+                    bars_left: Some(config.limit_bars),
+                });
+
+                return;
+            }
+        }
+
+        // protect what is held now, which lets the agent trail its stops every bar
+        self.protect(idx, price, intent.stop_distance, intent.take_profit);
+    }
+
+    // replaces the stop loss and take profit of a position with ones measured from entry, only the given ones change
+    fn protect(&mut self, idx: usize, entry: f64, stop_distance: Option<f64>, take_profit: Option<f64>) {
+        let shares = self.holding(idx);
+
+        if shares == 0f64 {
+            return;
+        }
+
+        let (stop_loss, take_profit) = Order::bracket(idx, shares, entry, stop_distance, take_profit);
+
+        if stop_loss.is_some() {
+            self.order_book.stop_loss[idx] = stop_loss;
+        }
+
+        if take_profit.is_some() {
+            self.order_book.take_profit[idx] = take_profit;
+        }
+    }
+
+    // runs every resting order against the current bar's high and low
+    fn fill_orders(&mut self) {
+        for idx in 0..self.tickers.len() {
+            if self.order_book.resting() == 0 {
+                return;
+            }
+
+            let bar = Bar {
+                open: self.bar_value(idx, self.bars.columns.open),
+                high: self.bar_value(idx, self.bars.columns.high),
+                low: self.bar_value(idx, self.bars.columns.low),
+            };
+            let shares = self.holding(idx);
+
+            // protective orders go away with the position they were protecting
+            let protective = |order: Option<Order>| order.filter(|order| order.action * shares < 0f64);
+            let stop_loss = protective(self.order_book.stop_loss[idx]);
+            let take_profit = protective(self.order_book.take_profit[idx]);
+
+            // with both inside one bar there is no telling which came first, so assume the worse one did
+            let triggered = stop_loss
+                .and_then(|order| order.fill_price(&bar).map(|price| (order, price)))
+                .or_else(|| take_profit.and_then(|order| order.fill_price(&bar).map(|price| (order, price))));
+
+            match triggered {
+                Some((order, price)) => {
+                    match shares > 0f64 {
+                        true => self.sell(idx as u32, -1f64, price),
+                        false => self.cover(idx, 1f64, price),
+                    }
+
+                    self.filled_orders.push((self.timestamp, order, price));
+                    self.order_book.stop_loss[idx] = None;
+                    self.order_book.take_profit[idx] = None;
+                }
+                None => {
+                    self.order_book.stop_loss[idx] = stop_loss;
+                    self.order_book.take_profit[idx] = take_profit;
+                }
+            }
+
+            if let Some(order) = self.order_book.limit[idx] {
+                self.order_book.limit[idx] = None;
+
+                match order.fill_price(&bar) {
+                    Some(price) => {
+                        self.execute(idx, order.action, price);
+                        self.filled_orders.push((self.timestamp, order, price));
+                        self.protect(idx, price, order.stop_distance, order.take_profit);
+                    }
+                    None => {
+                        if let Some(bars_left) = order.bars_left.filter(|bars_left| *bars_left > 1) {
+                            self.order_book.limit[idx] = Some(Order {
+                                bars_left: Some(bars_left - 1),
+                                ..order
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
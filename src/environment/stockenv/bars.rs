@@ -0,0 +1,166 @@
+extern crate polars;
+
+use crate::stockframe::BarError;
+
+// where the columns StockEnv trades on ended up in the feature axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarColumns {
+    pub open: usize,
+    pub high: usize,
+    pub low: usize,
+    pub close: usize,
+    pub volume: usize,
+    pub atr: usize,
+}
+
+// the frame pivoted into a dense timestamp x ticker x feature array, so stepping is an index instead of a filter
+#[derive(Debug, Clone)]
+pub struct BarArray {
+    pub timestamps: Vec<polars::export::chrono::NaiveDateTime>,
+    pub tickers: Vec<String>,
+    pub features: Vec<String>,
+    pub columns: BarColumns,
+    values: Vec<f64>,
+}
+
+impl BarArray {
+    // tickers keep the given order and features the frame's, only timestamps every ticker has a bar at are kept
+    pub fn from_frame(df: &polars::prelude::DataFrame, tickers: &[String]) -> Result<Self, BarError> {
+        let features: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .filter(|name| *name != "symbol" && *name != "timestamp")
+            .map(|name| name.to_string())
+            .collect();
+
+        let feature = |name: &str| {
+            features
+                .iter()
+                .position(|feature| feature == name)
+                .ok_or_else(|| BarError::MissingColumn(String::from(name)))
+        };
+
+        let columns = BarColumns {
+            open: feature("open")?,
+            high: feature("high")?,
+            low: feature("low")?,
+            close: feature("close")?,
+            volume: feature("volume")?,
+            atr: features
+                .iter()
+                .position(|feature| is_indicator_column(feature, "atr"))
+                .ok_or_else(|| BarError::MissingColumn(String::from("atr")))?,
+        };
+
+        // every ticker's features column by column, and which row each timestamp is in
+        let mut ticker_columns: Vec<Vec<Vec<f64>>> = vec![];
+        let mut ticker_rows: Vec<std::collections::BTreeMap<polars::export::chrono::NaiveDateTime, usize>> = vec![];
+
+        for ticker in tickers {
+            let ticker_df = polars::prelude::IntoLazy::lazy(df.clone())
+                .filter(polars::prelude::col("symbol").eq(polars::prelude::lit(ticker.as_str())))
+                .collect()?;
+
+            if ticker_df.height() == 0 {
+                return Err(BarError::NoData(ticker.clone()));
+            }
+
+            ticker_rows.push(
+                ticker_df
+                    .column("timestamp")?
+                    .datetime()?
+                    .as_datetime_iter()
+                    .enumerate()
+                    .filter_map(|(row, timestamp)| timestamp.map(|timestamp| (timestamp, row)))
+                    .collect(),
+            );
+
+            ticker_columns.push(
+                features
+                    .iter()
+                    .map(|feature| {
+                        Ok(ticker_df
+                            .column(feature.as_str())?
+                            .cast(&polars::prelude::DataType::Float64)?
+                            .f64()?
+                            .into_iter()
+                            .map(|value| value.unwrap_or(f64::NAN))
+                            .collect())
+                    })
+                    .collect::<Result<Vec<Vec<f64>>, BarError>>()?,
+            );
+        }
+
+        let timestamps: Vec<polars::export::chrono::NaiveDateTime> = match ticker_rows.first() {
+            Some(first) => first
+                .keys()
+                .filter(|timestamp| ticker_rows.iter().all(|rows| rows.contains_key(timestamp)))
+                .copied()
+                .collect(),
+            None => vec![],
+        };
+
+        let mut values = Vec::with_capacity(timestamps.len() * tickers.len() * features.len());
+
+        for timestamp in timestamps.iter() {
+            for (rows, columns) in ticker_rows.iter().zip(ticker_columns.iter()) {
+                let row = rows[timestamp];
+                values.extend(columns.iter().map(|column| column[row]));
+            }
+        }
+
+        Ok(BarArray {
+            timestamps,
+            tickers: tickers.to_vec(),
+            features,
+            columns,
+            values,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    pub fn feature(&self, name: &str) -> Option<usize> {
+        self.features.iter().position(|feature| feature == name)
+    }
+
+    // first column of an indicator output whatever its parameters, e.g. sma finds sma_30
+    // coarser timeframes' copies come after the bars' own columns so those are found first
+    pub fn indicator(&self, output: &str) -> Option<usize> {
+        self.features.iter().position(|feature| is_indicator_column(feature, output))
+    }
+
+    // every ticker's features at one timestamp, ticker after ticker
+    pub fn row(&self, t: usize) -> &[f64] {
+        let width = self.tickers.len() * self.features.len();
+        &self.values[t * width..(t + 1) * width]
+    }
+
+    pub fn value(&self, t: usize, ticker: usize, feature: usize) -> f64 {
+        self.values[(t * self.tickers.len() + ticker) * self.features.len() + feature]
+    }
+
+    // first bar at or after timestamp
+    pub fn position(&self, timestamp: polars::export::chrono::NaiveDateTime) -> Option<usize> {
+        let t = self.timestamps.partition_point(|bar| *bar < timestamp);
+
+        match t < self.timestamps.len() {
+            true => Some(t),
+            false => None,
+        }
+    }
+}
+
+// indicator columns are named {output}_{parameters}, see Indicator::columns
+fn is_indicator_column(column: &str, output: &str) -> bool {
+    match column.strip_prefix(output).and_then(|rest| rest.strip_prefix('_')) {
+        Some(parameters) => parameters.starts_with(|c: char| c.is_ascii_digit()),
+        None => false,
+    }
+}
@@ -0,0 +1,237 @@
+extern crate rand;
+
+use crate::environment::stockenv::report::BacktestReport;
+use crate::environment::stockenv::StockEnv;
+use crate::environment::{Environment, Terminate};
+
+// bars between equal weight rebalances, a trading day of minute bars
+const DEFAULT_REBALANCE_BARS: usize = 390;
+
+// non learning policy that trades through the same env as the agent
+pub trait Benchmark {
+    fn name(&self) -> String;
+
+    // one size per ticker like the plain action, see full_action for the rest
+    fn sizes(&mut self, env: &StockEnv) -> Vec<f64>;
+}
+
+// sizes followed by zeros for the order blocks, so benchmarks only ever send market orders without protection
+fn full_action(env: &StockEnv, sizes: Vec<f64>) -> Vec<f64> {
+    let mut action = sizes;
+    action.resize(env.action_spec().shape as usize, 0f64);
+    action
+}
+
+// one episode over env's window
+pub fn run(env: &mut StockEnv, benchmark: &mut dyn Benchmark) -> BacktestReport {
+    let mut ts = env.reset();
+
+    while ts.as_any().downcast_ref::<Terminate>().is_none() {
+        let action = full_action(env, benchmark.sizes(env));
+        ts = env.step(action);
+    }
+
+    BacktestReport::from_env(benchmark.name().as_str(), env)
+}
+
+// every benchmark that works with env's tickers
+pub fn benchmarks(env: &StockEnv, seed: u64) -> Vec<Box<dyn Benchmark>> {
+    let mut benchmarks: Vec<Box<dyn Benchmark>> = vec![];
+
+    if let Some(buy_and_hold) = BuyAndHold::new(env, "SPY") {
+        benchmarks.push(Box::new(buy_and_hold));
+    }
+
+    benchmarks.push(Box::new(EqualWeight::new(None)));
+    benchmarks.push(Box::new(RandomPolicy::new(seed)));
+
+    if let Some(sma_crossover) = SmaCrossover::new(env) {
+        benchmarks.push(Box::new(sma_crossover));
+    }
+
+    benchmarks
+}
+
+// puts all the cash into one ticker on the first bar and never touches it again
+pub struct BuyAndHold {
+    pub ticker: String,
+    idx: usize,
+    bought: bool,
+}
+
+impl BuyAndHold {
+    pub fn new(env: &StockEnv, ticker: &str) -> Option<Self> {
+        Some(BuyAndHold {
+            ticker: String::from(ticker),
+            idx: env.tickers.iter().position(|t| t == ticker)?,
+            bought: false,
+        })
+    }
+}
+
+impl Benchmark for BuyAndHold {
+    fn name(&self) -> String {
+        format!("Buy and hold {}", self.ticker)
+    }
+
+    fn sizes(&mut self, env: &StockEnv) -> Vec<f64> {
+        let mut sizes = vec![0f64; env.tickers.len()];
+
+        // a fresh episode starts flat again
+        if env.holding(self.idx) == 0f64 {
+            self.bought = false;
+        }
+
+        if !self.bought {
+            sizes[self.idx] = 1f64;
+            self.bought = true;
+        }
+
+        sizes
+    }
+}
+
+// the same share of equity in every ticker, brought back to it every rebalance_bars
+pub struct EqualWeight {
+    pub rebalance_bars: usize,
+}
+
+impl EqualWeight {
+    pub fn new(rebalance_bars: Option<usize>) -> Self {
+        EqualWeight {
+            rebalance_bars: rebalance_bars.unwrap_or(DEFAULT_REBALANCE_BARS),
+        }
+    }
+}
+
+impl Benchmark for EqualWeight {
+    fn name(&self) -> String {
+        String::from("Equal weight")
+    }
+
+    fn sizes(&mut self, env: &StockEnv) -> Vec<f64> {
+        let tickers = env.tickers.len();
+        let mut sizes = vec![0f64; tickers];
+
+        // timeline has one entry per step plus the reset
+        if (env.timeline.len() - 1) % self.rebalance_bars != 0 {
+            return sizes;
+        }
+
+        let target = env.equity() / tickers as f64;
+        let values: Vec<f64> = (0..tickers).map(|idx| env.position_value(idx)).collect();
+
+        // sells go first and their proceeds are cash for the buys
+        let mut cash = env.free_cash();
+
+        for idx in 0..tickers {
+            if values[idx] > target {
+                sizes[idx] = -(values[idx] - target) / values[idx];
+                cash += values[idx] - target;
+            }
+        }
+
+        // buys run smallest action first and each takes its share of what is left, so hand out cash in that order
+        let mut needs: Vec<(usize, f64)> = (0..tickers)
+            .filter(|idx| values[*idx] < target)
+            .map(|idx| (idx, target - values[idx]))
+            .collect();
+        needs.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        for (idx, need) in needs {
+            if cash <= 0f64 {
+                break;
+            }
+
+            sizes[idx] = f64::min(need / cash, 1f64);
+            cash -= need;
+        }
+
+        sizes
+    }
+}
+
+// uniform sizes in [-1, 1], seeded so runs can be repeated
+pub struct RandomPolicy {
+    rng: rand::prelude::StdRng,
+}
+
+impl RandomPolicy {
+    pub fn new(seed: u64) -> Self {
+        RandomPolicy {
+            rng: <rand::prelude::StdRng as rand::prelude::SeedableRng>::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Benchmark for RandomPolicy {
+    fn name(&self) -> String {
+        String::from("Random")
+    }
+
+    fn sizes(&mut self, env: &StockEnv) -> Vec<f64> {
+        let uniform = rand::distributions::Uniform::from(-1f64..1f64);
+
+        (0..env.tickers.len())
+            .map(|_| rand::prelude::Distribution::sample(&uniform, &mut self.rng))
+            .collect()
+    }
+}
+
+// buys when the close crosses above the first sma column and sells everything when it crosses back below
+pub struct SmaCrossover {
+    sma: usize,
+    above: Vec<Option<bool>>,
+}
+
+impl SmaCrossover {
+    pub fn new(env: &StockEnv) -> Option<Self> {
+        Some(SmaCrossover {
+            sma: env.bars.indicator("sma")?,
+            above: vec![None; env.tickers.len()],
+        })
+    }
+}
+
+impl Benchmark for SmaCrossover {
+    fn name(&self) -> String {
+        String::from("SMA crossover")
+    }
+
+    fn sizes(&mut self, env: &StockEnv) -> Vec<f64> {
+        let tickers = env.tickers.len();
+        let mut sizes = vec![0f64; tickers];
+
+        if env.timeline.len() == 1 {
+            self.above = vec![None; tickers];
+        }
+
+        // cash is split evenly over everything not held, the j-th buy gets 1 / (unheld - j) of what is left
+        let unheld = (0..tickers).filter(|idx| env.holding(*idx) <= 0f64).count();
+        let mut buys = 0;
+
+        for idx in 0..tickers {
+            let sma = env.bar_value(idx, self.sma);
+
+            // the indicator needs a warm up and is zero until then
+            if sma <= 0f64 || sma.is_nan() {
+                continue;
+            }
+
+            let above = env.close_price(idx) > sma;
+
+            match (self.above[idx], above) {
+                (Some(false), true) if env.holding(idx) <= 0f64 => {
+                    sizes[idx] = 1f64 / (unheld - buys) as f64;
+                    buys += 1;
+                }
+                (Some(true), false) if env.holding(idx) > 0f64 => sizes[idx] = -1f64,
+                _ => {}
+            }
+
+            self.above[idx] = Some(above);
+        }
+
+        sizes
+    }
+}
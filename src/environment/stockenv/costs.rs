@@ -0,0 +1,169 @@
+extern crate anyhow;
+extern crate serde_json;
+
+// one side of a trade as the cost model sees it
+#[derive(Debug, Clone, Copy)]
+pub struct Fill {
+    pub shares: f64,
+    pub price: f64,
+    // volume of the bar the fill happens in
+    pub bar_volume: f64,
+    // atr of the bar, used by volatility scaled spreads
+    pub atr: f64,
+}
+
+// everything a fill costs on top of shares * price, always charged in cash
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TradeCost {
+    pub commission: f64,
+    pub spread: f64,
+    pub slippage: f64,
+}
+
+impl TradeCost {
+    pub fn total(&self) -> f64 {
+        self.commission + self.spread + self.slippage
+    }
+}
+
+impl std::ops::AddAssign for TradeCost {
+    fn add_assign(&mut self, other: Self) {
+        self.commission += other.commission;
+        self.spread += other.spread;
+        self.slippage += other.slippage;
+    }
+}
+
+pub trait CostModel: std::fmt::Debug {
+    fn cost(&self, fill: &Fill) -> TradeCost;
+}
+
+// trading is free, what StockEnv always did
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZeroCost;
+
+impl CostModel for ZeroCost {
+    fn cost(&self, _fill: &Fill) -> TradeCost {
+        TradeCost::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Commission {
+    None,
+    PerShare { rate: f64, minimum: f64 },
+    // fraction of the traded notional
+    Percent(f64),
+}
+
+// every fill crosses half of the spread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spread {
+    None,
+    // full spread in dollars per share
+    Fixed(f64),
+    // full spread as a multiple of the bar's atr
+    VolatilityScaled(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slippage {
+    None,
+    // square root market impact, price * coefficient * sqrt(shares / bar volume) per share
+    VolumeImpact(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StandardCostModel {
+    pub commission: Commission,
+    pub spread: Spread,
+    pub slippage: Slippage,
+}
+
+impl Default for StandardCostModel {
+    fn default() -> Self {
+        StandardCostModel {
+            commission: Commission::None,
+            spread: Spread::None,
+            slippage: Slippage::None,
+        }
+    }
+}
+
+impl CostModel for StandardCostModel {
+    fn cost(&self, fill: &Fill) -> TradeCost {
+        if fill.shares <= 0f64 {
+            return TradeCost::default();
+        }
+
+        let commission = match self.commission {
+            Commission::None => 0f64,
+            Commission::PerShare { rate, minimum } => f64::max(rate * fill.shares, minimum),
+            Commission::Percent(rate) => rate * fill.shares * fill.price,
+        };
+
+        let spread = match self.spread {
+            Spread::None => 0f64,
+            Spread::Fixed(spread) => 0.5 * spread * fill.shares,
+            Spread::VolatilityScaled(multiplier) => 0.5 * multiplier * fill.atr * fill.shares,
+        };
+
+        // no volume means no liquidity info, so there is nothing to scale the impact by
+        let slippage = match self.slippage {
+            Slippage::VolumeImpact(coefficient) if fill.bar_volume > 0f64 => {
+                coefficient * fill.price * (fill.shares / fill.bar_volume).sqrt() * fill.shares
+            }
+            _ => 0f64,
+        };
+
+        TradeCost {
+            commission,
+            spread,
+            slippage,
+        }
+    }
+}
+
+impl StandardCostModel {
+    // {"commission": {"per_share": 0.005, "minimum": 1.0} | {"percent": 0.0005},
+    //  "spread": {"fixed": 0.01} | {"volatility": 0.1}, "slippage": {"impact": 0.1}}
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let mut model = StandardCostModel::default();
+
+        let number = |value: &serde_json::Value, key: &str| -> anyhow::Result<f64> {
+            value
+                .get(key)
+                .and_then(|number| number.as_f64())
+                .ok_or_else(|| anyhow::anyhow!("Expected a number for {} in {}", key, value))
+        };
+
+        for (key, value) in value
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Cost model must be a json object"))?
+        {
+            match key.as_str() {
+                "commission" => {
+                    model.commission = match (value.get("per_share"), value.get("percent")) {
+                        (Some(_), None) => Commission::PerShare {
+                            rate: number(value, "per_share")?,
+                            minimum: value.get("minimum").and_then(|minimum| minimum.as_f64()).unwrap_or(0f64),
+                        },
+                        (None, Some(_)) => Commission::Percent(number(value, "percent")?),
+                        _ => anyhow::bail!("Commission needs either per_share or percent: {}", value),
+                    }
+                }
+                "spread" => {
+                    model.spread = match (value.get("fixed"), value.get("volatility")) {
+                        (Some(_), None) => Spread::Fixed(number(value, "fixed")?),
+                        (None, Some(_)) => Spread::VolatilityScaled(number(value, "volatility")?),
+                        _ => anyhow::bail!("Spread needs either fixed or volatility: {}", value),
+                    }
+                }
+                "slippage" => model.slippage = Slippage::VolumeImpact(number(value, "impact")?),
+                _ => anyhow::bail!("Unknown cost in cost model: {}", key),
+            }
+        }
+
+        Ok(model)
+    }
+}
@@ -0,0 +1,69 @@
+extern crate anyhow;
+extern crate serde_json;
+
+// seconds in a year, borrow rates are annual and accrue on calendar time like a broker charges them
+const SECONDS_PER_YEAR: f64 = 365f64 * 24f64 * 60f64 * 60f64;
+
+// turns on short selling, without it StockEnv stays long only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarginConfig {
+    // fraction of a short's value that has to be put up on top of the proceeds when opening it
+    pub initial_margin: f64,
+    // equity below this fraction of the short value gets every short bought back
+    pub maintenance_margin: f64,
+    // annual fee on the value of the borrowed shares
+    pub borrow_rate: f64,
+}
+
+// reg t style 50% initial and 30% maintenance with a general collateral borrow rate
+impl Default for MarginConfig {
+    fn default() -> Self {
+        MarginConfig {
+            initial_margin: 0.5,
+            maintenance_margin: 0.3,
+            borrow_rate: 0.03,
+        }
+    }
+}
+
+impl MarginConfig {
+    // {"initial_margin": 0.5, "maintenance_margin": 0.3, "borrow_rate": 0.03}
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let mut config = MarginConfig::default();
+
+        for (key, value) in value
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Margin config must be a json object"))?
+        {
+            let number = value
+                .as_f64()
+                .ok_or_else(|| anyhow::anyhow!("{} must be a number: {}", key, value))?;
+
+            match key.as_str() {
+                "initial_margin" => config.initial_margin = number,
+                "maintenance_margin" => config.maintenance_margin = number,
+                "borrow_rate" => config.borrow_rate = number,
+                _ => anyhow::bail!("Unknown key in margin config: {}", key),
+            }
+        }
+
+        if config.initial_margin <= 0f64 {
+            anyhow::bail!("initial_margin must be positive, got {}", config.initial_margin);
+        }
+
+        if config.maintenance_margin < 0f64 || config.maintenance_margin > config.initial_margin {
+            anyhow::bail!(
+                "maintenance_margin must be between 0 and initial_margin {}, got {}",
+                config.initial_margin,
+                config.maintenance_margin
+            );
+        }
+
+        Ok(config)
+    }
+
+    // fee for holding short_value worth of borrowed shares for elapsed
+    pub fn borrow_fee(&self, short_value: f64, elapsed: polars::export::chrono::Duration) -> f64 {
+        short_value.abs() * self.borrow_rate * elapsed.num_seconds() as f64 / SECONDS_PER_YEAR
+    }
+}
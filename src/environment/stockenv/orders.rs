@@ -0,0 +1,181 @@
+extern crate anyhow;
+extern crate serde_json;
+
+// turns on resting orders, the action gets a limit offset, stop distance and take profit per ticker on top of the size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderConfig {
+    // furthest a limit can sit from the close, as a fraction of it
+    pub max_limit_offset: f64,
+    // furthest a stop loss or take profit can sit from the entry, as a fraction of it
+    pub max_stop_distance: f64,
+    pub max_take_profit: f64,
+    // bars an unfilled limit order waits before it is cancelled
+    pub limit_bars: u32,
+}
+
+impl Default for OrderConfig {
+    fn default() -> Self {
+        OrderConfig {
+            max_limit_offset: 0.01,
+            max_stop_distance: 0.05,
+            max_take_profit: 0.1,
+            limit_bars: 30,
+        }
+    }
+}
+
+impl OrderConfig {
+    // {"max_limit_offset": 0.01, "max_stop_distance": 0.05, "max_take_profit": 0.1, "limit_bars": 30}
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let mut config = OrderConfig::default();
+
+        for (key, value) in value
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Order config must be a json object"))?
+        {
+            let number = value
+                .as_f64()
+                .filter(|number| *number > 0f64)
+                .ok_or_else(|| anyhow::anyhow!("{} must be a positive number: {}", key, value))?;
+
+            match key.as_str() {
+                "max_limit_offset" => config.max_limit_offset = number,
+                "max_stop_distance" => config.max_stop_distance = number,
+                "max_take_profit" => config.max_take_profit = number,
+                "limit_bars" => {
+                    config.limit_bars = value
+                        .as_u64()
+                        .filter(|bars| *bars > 0 && *bars <= u32::MAX as u64)
+                        .ok_or_else(|| anyhow::anyhow!("limit_bars must be a positive whole number: {}", value))?
+                        as u32
+                }
+                _ => anyhow::bail!("Unknown key in order config: {}", key),
+            }
+        }
+
+        Ok(config)
+    }
+
+    // the action is laid out as [sizes, limit offsets, stop distances, take profits] with one entry per ticker each
+    // sizes work like the plain action, the others only do something above zero and scale up to their max
+    pub fn intent(&self, action: &[f64], idx: usize, tickers: usize) -> OrderIntent {
+        let scaled = |block: usize, max: f64| match action[block * tickers + idx] {
+            value if value > 0f64 => Some(value * max),
+            _ => None,
+        };
+
+        OrderIntent {
+            size: action[idx],
+            limit_offset: scaled(1, self.max_limit_offset),
+            stop_distance: scaled(2, self.max_stop_distance),
+            take_profit: scaled(3, self.max_take_profit),
+        }
+    }
+}
+
+// what the agent asked for on one ticker, None means market order or no protection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderIntent {
+    pub size: f64,
+    pub limit_offset: Option<f64>,
+    pub stop_distance: Option<f64>,
+    pub take_profit: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderKind {
+    Limit,
+    StopLoss,
+    TakeProfit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Order {
+    pub ticker: usize,
+    pub kind: OrderKind,
+    // positive buys and negative sells like the action, protective orders always close the whole position
+    pub action: f64,
+    pub price: f64,
+    // bracket put around the position once a limit order fills
+    pub stop_distance: Option<f64>,
+    pub take_profit: Option<f64>,
+    // None is good till cancelled
+    pub bars_left: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+}
+
+impl Order {
+    // stop loss and take profit for a position, long when shares are positive
+    pub fn bracket(
+        ticker: usize,
+        shares: f64,
+        entry: f64,
+        stop_distance: Option<f64>,
+        take_profit: Option<f64>,
+    ) -> (Option<Order>, Option<Order>) {
+        let side = shares.signum();
+
+        let protective = |kind: OrderKind, price: f64| Order {
+            ticker,
+            kind,
+            action: -side,
+            price,
+            stop_distance: None,
+            take_profit: None,
+            bars_left: None,
+        };
+
+        (
+            stop_distance.map(|distance| protective(OrderKind::StopLoss, entry * (1f64 - side * distance))),
+            take_profit.map(|profit| protective(OrderKind::TakeProfit, entry * (1f64 + side * profit))),
+        )
+    }
+
+    // price the order fills at in this bar, a bar that gaps through the order price fills at the open
+    pub fn fill_price(&self, bar: &Bar) -> Option<f64> {
+        let buying = self.action > 0f64;
+
+        // limits and take profits want a better price, stops trigger on a worse one
+        let fills_below = match self.kind {
+            OrderKind::Limit | OrderKind::TakeProfit => buying,
+            OrderKind::StopLoss => !buying,
+        };
+
+        match fills_below {
+            true if bar.low <= self.price => Some(f64::min(bar.open, self.price)),
+            false if bar.high >= self.price => Some(f64::max(bar.open, self.price)),
+            _ => None,
+        }
+    }
+}
+
+// at most one order of every kind per ticker, a new one replaces what was resting
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBook {
+    pub limit: Vec<Option<Order>>,
+    pub stop_loss: Vec<Option<Order>>,
+    pub take_profit: Vec<Option<Order>>,
+}
+
+impl OrderBook {
+    pub fn new(tickers: usize) -> Self {
+        OrderBook {
+            limit: vec![None; tickers],
+            stop_loss: vec![None; tickers],
+            take_profit: vec![None; tickers],
+        }
+    }
+
+    pub fn resting(&self) -> usize {
+        [&self.limit, &self.stop_loss, &self.take_profit]
+            .iter()
+            .map(|orders| orders.iter().flatten().count())
+            .sum()
+    }
+}
@@ -0,0 +1,313 @@
+extern crate polars;
+extern crate serde_json;
+
+use crate::environment::stockenv::{calc_gain_to_pain, calc_lake_ratio, StockEnv};
+
+const DAYS_PER_YEAR: f64 = 365.25;
+
+// one color per curve in the html report, repeats after that
+const CURVE_COLORS: [&str; 6] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b"];
+
+const CHART_WIDTH: f64 = 900.0;
+const CHART_HEIGHT: f64 = 320.0;
+const CHART_MARGIN: f64 = 50.0;
+
+// how an episode went, built from the env once it is done
+#[derive(Debug, Clone)]
+pub struct BacktestReport {
+    pub label: String,
+    pub start: polars::export::chrono::NaiveDateTime,
+    pub end: polars::export::chrono::NaiveDateTime,
+    pub steps: usize,
+    pub initial_equity: f64,
+    pub final_equity: f64,
+
+    pub total_return: f64,
+    pub annualized_return: f64,
+    pub sharpe: f64,
+    pub sortino: f64,
+    pub calmar: f64,
+    // deepest fall from a peak as a fraction of it, and the longest time spent under a peak
+    pub max_drawdown: f64,
+    pub max_drawdown_duration: polars::export::chrono::Duration,
+    // share of closed trades that made money
+    pub win_rate: f64,
+    pub trades: usize,
+    // traded value over average equity
+    pub turnover: f64,
+    // average gross exposure
+    pub exposure: f64,
+    pub gain_to_pain: f64,
+    pub lake_ratio: f64,
+    // trading costs and borrow fees together
+    pub costs: f64,
+
+    pub equity_curve: Vec<(polars::export::chrono::NaiveDateTime, f64)>,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    match values.is_empty() {
+        true => 0f64,
+        false => values.iter().sum::<f64>() / values.len() as f64,
+    }
+}
+
+impl BacktestReport {
+    pub fn from_env(label: &str, env: &StockEnv) -> Self {
+        let equity = env.total_asset.clone();
+        let timeline = env.timeline.clone();
+
+        let start = *timeline.first().unwrap_or(&env.train_start);
+        let end = *timeline.last().unwrap_or(&env.train_end);
+        let initial_equity = *equity.first().unwrap_or(&env.initial_balance);
+        let final_equity = *equity.last().unwrap_or(&env.initial_balance);
+
+        let returns: Vec<f64> = equity.windows(2).map(|pair| pair[1] / pair[0] - 1f64).collect();
+
+        // bars aren't evenly spaced (nights, weekends), so the year is measured on the clock
+        let years = (end - start).num_seconds() as f64 / (DAYS_PER_YEAR * 24f64 * 60f64 * 60f64);
+        let periods_per_year = match years > 0f64 {
+            true => returns.len() as f64 / years,
+            false => 0f64,
+        };
+
+        let total_return = final_equity / initial_equity - 1f64;
+        let annualized_return = match years > 0f64 && final_equity > 0f64 {
+            true => (final_equity / initial_equity).powf(1f64 / years) - 1f64,
+            false => total_return,
+        };
+
+        let mean_return = mean(&returns);
+        let deviation = (returns.iter().map(|r| (r - mean_return).powi(2)).sum::<f64>()
+            / f64::max(returns.len() as f64 - 1f64, 1f64))
+        .sqrt();
+        let downside_deviation = mean(&returns.iter().map(|r| f64::min(*r, 0f64).powi(2)).collect::<Vec<f64>>()).sqrt();
+
+        let ratio = |numerator: f64, denominator: f64| match denominator > 0f64 {
+            true => numerator / denominator,
+            false => 0f64,
+        };
+
+        let mut peak = initial_equity;
+        let mut peak_time = start;
+        let mut max_drawdown = 0f64;
+        let mut max_drawdown_duration = polars::export::chrono::Duration::zero();
+
+        for (value, timestamp) in equity.iter().zip(timeline.iter()) {
+            if *value >= peak {
+                peak = *value;
+                peak_time = *timestamp;
+            } else {
+                max_drawdown = f64::max(max_drawdown, 1f64 - value / peak);
+                max_drawdown_duration = std::cmp::max(max_drawdown_duration, *timestamp - peak_time);
+            }
+        }
+
+        let wins = env.closed_trades.iter().filter(|pnl| **pnl > 0f64).count();
+
+        let series =
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("_", equity.clone());
+
+        BacktestReport {
+            label: String::from(label),
+            start,
+            end,
+            steps: returns.len(),
+            initial_equity,
+            final_equity,
+            total_return,
+            annualized_return,
+            sharpe: ratio(mean_return, deviation) * periods_per_year.sqrt(),
+            sortino: ratio(mean_return, downside_deviation) * periods_per_year.sqrt(),
+            calmar: ratio(annualized_return, max_drawdown),
+            max_drawdown,
+            max_drawdown_duration,
+            win_rate: ratio(wins as f64, env.closed_trades.len() as f64),
+            trades: env.closed_trades.len(),
+            turnover: ratio(env.traded_value.iter().sum(), mean(&equity)),
+            exposure: mean(&env.gross_exposures),
+            // both need at least two returns to mean anything
+            gain_to_pain: match returns.len() > 1 {
+                true => calc_gain_to_pain(series.clone()),
+                false => 0f64,
+            },
+            lake_ratio: match returns.len() > 1 {
+                true => calc_lake_ratio(series),
+                false => 0f64,
+            },
+            costs: env.cost_history.iter().map(|cost| cost.total()).sum::<f64>() + env.borrow_fees.iter().sum::<f64>(),
+            equity_curve: timeline.into_iter().zip(equity).collect(),
+        }
+    }
+
+    // name and value of every metric, in the order the reports show them
+    pub fn metrics(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("total_return", self.total_return),
+            ("annualized_return", self.annualized_return),
+            ("sharpe", self.sharpe),
+            ("sortino", self.sortino),
+            ("calmar", self.calmar),
+            ("max_drawdown", self.max_drawdown),
+            (
+                "max_drawdown_days",
+                self.max_drawdown_duration.num_seconds() as f64 / (24f64 * 60f64 * 60f64),
+            ),
+            ("win_rate", self.win_rate),
+            ("trades", self.trades as f64),
+            ("turnover", self.turnover),
+            ("exposure", self.exposure),
+            ("gain_to_pain", self.gain_to_pain),
+            ("lake_ratio", self.lake_ratio),
+            ("costs", self.costs),
+            ("initial_equity", self.initial_equity),
+            ("final_equity", self.final_equity),
+        ]
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "label": self.label,
+            "start": self.start.to_string(),
+            "end": self.end.to_string(),
+            "steps": self.steps,
+            "equity_curve": self
+                .equity_curve
+                .iter()
+                .map(|(timestamp, value)| serde_json::json!([timestamp.to_string(), value]))
+                .collect::<Vec<serde_json::Value>>(),
+        });
+
+        for (name, value) in self.metrics() {
+            json[name] = serde_json::json!(value);
+        }
+
+        json
+    }
+
+    // writes {path}.json and {path}.html
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        save_reports(path, self.label.as_str(), &[self.clone()])
+    }
+}
+
+pub fn save_reports(path: &str, title: &str, reports: &[BacktestReport]) -> std::io::Result<()> {
+    let json = match reports {
+        [report] => report.to_json(),
+        _ => serde_json::Value::Array(reports.iter().map(|report| report.to_json()).collect()),
+    };
+
+    std::fs::write(
+        format!("{}.json", path),
+        serde_json::to_string_pretty(&json).expect("Failed to convert report to string"),
+    )?;
+    std::fs::write(format!("{}.html", path), html(title, reports))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// a page with nothing to load, the equity curves are drawn as inline svg
+pub fn html(title: &str, reports: &[BacktestReport]) -> String {
+    let points: Vec<(polars::export::chrono::NaiveDateTime, f64)> =
+        reports.iter().flat_map(|report| report.equity_curve.clone()).collect();
+
+    let (first, last) = match (
+        points.iter().map(|(timestamp, _)| *timestamp).min(),
+        points.iter().map(|(timestamp, _)| *timestamp).max(),
+    ) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return format!("<!DOCTYPE html><html><body><h1>{}</h1><p>No data</p></body></html>", escape(title)),
+    };
+
+    let low = points.iter().map(|(_, value)| *value).fold(f64::INFINITY, f64::min);
+    let high = points.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+
+    let span = f64::max((last - first).num_seconds() as f64, 1f64);
+    let range = f64::max(high - low, 1e-9);
+
+    let x = |timestamp: polars::export::chrono::NaiveDateTime| {
+        CHART_MARGIN + (timestamp - first).num_seconds() as f64 / span * (CHART_WIDTH - 2f64 * CHART_MARGIN)
+    };
+    let y = |value: f64| CHART_HEIGHT - CHART_MARGIN - (value - low) / range * (CHART_HEIGHT - 2f64 * CHART_MARGIN);
+
+    let mut svg = format!(
+        "<svg width=\"{w}\" height=\"{h}\" xmlns=\"http://www.w3.org/2000/svg\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#ffffff\" stroke=\"#cccccc\"/>\n\
+         <text x=\"{m}\" y=\"{top}\" font-size=\"12\">{high:.2}</text>\n\
+         <text x=\"{m}\" y=\"{bottom}\" font-size=\"12\">{low:.2}</text>\n\
+         <text x=\"{m}\" y=\"{h_text}\" font-size=\"12\">{first}</text>\n\
+         <text x=\"{right}\" y=\"{h_text}\" font-size=\"12\" text-anchor=\"end\">{last}</text>\n",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT,
+        m = CHART_MARGIN,
+        top = CHART_MARGIN - 5f64,
+        bottom = CHART_HEIGHT - CHART_MARGIN + 15f64,
+        h_text = CHART_HEIGHT - 10f64,
+        right = CHART_WIDTH - CHART_MARGIN,
+        high = high,
+        low = low,
+        first = first,
+        last = last,
+    );
+
+    for (idx, report) in reports.iter().enumerate() {
+        let polyline = report
+            .equity_curve
+            .iter()
+            .map(|(timestamp, value)| format!("{:.1},{:.1}", x(*timestamp), y(*value)))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        svg += format!(
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+            CURVE_COLORS[idx % CURVE_COLORS.len()],
+            polyline
+        )
+        .as_str();
+    }
+
+    svg += "</svg>\n";
+
+    let mut table = String::from("<table>\n<tr><th>metric</th>");
+
+    for (idx, report) in reports.iter().enumerate() {
+        table += format!(
+            "<th style=\"color: {}\">{}</th>",
+            CURVE_COLORS[idx % CURVE_COLORS.len()],
+            escape(report.label.as_str())
+        )
+        .as_str();
+    }
+
+    table += "</tr>\n";
+
+    if let Some(report) = reports.first() {
+        for (row, (name, _)) in report.metrics().iter().enumerate() {
+            table += format!("<tr><td>{}</td>", name).as_str();
+
+            for report in reports {
+                table += format!("<td>{:.4}</td>", report.metrics()[row].1).as_str();
+            }
+
+            table += "</tr>\n";
+        }
+    }
+
+    table += "</table>\n";
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body {{ font-family: sans-serif; margin: 2em; }} \
+         table {{ border-collapse: collapse; margin-top: 1em; }} \
+         td, th {{ border: 1px solid #cccccc; padding: 4px 10px; text-align: right; }} \
+         td:first-child {{ text-align: left; }}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n<p>{first} - {last}</p>\n{svg}{table}</body>\n</html>\n",
+        title = escape(title),
+        first = first,
+        last = last,
+        svg = svg,
+        table = table,
+    )
+}
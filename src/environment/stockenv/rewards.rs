@@ -0,0 +1,341 @@
+extern crate anyhow;
+extern crate polars;
+extern crate serde_json;
+
+use crate::environment::stockenv::{calc_gain_to_pain, calc_lake_ratio};
+
+// one term of the reward, gets the whole equity curve of the episode so far after every step
+pub trait RewardFunction: std::fmt::Debug {
+    fn name(&self) -> String;
+
+    fn reward(&mut self, equity: &[f64]) -> f64;
+
+    // forget running statistics at the start of an episode
+    fn reset(&mut self) {}
+
+    fn box_clone(&self) -> Box<dyn RewardFunction>;
+}
+
+// return of the last step, nothing to compute before there are two points
+fn last_return(equity: &[f64]) -> Option<f64> {
+    match equity {
+        [.., previous, current] if *previous > 0f64 => Some(current / previous - 1f64),
+        _ => None,
+    }
+}
+
+// what StockEnv always rewarded: change in total asset plus gain to pain and minus lake ratio once there are 30 points
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LegacyReward;
+
+impl RewardFunction for LegacyReward {
+    fn name(&self) -> String {
+        String::from("legacy")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        let change = match equity {
+            [.., previous, current] => current - previous,
+            _ => 0f64,
+        };
+
+        if equity.len() > 29 {
+            let total_asset =
+                <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("_", equity.to_vec());
+
+            change + (100f64 * calc_gain_to_pain(total_asset.tail(Some(30))))
+                - (500f64 * calc_lake_ratio(total_asset))
+        } else {
+            change
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogReturn;
+
+impl RewardFunction for LogReturn {
+    fn name(&self) -> String {
+        String::from("log_return")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        match last_return(equity) {
+            Some(r) if r > -1f64 => (1f64 + r).ln(),
+            _ => 0f64,
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+// moody and saffell's differential sharpe ratio, how much the last return moved an exponential moving sharpe
+#[derive(Debug, Clone, Copy)]
+pub struct DifferentialSharpe {
+    // adaptation rate of the moving first and second moment
+    pub eta: f64,
+    mean: f64,
+    second_moment: f64,
+}
+
+impl DifferentialSharpe {
+    pub fn new(eta: Option<f64>) -> Self {
+        DifferentialSharpe {
+            eta: eta.unwrap_or(0.01),
+            mean: 0f64,
+            second_moment: 0f64,
+        }
+    }
+}
+
+impl RewardFunction for DifferentialSharpe {
+    fn name(&self) -> String {
+        String::from("differential_sharpe")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        let r = match last_return(equity) {
+            Some(r) => r,
+            None => return 0f64,
+        };
+
+        let delta_mean = r - self.mean;
+        let delta_second_moment = r.powi(2) - self.second_moment;
+        let variance = self.second_moment - self.mean.powi(2);
+
+        // no spread of returns yet, so there is no sharpe to move
+        let reward = match variance > 0f64 {
+            true => (self.second_moment * delta_mean - 0.5 * self.mean * delta_second_moment) / variance.powf(1.5),
+            false => 0f64,
+        };
+
+        self.mean += self.eta * delta_mean;
+        self.second_moment += self.eta * delta_second_moment;
+
+        reward
+    }
+
+    fn reset(&mut self) {
+        self.mean = 0f64;
+        self.second_moment = 0f64;
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+// sortino style, squared shortfall of the last return under target and nothing for returns above it
+#[derive(Debug, Clone, Copy)]
+pub struct DownsidePenalty {
+    pub target: f64,
+}
+
+impl RewardFunction for DownsidePenalty {
+    fn name(&self) -> String {
+        String::from("downside")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        match last_return(equity) {
+            Some(r) => -f64::min(r - self.target, 0f64).powi(2),
+            None => 0f64,
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+// how far under its peak the equity is, as a fraction of the peak
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawdownPenalty;
+
+impl RewardFunction for DrawdownPenalty {
+    fn name(&self) -> String {
+        String::from("drawdown")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        let peak = equity.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        match (equity.last(), peak > 0f64) {
+            (Some(current), true) => -(1f64 - current / peak),
+            _ => 0f64,
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+// expected loss in the worst alpha of the last window returns, only once the tail has a full return in it
+#[derive(Debug, Clone, Copy)]
+pub struct CvarPenalty {
+    pub alpha: f64,
+    pub window: usize,
+}
+
+impl RewardFunction for CvarPenalty {
+    fn name(&self) -> String {
+        String::from("cvar")
+    }
+
+    fn reward(&mut self, equity: &[f64]) -> f64 {
+        let start = equity.len().saturating_sub(self.window + 1);
+        let mut returns: Vec<f64> = equity[start..]
+            .windows(2)
+            .filter_map(last_return)
+            .collect();
+
+        let tail = (self.alpha * returns.len() as f64).floor() as usize;
+
+        if tail == 0 {
+            return 0f64;
+        }
+
+        returns.sort_by(f64::total_cmp);
+
+        // mean of the worst returns, losses are negative so this is already a penalty
+        f64::min(returns[..tail].iter().sum::<f64>() / tail as f64, 0f64)
+    }
+
+    fn box_clone(&self) -> Box<dyn RewardFunction> {
+        Box::new(*self)
+    }
+}
+
+// weighted sum of reward terms, the value of every term from the last step is kept in info
+#[derive(Debug)]
+pub struct WeightedReward {
+    pub terms: Vec<(f64, Box<dyn RewardFunction>)>,
+    pub info: Vec<(String, f64)>,
+}
+
+impl Clone for WeightedReward {
+    fn clone(&self) -> Self {
+        WeightedReward {
+            terms: self
+                .terms
+                .iter()
+                .map(|(weight, term)| (*weight, term.box_clone()))
+                .collect(),
+            info: self.info.clone(),
+        }
+    }
+}
+
+impl Default for WeightedReward {
+    fn default() -> Self {
+        WeightedReward::new(vec![(1f64, Box::new(LegacyReward))])
+    }
+}
+
+impl WeightedReward {
+    pub fn new(terms: Vec<(f64, Box<dyn RewardFunction>)>) -> Self {
+        WeightedReward { terms, info: vec![] }
+    }
+
+    // [{"type": "log_return", "weight": 1.0}, {"type": "differential_sharpe", "eta": 0.01},
+    //  {"type": "downside", "target": 0.0}, {"type": "drawdown", "weight": 0.1},
+    //  {"type": "cvar", "alpha": 0.05, "window": 390}, {"type": "legacy"}]
+    // weight defaults to 1, penalties are already negative so they want positive weights too
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let mut terms: Vec<(f64, Box<dyn RewardFunction>)> = vec![];
+
+        for term in value
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("Reward must be a list of terms: {}", value))?
+        {
+            let object = term
+                .as_object()
+                .ok_or_else(|| anyhow::anyhow!("Reward term must be a json object: {}", term))?;
+
+            let number = |key: &str| -> anyhow::Result<Option<f64>> {
+                match object.get(key) {
+                    None => Ok(None),
+                    Some(number) => Ok(Some(
+                        number
+                            .as_f64()
+                            .ok_or_else(|| anyhow::anyhow!("{} must be a number: {}", key, term))?,
+                    )),
+                }
+            };
+
+            let kind = object
+                .get("type")
+                .and_then(|kind| kind.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Reward term needs a type: {}", term))?;
+
+            let allowed: &[&str] = match kind {
+                "differential_sharpe" => &["eta"],
+                "downside" => &["target"],
+                "cvar" => &["alpha", "window"],
+                _ => &[],
+            };
+
+            for key in object.keys() {
+                if key != "type" && key != "weight" && !allowed.contains(&key.as_str()) {
+                    anyhow::bail!("Unknown key {} for {} reward: {}", key, kind, term);
+                }
+            }
+
+            let function: Box<dyn RewardFunction> = match kind {
+                "legacy" => Box::new(LegacyReward),
+                "log_return" => Box::new(LogReturn),
+                "differential_sharpe" => Box::new(DifferentialSharpe::new(number("eta")?)),
+                "downside" => Box::new(DownsidePenalty {
+                    target: number("target")?.unwrap_or(0f64),
+                }),
+                "drawdown" => Box::new(DrawdownPenalty),
+                "cvar" => {
+                    let alpha = number("alpha")?.unwrap_or(0.05);
+
+                    if alpha <= 0f64 || alpha >= 1f64 {
+                        anyhow::bail!("cvar alpha must be between 0 and 1: {}", term);
+                    }
+
+                    Box::new(CvarPenalty {
+                        alpha,
+                        window: number("window")?.unwrap_or(390f64) as usize,
+                    })
+                }
+                _ => anyhow::bail!("Unknown reward type: {}", kind),
+            };
+
+            terms.push((number("weight")?.unwrap_or(1f64), function));
+        }
+
+        if terms.is_empty() {
+            anyhow::bail!("Reward needs at least one term");
+        }
+
+        Ok(WeightedReward::new(terms))
+    }
+
+    pub fn reward(&mut self, equity: &[f64]) -> f64 {
+        self.info = self
+            .terms
+            .iter_mut()
+            .map(|(weight, term)| (term.name(), *weight * term.reward(equity)))
+            .collect();
+
+        self.info.iter().map(|(_, value)| value).sum()
+    }
+
+    pub fn reset(&mut self) {
+        self.info = vec![];
+
+        for (_, term) in self.terms.iter_mut() {
+            term.reset();
+        }
+    }
+}
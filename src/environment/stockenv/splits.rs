@@ -0,0 +1,177 @@
+extern crate anyhow;
+extern crate serde_json;
+
+// share of the date range that goes to training and validation when no split dates are given, test gets the rest
+const DEFAULT_TRAIN_FRACTION: f64 = 0.7;
+const DEFAULT_VALIDATION_FRACTION: f64 = 0.15;
+
+// inclusive range of bars an env steps through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateWindow {
+    pub start: polars::export::chrono::NaiveDateTime,
+    pub end: polars::export::chrono::NaiveDateTime,
+}
+
+impl std::fmt::Display for DateWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} - {}", self.start, self.end)
+    }
+}
+
+// train on train_days, evaluate during training on the validation_days after them, test on the test_days
+// after those, then move everything step_days forward
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WalkForward {
+    pub train_days: i64,
+    pub validation_days: i64,
+    pub test_days: i64,
+    pub step_days: i64,
+    // start every fold from a fresh policy instead of training the last one further
+    pub retrain: bool,
+}
+
+impl WalkForward {
+    // {"train_days": 20, "validation_days": 5, "test_days": 5, "step_days": 5, "retrain": true}
+    // validation_days and step_days default to test_days
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let days = |key: &str| -> anyhow::Result<Option<i64>> {
+            match value.get(key) {
+                None => Ok(None),
+                Some(days) => match days.as_i64() {
+                    Some(days) if days > 0 => Ok(Some(days)),
+                    _ => anyhow::bail!("{} must be a positive number of days: {}", key, days),
+                },
+            }
+        };
+
+        for key in value
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Walk forward config must be a json object"))?
+            .keys()
+        {
+            if !["train_days", "validation_days", "test_days", "step_days", "retrain"].contains(&key.as_str()) {
+                anyhow::bail!("Unknown key in walk forward config: {}", key);
+            }
+        }
+
+        let train_days = days("train_days")?.ok_or_else(|| anyhow::anyhow!("Walk forward needs train_days"))?;
+        let test_days = days("test_days")?.ok_or_else(|| anyhow::anyhow!("Walk forward needs test_days"))?;
+
+        Ok(WalkForward {
+            train_days,
+            validation_days: days("validation_days")?.unwrap_or(test_days),
+            test_days,
+            step_days: days("step_days")?.unwrap_or(test_days),
+            retrain: match value.get("retrain") {
+                None => true,
+                Some(retrain) => retrain
+                    .as_bool()
+                    .ok_or_else(|| anyhow::anyhow!("retrain must be true or false: {}", retrain))?,
+            },
+        })
+    }
+
+    // (train, validation, test) windows that fit between start and end, at least one has to
+    pub fn folds(
+        &self,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> anyhow::Result<Vec<(DateWindow, DateWindow, DateWindow)>> {
+        let mut folds = vec![];
+        let mut fold_start = start;
+
+        loop {
+            let validation_start = fold_start + polars::export::chrono::Duration::days(self.train_days);
+            let test_start = validation_start + polars::export::chrono::Duration::days(self.validation_days);
+            let test_end = test_start + polars::export::chrono::Duration::days(self.test_days);
+
+            if test_end > end {
+                break;
+            }
+
+            // windows are inclusive, so stop a millisecond short of where the next one starts
+            let before = |datetime: polars::export::chrono::NaiveDateTime| {
+                datetime - polars::export::chrono::Duration::milliseconds(1)
+            };
+
+            folds.push((
+                DateWindow {
+                    start: fold_start,
+                    end: before(validation_start),
+                },
+                DateWindow {
+                    start: validation_start,
+                    end: before(test_start),
+                },
+                DateWindow {
+                    start: test_start,
+                    end: before(test_end),
+                },
+            ));
+
+            fold_start += polars::export::chrono::Duration::days(self.step_days);
+        }
+
+        if folds.is_empty() {
+            anyhow::bail!(
+                "Walk forward needs at least {} days between {} and {}",
+                self.train_days + self.validation_days + self.test_days,
+                start,
+                end
+            );
+        }
+
+        Ok(folds)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Splits {
+    pub validation_start: Option<polars::export::chrono::NaiveDateTime>,
+    pub test_start: Option<polars::export::chrono::NaiveDateTime>,
+    pub walk_forward: Option<WalkForward>,
+}
+
+impl Splits {
+    // train, validation and test windows of start..end, split dates that aren't given fall back to 70/15/15
+    pub fn windows(
+        &self,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> anyhow::Result<(DateWindow, DateWindow, DateWindow)> {
+        let at = |fraction: f64| {
+            start + polars::export::chrono::Duration::milliseconds(((end - start).num_milliseconds() as f64 * fraction) as i64)
+        };
+
+        let validation_start = self.validation_start.unwrap_or_else(|| at(DEFAULT_TRAIN_FRACTION));
+        let test_start = self
+            .test_start
+            .unwrap_or_else(|| at(DEFAULT_TRAIN_FRACTION + DEFAULT_VALIDATION_FRACTION));
+
+        if !(start < validation_start && validation_start < test_start && test_start < end) {
+            anyhow::bail!(
+                "Splits must be in order: start {} < validation_start {} < test_start {} < end {}",
+                start,
+                validation_start,
+                test_start,
+                end
+            );
+        }
+
+        let before = |datetime: polars::export::chrono::NaiveDateTime| {
+            datetime - polars::export::chrono::Duration::milliseconds(1)
+        };
+
+        Ok((
+            DateWindow {
+                start,
+                end: before(validation_start),
+            },
+            DateWindow {
+                start: validation_start,
+                end: before(test_start),
+            },
+            DateWindow { start: test_start, end },
+        ))
+    }
+}
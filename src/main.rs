@@ -3,6 +3,7 @@
 
 mod environment;
 mod optimizer;
+mod recording;
 mod replay_buffer;
 mod stockframe;
 mod td3;
@@ -15,11 +16,15 @@ use crate::environment::halfcheetahenv::HalfCheetahEnv;
 use crate::environment::antenv::AntEnv;
 use crate::environment::hopperenv::HopperEnv;
 
-use crate::environment::stockenv::StockEnv;
+use crate::environment::stockenv::benchmarks;
+use crate::environment::stockenv::report::{save_reports, BacktestReport};
+use crate::environment::stockenv::splits::WalkForward;
+use crate::environment::stockenv::{StockEnv, StockEnvConfig};
+use crate::recording::Recording;
 use crate::replay_buffer::ReplayBuffer;
 
-use crate::td3::TD3;
-use crate::viewer::Viewer;
+use crate::td3::{ArchitectureConfig, NetworkSpec, TD3};
+use crate::viewer::{CheckpointWatcher, Viewer};
 
 lazy_static::lazy_static! {
     static ref device: std::sync::Arc<tch::Device> = std::sync::Arc::new(tch::Device::cuda_if_available());
@@ -50,11 +55,50 @@ enum Commands {
         eval_freq: Option<u32>,
         #[arg(long)]
         save_policy: Option<bool>,
+        #[arg(long)]
+        actor_arch: Option<String>,
+        #[arg(long)]
+        q1_arch: Option<String>,
+        #[arg(long)]
+        q2_arch: Option<String>,
+        #[arg(long)]
+        arch_config: Option<String>,
+        #[arg(long)]
+        stock_config: Option<String>,
     },
 
     Run {
+        #[arg(long)]
+        savefile: Option<String>,
+        #[arg(long)]
+        watch: Option<String>,
+        #[arg(long)]
+        models_dir: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        obs_indices: Option<Vec<usize>>,
+        #[arg(long)]
+        compare: Vec<String>,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    Record {
         #[arg(long)]
         savefile: String,
+        #[arg(long)]
+        output: String,
+    },
+
+    Replay {
+        #[arg(long)]
+        recording: String,
+    },
+
+    Backtest {
+        #[arg(long)]
+        savefile: String,
+        #[arg(long)]
+        stock_config: Option<String>,
     },
 }
 
@@ -86,6 +130,8 @@ fn run_td3(
     save_policy: bool,
     actor_opt: &str,
     critic_opt: &str,
+    architecture: ArchitectureConfig,
+    stock_config: StockEnvConfig,
 ) {
     if !std::path::Path::new("./results").exists() {
         std::fs::create_dir_all("./results").expect("Failed to create results directory");
@@ -95,6 +141,9 @@ fn run_td3(
         std::fs::create_dir_all("./models").expect("Failed to create models directory");
     }
 
+    // only stocks have data the policy never saw during training
+    let mut test_env: Option<StockEnv> = None;
+
     let envs: (Box<dyn Environment>, Box<dyn Environment>) = match env {
         "halfcheetah" => {
             let train_env = Box::new(HalfCheetahEnv::new(
@@ -133,16 +182,44 @@ fn run_td3(
         }
 
         "stockenv" => {
-            let end = polars::export::chrono::Utc::now()
-                .date_naive()
-                .and_hms_micro_opt(0, 0, 0, 0)
-                .unwrap();
-            let start = end - polars::export::chrono::Duration::days(15);
+            let stock_env = StockEnv::new(stock_config.clone())
+                .unwrap_or_else(|err| panic!("Failed to load stock data: {}", err));
+
+            if let Some(walk_forward) = stock_config.splits.walk_forward {
+                return run_walk_forward(
+                    stock_env,
+                    &stock_config,
+                    walk_forward,
+                    filename,
+                    expl_noise,
+                    max_timesteps,
+                    start_timesteps,
+                    eval_freq,
+                    save_policy,
+                    actor_opt,
+                    critic_opt,
+                    architecture,
+                );
+            }
 
-            let train_env = Box::new(StockEnv::new(start, end));
-            let eval_env = train_env.clone();
+            let (train, validation, test) = stock_config
+                .splits
+                .windows(stock_config.start, stock_config.end)
+                .unwrap_or_else(|err| panic!("Invalid stock env splits: {}", err));
 
-            (train_env, eval_env)
+            let window = |window| {
+                stock_env
+                    .window(window)
+                    .unwrap_or_else(|err| panic!("Failed to make stock env window: {}", err))
+            };
+
+            println!("Train: {} Validation: {} Test: {}", train, validation, test);
+            test_env = Some(window(test));
+
+            (
+                Box::new(window(train)) as Box<dyn Environment>,
+                Box::new(window(validation)) as Box<dyn Environment>,
+            )
         }
 
         &_ => {
@@ -153,29 +230,236 @@ fn run_td3(
     let mut train_env = envs.0;
     let mut eval_env = envs.1;
 
-    let state_dim = train_env.observation_spec().shape;
-    let action_dim = train_env.action_spec().shape;
-    let max_action = train_env.action_spec().max;
+    let mut policy = new_td3(train_env.as_ref(), actor_opt, critic_opt, architecture);
+    let mut replaybuffer = ReplayBuffer::new(
+        train_env.observation_spec().shape as i64,
+        train_env.action_spec().shape as i64,
+        None,
+    );
+
+    train_td3(
+        &mut policy,
+        &mut replaybuffer,
+        &mut train_env,
+        &mut eval_env,
+        filename,
+        expl_noise,
+        max_timesteps,
+        start_timesteps,
+        eval_freq,
+        save_policy,
+    );
+
+    if let Some(test_env) = test_env {
+        let (test_reward, _) = backtest(&policy, &test_env, filename);
+        println!("Test Reward: {:.3}", test_reward);
+    }
+}
 
-    let mut policy = TD3::new(
-        state_dim as i64,
-        action_dim as i64,
-        max_action,
+fn new_td3(env: &dyn Environment, actor_opt: &str, critic_opt: &str, architecture: ArchitectureConfig) -> TD3 {
+    TD3::new(
+        env.observation_spec().shape as i64,
+        env.action_spec().shape as i64,
+        env.action_spec().max,
         actor_opt,
         critic_opt,
-        None,
-        None,
-        None,
+        architecture.actor,
+        architecture.q1,
+        architecture.q2,
+        architecture.encoder,
         None,
         None,
         None,
         None,
         None,
     )
-    .expect("Failed to create TD3 Policy");
+    .expect("Failed to create TD3 Policy")
+}
+
+fn write_results(filename: &str, results: &serde_json::Value) {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(format!("./results/{}.banan", filename))
+        .unwrap_or_else(|_| panic!("Failed to open file ./results/{}.banan", filename));
+
+    std::io::Write::write_all(
+        &mut file,
+        serde_json::to_string_pretty(results)
+            .expect("Failed to convert results to string")
+            .as_bytes(),
+    )
+    .expect("Failed to write result");
+}
+
+// one episode over the whole window, returns the summed reward and the backtest report
+fn eval_stock(policy: &TD3, env: &mut StockEnv, label: &str) -> (f64, BacktestReport) {
+    let mut ts = env.reset();
+    let mut reward = 0f64;
+
+    while ts.as_any().downcast_ref::<Terminate>().is_none() {
+        let action = policy.select_action(ts.observation());
+        ts = env.step(action);
+
+        reward += ts.reward().unwrap_or(0f64);
+    }
+
+    (reward, BacktestReport::from_env(label, env))
+}
+
+// runs the policy and every benchmark over env's window, prints how the policy did against each
+// and saves all of them to one report, the policy's report comes first
+fn backtest(policy: &TD3, env: &StockEnv, name: &str) -> (f64, Vec<BacktestReport>) {
+    let (reward, report) = eval_stock(policy, &mut env.clone(), name);
+    let mut reports = vec![report];
+
+    for mut benchmark in benchmarks::benchmarks(env, 0) {
+        reports.push(benchmarks::run(&mut env.clone(), benchmark.as_mut()));
+    }
+
+    println!(
+        "{:<24} {:>10} {:>10} {:>8} {:>10}",
+        "", "Return", "vs Policy", "Sharpe", "Drawdown"
+    );
+
+    for report in reports.iter() {
+        println!(
+            "{:<24} {:>9.2}% {:>9.2}% {:>8.3} {:>9.2}%",
+            report.label,
+            100f64 * report.total_return,
+            100f64 * (reports[0].total_return - report.total_return),
+            report.sharpe,
+            100f64 * report.max_drawdown
+        );
+    }
+
+    save_reports(format!("./results/{}_backtest", name).as_str(), name, &reports)
+        .expect("Failed to write backtest report");
+
+    (reward, reports)
+}
+
+// trains on every fold's train window, evals during training on the validation window after it
+// and tests on the days after that, the test window stays unseen until the fold is done
+fn run_walk_forward(
+    stock_env: StockEnv,
+    stock_config: &StockEnvConfig,
+    walk_forward: WalkForward,
+    filename: &str,
+    expl_noise: f64,
+    max_timesteps: u32,
+    start_timesteps: u32,
+    eval_freq: u32,
+    save_policy: bool,
+    actor_opt: &str,
+    critic_opt: &str,
+    architecture: ArchitectureConfig,
+) {
+    let window = |window| {
+        stock_env
+            .window(window)
+            .unwrap_or_else(|err| panic!("Failed to make stock env window: {}", err))
+    };
+
+    let mut policy = new_td3(&stock_env, actor_opt, critic_opt, architecture.clone());
+    let mut replaybuffer = ReplayBuffer::new(
+        stock_env.observation_spec().shape as i64,
+        stock_env.action_spec().shape as i64,
+        None,
+    );
+    let mut folds = vec![];
+
+    for (fold, (train, validation, test)) in walk_forward
+        .folds(stock_config.start, stock_config.end)
+        .unwrap_or_else(|err| panic!("Failed to make walk forward folds: {}", err))
+        .into_iter()
+        .enumerate()
+    {
+        println!(
+            "Fold: {} Train: {} Validation: {} Test: {}",
+            fold + 1,
+            train,
+            validation,
+            test
+        );
+        let fold_name = format!("{}_fold_{}", filename, fold + 1);
+
+        if walk_forward.retrain && fold > 0 {
+            policy = new_td3(&stock_env, actor_opt, critic_opt, architecture.clone());
+            replaybuffer = ReplayBuffer::new(
+                stock_env.observation_spec().shape as i64,
+                stock_env.action_spec().shape as i64,
+                None,
+            );
+        }
+
+        let mut train_env: Box<dyn Environment> = Box::new(window(train));
+        let mut eval_env: Box<dyn Environment> = Box::new(window(validation));
+
+        // a policy carried over from the last fold is already trained, so skip the random warm up
+        let start_timesteps = match walk_forward.retrain || fold == 0 {
+            true => start_timesteps,
+            false => 0,
+        };
+
+        train_td3(
+            &mut policy,
+            &mut replaybuffer,
+            &mut train_env,
+            &mut eval_env,
+            fold_name.as_str(),
+            expl_noise,
+            max_timesteps,
+            start_timesteps,
+            eval_freq,
+            save_policy,
+        );
+
+        let (test_reward, reports) = backtest(&policy, &window(test), fold_name.as_str());
+        let report = &reports[0];
+        println!("Fold: {} Test Reward: {:.3}", fold + 1, test_reward);
+
+        folds.push(serde_json::json!({
+            "fold": fold + 1,
+            "train_start": train.start.to_string(),
+            "train_end": train.end.to_string(),
+            "validation_start": validation.start.to_string(),
+            "validation_end": validation.end.to_string(),
+            "test_start": test.start.to_string(),
+            "test_end": test.end.to_string(),
+            "reward": test_reward,
+            "return": report.total_return,
+            "sharpe": report.sharpe,
+            "max_drawdown": report.max_drawdown,
+            "benchmarks": reports[1..]
+                .iter()
+                .map(|benchmark| serde_json::json!({"label": benchmark.label, "return": benchmark.total_return, "sharpe": benchmark.sharpe}))
+                .collect::<Vec<serde_json::Value>>(),
+        }));
+
+        write_results(
+            format!("{}_walk_forward", filename).as_str(),
+            &serde_json::Value::Array(folds.clone()),
+        );
+    }
+}
+
+fn train_td3(
+    policy: &mut TD3,
+    replaybuffer: &mut ReplayBuffer,
+    train_env: &mut Box<dyn Environment>,
+    eval_env: &mut Box<dyn Environment>,
+    filename: &str,
+    expl_noise: f64,
+    max_timesteps: u32,
+    start_timesteps: u32,
+    eval_freq: u32,
+    save_policy: bool,
+) {
+    let max_action = train_env.action_spec().max;
 
-    let mut replaybuffer = ReplayBuffer::new(state_dim as i64, action_dim as i64, None);
-    let mut evals = vec![eval_td3(&policy, &mut eval_env, None)];
+    let mut evals = vec![eval_td3(policy, eval_env, None)];
 
     let mut ts = train_env.reset();
     let mut episode_reward = 0f64;
@@ -228,7 +512,7 @@ fn run_td3(
         ts = next_ts;
 
         if t >= start_timesteps {
-            policy.train(&replaybuffer, None);
+            policy.train(replaybuffer, None);
         }
 
         if done {
@@ -247,7 +531,7 @@ fn run_td3(
         }
 
         if (t + 1) % eval_freq == 0 {
-            evals.push(eval_td3(&policy, &mut eval_env, None));
+            evals.push(eval_td3(policy, eval_env, None));
             let mut file = std::fs::OpenOptions::new()
                 .write(true)
                 .create(true)
@@ -273,7 +557,7 @@ fn run_td3(
 
                 std::io::Write::write_all(
                     &mut file,
-                    serde_json::to_string_pretty(&policy)
+                    serde_json::to_string_pretty(&*policy)
                         .expect("Failed to serialize td3 to json")
                         .as_bytes(),
                 )
@@ -290,6 +574,35 @@ fn load_td3(filename: String) -> TD3 {
         .unwrap_or_else(|_| panic!("Failed to parse td3 from file: {}", filename.clone()))
 }
 
+// checkpoint file name without directory and extension, used to tell compared policies apart
+fn policy_label(savefile: &str) -> String {
+    std::path::Path::new(savefile)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(savefile)
+        .to_string()
+}
+
+fn make_mujoco_env(env: &str) -> Box<dyn Mujoco> {
+    match env {
+        "halfcheetah" => Box::new(HalfCheetahEnv::new(
+            None, None, None, None, None, None, None,
+        )),
+
+        "ant" => Box::new(AntEnv::new(
+            None, None, None, None, None, None, None, None, None, None, None, None, None,
+        )),
+
+        "hopper" => Box::new(HopperEnv::new(
+            None, None, None, None, None, None, None, None, None, None, None, None,
+        )),
+
+        &_ => {
+            panic!("Selected Environment is not renderable")
+        }
+    }
+}
+
 fn main() {
     println!("Cuda Enabled: {}", device.is_cuda());
 
@@ -304,6 +617,11 @@ fn main() {
             start_timesteps,
             eval_freq,
             save_policy,
+            actor_arch,
+            q1_arch,
+            q2_arch,
+            arch_config,
+            stock_config,
         } => {
             let expl_noise = expl_noise.unwrap_or(0.1);
             let max_timesteps = max_timesteps.unwrap_or(100000);
@@ -311,6 +629,36 @@ fn main() {
             let eval_freq = eval_freq.unwrap_or(5000);
             let save_policy = save_policy.unwrap_or(false);
 
+            // architecture flags override whatever is in the config file
+            let mut architecture = match arch_config {
+                None => ArchitectureConfig::default(),
+                Some(arch_config) => ArchitectureConfig::from_file(arch_config.as_str())
+                    .unwrap_or_else(|err| panic!("Failed to load architecture config {}: {}", arch_config, err)),
+            };
+
+            let stock_config = match stock_config {
+                None => StockEnvConfig::default(),
+                Some(stock_config) => StockEnvConfig::from_file(stock_config.as_str())
+                    .unwrap_or_else(|err| panic!("Failed to load stock env config {}: {}", stock_config, err)),
+            };
+
+            let parse_arch = |arch: String| -> NetworkSpec {
+                arch.parse()
+                    .unwrap_or_else(|err| panic!("Invalid architecture {}: {}", arch, err))
+            };
+
+            if let Some(actor_arch) = actor_arch {
+                architecture.actor = Some(parse_arch(actor_arch));
+            }
+
+            if let Some(q1_arch) = q1_arch {
+                architecture.q1 = Some(parse_arch(q1_arch));
+            }
+
+            if let Some(q2_arch) = q2_arch {
+                architecture.q2 = Some(parse_arch(q2_arch));
+            }
+
             let filename = format!(
                 "td3_{}_{}_{}",
                 args.env,
@@ -328,32 +676,130 @@ fn main() {
                 save_policy,
                 actor_opt.as_str(),
                 critic_opt.as_str(),
+                architecture,
+                stock_config,
             );
         }
 
-        Commands::Run { savefile } => {
-            let td3 = load_td3(savefile);
+        Commands::Run {
+            savefile,
+            watch,
+            models_dir,
+            obs_indices,
+            compare,
+            seed,
+        } => {
+            let label = match (&savefile, &watch) {
+                (Some(savefile), _) => policy_label(savefile.as_str()),
+                (None, Some(watch)) => watch.clone(),
+                (None, None) => String::from("Policy"),
+            };
 
-            let env: Box<dyn Mujoco> = match args.env.as_str() {
-                "halfcheetah" => Box::new(HalfCheetahEnv::new(
-                    None, None, None, None, None, None, None,
-                )),
+            let models_dir = models_dir.unwrap_or(String::from("./models"));
+            let mut watcher = watch.map(|name| CheckpointWatcher::new(models_dir.as_str(), name.as_str()));
 
-                "ant" => Box::new(AntEnv::new(
-                    None, None, None, None, None, None, None, None, None, None, None, None, None,
-                )),
+            let td3 = match (savefile, &mut watcher) {
+                (Some(savefile), _) => load_td3(savefile),
 
-                "hopper" => Box::new(HopperEnv::new(
-                    None, None, None, None, None, None, None, None, None, None, None, None,
-                )),
+                (None, Some(watcher)) => loop {
+                    if let Some((step, td3)) = watcher.poll() {
+                        println!("Loaded checkpoint: {} steps", step);
+                        break td3;
+                    }
 
-                &_ => {
-                    panic!("Selected Environment is not renderable")
-                }
+                    println!("Waiting for a checkpoint in {}", models_dir);
+                    std::thread::sleep(std::time::Duration::from_secs(2));
+                },
+
+                (None, None) => panic!("Run needs either --savefile or --watch"),
             };
 
+            let env = make_mujoco_env(args.env.as_str());
+
             let mut viewer = Viewer::new(env, td3, None, None);
+            viewer.set_label(label.as_str());
+
+            for savefile in compare {
+                let label = policy_label(savefile.as_str());
+                viewer.compare(make_mujoco_env(args.env.as_str()), load_td3(savefile), label.as_str());
+            }
+
+            if let Some(obs_indices) = obs_indices {
+                viewer.set_observation_indices(obs_indices);
+            }
+
+            if let Some(seed) = seed {
+                viewer.set_seed(seed);
+            }
+
+            if let Some(watcher) = watcher {
+                viewer.watch(watcher);
+            }
+
+            viewer.render();
+        }
+
+        Commands::Record { savefile, output } => {
+            let td3 = load_td3(savefile);
+            let mut env = make_mujoco_env(args.env.as_str());
+
+            let mut ts = env.reset();
+            let mut recording = Recording::new(env.as_mut());
+
+            while ts.as_any().downcast_ref::<Terminate>().is_none() {
+                let action = td3.select_action(ts.observation());
+                ts = env.step(action.clone());
+                recording.push(env.as_mut(), action, ts.reward().unwrap_or(0f64));
+            }
+
+            println!(
+                "Recorded {} steps with Return: {:.3}",
+                recording.len(),
+                recording.rewards.iter().sum::<f64>()
+            );
+
+            recording
+                .save(output.as_str())
+                .unwrap_or_else(|err| panic!("Failed to save recording {}: {}", output, err));
+        }
+
+        Commands::Replay { recording } => {
+            let mut env = make_mujoco_env(args.env.as_str());
+
+            let recording = Recording::from_file(recording.as_str())
+                .and_then(|loaded| loaded.check(env.as_mut()).map(|_| loaded))
+                .unwrap_or_else(|err| panic!("Failed to load recording {}: {}", recording, err));
+
+            let mut viewer = Viewer::replay(env, recording, None, None);
             viewer.render();
         }
+
+        // the policy against the benchmarks on the test window of the stock config
+        Commands::Backtest {
+            savefile,
+            stock_config,
+        } => {
+            let stock_config = match stock_config {
+                None => StockEnvConfig::default(),
+                Some(stock_config) => StockEnvConfig::from_file(stock_config.as_str())
+                    .unwrap_or_else(|err| panic!("Failed to load stock env config {}: {}", stock_config, err)),
+            };
+
+            let (_, _, test) = stock_config
+                .splits
+                .windows(stock_config.start, stock_config.end)
+                .unwrap_or_else(|err| panic!("Invalid stock env splits: {}", err));
+
+            let test_env = StockEnv::new(stock_config)
+                .and_then(|env| env.window(test))
+                .unwrap_or_else(|err| panic!("Failed to load stock data: {}", err));
+
+            if !std::path::Path::new("./results").exists() {
+                std::fs::create_dir_all("./results").expect("Failed to create results directory");
+            }
+
+            let td3 = load_td3(savefile.clone());
+            backtest(&td3, &test_env, policy_label(savefile.as_str()).as_str());
+        }
     }
 }
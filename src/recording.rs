@@ -0,0 +1,216 @@
+extern crate anyhow;
+extern crate serde;
+extern crate serde_json;
+
+use crate::environment::Mujoco;
+
+// one recorded episode, the state after every step plus the actions and rewards that led there
+// a recording with only actions is replayed by stepping the env again from the initial state
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    pub initial_qpos: Vec<f64>,
+    pub initial_qvel: Vec<f64>,
+    pub time: Vec<f64>,
+    pub qpos: Vec<Vec<f64>>,
+    pub qvel: Vec<Vec<f64>>,
+    pub actions: Vec<Vec<f64>>,
+    pub rewards: Vec<f64>,
+}
+
+impl Recording {
+    // starts a recording from whatever state the env is in, call it right after reset
+    pub fn new(env: &mut dyn Mujoco) -> Self {
+        let (initial_qpos, initial_qvel) = read_state(env);
+
+        Recording {
+            initial_qpos,
+            initial_qvel,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, env: &mut dyn Mujoco, action: Vec<f64>, reward: f64) {
+        let (qpos, qvel) = read_state(env);
+
+        self.time.push(env.data().time);
+        self.qpos.push(qpos);
+        self.qvel.push(qvel);
+        self.actions.push(action);
+        self.rewards.push(reward);
+    }
+
+    pub fn len(&self) -> usize {
+        usize::max(self.qpos.len(), self.actions.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // states are replayed as is, otherwise the actions have to be stepped through again
+    pub fn has_states(&self) -> bool {
+        !self.qpos.is_empty()
+    }
+
+    // the reward the env handed out for the step that produced frame
+    pub fn reward(&self, frame: usize) -> Option<f64> {
+        self.rewards.get(frame).copied()
+    }
+
+    // makes sure the recording was made with a model of the same size as env
+    pub fn check(&self, env: &mut dyn Mujoco) -> anyhow::Result<()> {
+        let nq = env.model().nq as usize;
+        let nv = env.model().nv as usize;
+        let nu = env.action_spec().shape as usize;
+
+        if self.initial_qpos.len() != nq || self.initial_qvel.len() != nv {
+            anyhow::bail!(
+                "Recording starts from a state of size {}/{} but the env has nq={} nv={}",
+                self.initial_qpos.len(),
+                self.initial_qvel.len(),
+                nq,
+                nv
+            );
+        }
+
+        if self.qpos.iter().any(|qpos| qpos.len() != nq) || self.qvel.iter().any(|qvel| qvel.len() != nv) {
+            anyhow::bail!("Recording contains states that don't match nq={} nv={}", nq, nv);
+        }
+
+        if self.actions.iter().any(|action| action.len() != nu) {
+            anyhow::bail!("Recording contains actions that don't match the action size {}", nu);
+        }
+
+        self.check_lengths()
+    }
+
+    // every step has one entry in each of the lists that are there, len() relies on that when replaying
+    fn check_lengths(&self) -> anyhow::Result<()> {
+        if self.qpos.len() != self.qvel.len() {
+            anyhow::bail!(
+                "Recording has {} qpos frames but {} qvel frames",
+                self.qpos.len(),
+                self.qvel.len()
+            );
+        }
+
+        // the viewer plays states back at their recorded time, without it every frame lands at once
+        if self.has_states() && self.time.len() != self.qpos.len() {
+            anyhow::bail!(
+                "Recording has {} qpos frames but {} time entries",
+                self.qpos.len(),
+                self.time.len()
+            );
+        }
+
+        let frames = self.len();
+
+        for (name, len) in [
+            ("time", self.time.len()),
+            ("qpos", self.qpos.len()),
+            ("actions", self.actions.len()),
+            ("rewards", self.rewards.len()),
+        ] {
+            if len != 0 && len != frames {
+                anyhow::bail!("Recording has {} {} entries but {} frames", len, name, frames);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn restore_initial(&self, env: &mut dyn Mujoco) {
+        write_state(env, &self.initial_qpos, &self.initial_qvel, 0f64);
+    }
+
+    // puts env in the state recorded after step frame
+    pub fn restore(&self, env: &mut dyn Mujoco, frame: usize) {
+        write_state(env, &self.qpos[frame], &self.qvel[frame], self.time[frame]);
+    }
+
+    pub fn save(&self, filename: &str) -> anyhow::Result<()> {
+        let json = serde_json::json!({
+            "initial_qpos": self.initial_qpos,
+            "initial_qvel": self.initial_qvel,
+            "time": self.time,
+            "qpos": self.qpos,
+            "qvel": self.qvel,
+            "actions": self.actions,
+            "rewards": self.rewards,
+        });
+
+        std::fs::write(filename, serde_json::to_string(&json)?)?;
+
+        Ok(())
+    }
+
+    // everything apart from the initial state is optional, so a file can hold just states or just actions
+    // states need their time though
+    pub fn from_file(filename: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(filename)?;
+        let json: serde_json::Value = serde_json::from_str(data.as_str())?;
+
+        let json = json
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Recording must be a json object"))?;
+
+        fn parse<T: serde::de::DeserializeOwned + Default>(
+            json: &serde_json::Map<String, serde_json::Value>,
+            name: &str,
+        ) -> anyhow::Result<T> {
+            match json.get(name) {
+                None => Ok(T::default()),
+                Some(value) => serde_json::from_value(value.clone())
+                    .map_err(|err| anyhow::anyhow!("Invalid {} in recording: {}", name, err)),
+            }
+        }
+
+        let recording = Recording {
+            initial_qpos: parse(json, "initial_qpos")?,
+            initial_qvel: parse(json, "initial_qvel")?,
+            time: parse(json, "time")?,
+            qpos: parse(json, "qpos")?,
+            qvel: parse(json, "qvel")?,
+            actions: parse(json, "actions")?,
+            rewards: parse(json, "rewards")?,
+        };
+
+        recording.check_lengths()?;
+
+        if recording.is_empty() {
+            anyhow::bail!("Recording {} has neither states nor actions", filename);
+        }
+
+        Ok(recording)
+    }
+}
+
+pub fn read_state(env: &mut dyn Mujoco) -> (Vec<f64>, Vec<f64>) {
+    let nq = env.model().nq as usize;
+    let nv = env.model().nv as usize;
+    let data = env.data();
+
+    unsafe {
+        (
+            core::slice::from_raw_parts(data.qpos as *const f64, nq).to_vec(),
+            core::slice::from_raw_parts(data.qvel as *const f64, nv).to_vec(),
+        )
+    }
+}
+
+pub fn write_state(env: &mut dyn Mujoco, qpos: &[f64], qvel: &[f64], time: f64) {
+    let nq = env.model().nq as usize;
+    let nv = env.model().nv as usize;
+
+    assert_eq!(qpos.len(), nq);
+    assert_eq!(qvel.len(), nv);
+
+    unsafe {
+        let data = env.data();
+        std::ptr::copy_nonoverlapping(qpos.as_ptr(), data.qpos, nq);
+        std::ptr::copy_nonoverlapping(qvel.as_ptr(), data.qvel, nv);
+        data.time = time;
+
+        crate::wrappers::mujoco::mj_forward(env.model(), env.data());
+    }
+}
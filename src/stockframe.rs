@@ -2,170 +2,298 @@ extern crate anyhow;
 extern crate curl;
 extern crate serde_json;
 
+pub mod alpacaprovider;
+pub mod cachedprovider;
+pub mod calendar;
+pub mod indicators;
+pub mod localprovider;
+pub mod syntheticprovider;
+pub mod timeframe;
+
+use crate::stockframe::alpacaprovider::AlpacaProvider;
+use crate::stockframe::cachedprovider::CachedProvider;
+use crate::stockframe::calendar::NyseCalendar;
+use crate::stockframe::indicators::Indicator;
+use crate::stockframe::localprovider::LocalProvider;
+use crate::stockframe::timeframe::Timeframe;
+
 // Helper class that constructs Dataframe for me
-// in order to use it you must have alpaca api keys set as env variables
+// bars come from a BarProvider, the default one is alpaca which needs api keys set as env variables
 // ALPACA_KEY={your api key}
 // ALPACA_SECRET={your secret key}
-
-#[derive(Clone)]
-pub struct StockFrame {
-    pub columns: Vec<String>,
-    pub tickers: Vec<String>,
-    pub frame: std::cell::RefCell<polars::prelude::DataFrame>,
+// alpaca bars are cached in ./cache/bars, set MILKSHAKE_OFFLINE=1 to only use what is cached there
+
+const BAR_CACHE_DIR: &str = "./cache/bars";
+
+// every frame starts with these, in this order
+const BAR_COLUMNS: [&str; 9] = [
+    "symbol",
+    "timestamp",
+    "open",
+    "high",
+    "low",
+    "close",
+    "volume",
+    "vwap",
+    "trade_count",
+];
+
+#[derive(Debug)]
+pub enum BarError {
+    MissingCredentials(String),
+    Http(curl::Error),
+    InvalidResponse(String),
+    MissingColumn(String),
+    InvalidData(String),
+    Indicator(String),
+    NoData(String),
+    // the cache is missing these (inclusive) days and fetching is turned off
+    Offline {
+        ticker: String,
+        missing: Vec<(polars::export::chrono::NaiveDate, polars::export::chrono::NaiveDate)>,
+    },
+    Io(std::io::Error),
+    Polars(polars::prelude::PolarsError),
 }
 
-impl StockFrame {
-    fn grab_entire_json(
-        ticker: &String,
-        uri: &String,
-        page_token: Option<String>,
-    ) -> anyhow::Result<Vec<serde_json::Value>> {
-        let alpaca_key: String = std::env::var("ALPACA_KEY")?;
-        let alpaca_secret: String = std::env::var("ALPACA_SECRET")?;
+impl std::fmt::Display for BarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BarError::MissingCredentials(var) => write!(f, "Missing credentials, {} is not set", var),
+            BarError::Http(err) => write!(f, "Request failed: {}", err),
+            BarError::InvalidResponse(response) => write!(f, "Invalid API Response: {}", response),
+            BarError::MissingColumn(column) => write!(f, "Missing column: {}", column),
+            BarError::InvalidData(reason) => write!(f, "Invalid bar data: {}", reason),
+            BarError::Indicator(reason) => write!(f, "Indicator failed: {}", reason),
+            BarError::NoData(ticker) => write!(f, "No bars for ticker: {}", ticker),
+            BarError::Offline { ticker, missing } => write!(
+                f,
+                "Offline and the cache has no bars for {} on {}",
+                ticker,
+                missing
+                    .iter()
+                    .map(|(first, last)| match first == last {
+                        true => format!("{}", first),
+                        false => format!("{} to {}", first, last),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            BarError::Io(err) => write!(f, "{}", err),
+            BarError::Polars(err) => write!(f, "{}", err),
+        }
+    }
+}
 
-        let mut easy = curl::easy::Easy::new();
+impl std::error::Error for BarError {}
 
-        let mut data = Vec::new();
-        let mut headers = curl::easy::List::new();
+impl From<curl::Error> for BarError {
+    fn from(err: curl::Error) -> Self {
+        BarError::Http(err)
+    }
+}
 
-        headers.append(format!("APCA-API-KEY-ID: {}", alpaca_key).as_str())?;
-        headers.append(format!("APCA-API-SECRET-KEY: {}", alpaca_secret).as_str())?;
+impl From<std::io::Error> for BarError {
+    fn from(err: std::io::Error) -> Self {
+        BarError::Io(err)
+    }
+}
 
-        match page_token {
-            None => easy.url(uri.as_str()),
-            Some(token) => easy.url(format!("{}&page_token={}", uri, token).as_str()),
-        }?;
+impl From<polars::prelude::PolarsError> for BarError {
+    fn from(err: polars::prelude::PolarsError) -> Self {
+        BarError::Polars(err)
+    }
+}
 
-        easy.http_headers(headers)?;
+// anything that hands out bars for one ticker, normalized to alpaca's column names with a parsed timestamp
+pub trait BarProvider {
+    fn bars(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> Result<polars::prelude::DataFrame, BarError>;
+}
 
-        {
-            let mut transfer = easy.transfer();
-            transfer.write_function(|new_data| {
-                data.extend_from_slice(new_data);
-                Ok(new_data.len())
-            })?;
+// maps the column names of a local OHLCV file onto the names alpaca uses
+// vwap and trade_count are optional, a file without a symbol column is treated as a single ticker named after the file
+#[derive(Debug, Clone)]
+pub struct BarSchema {
+    pub symbol: String,
+    pub timestamp: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub vwap: String,
+    pub trade_count: String,
+
+    // chrono format for string timestamps, None means rfc3339 like alpaca returns
+    pub timestamp_format: Option<String>,
+}
 
-            transfer.perform()?;
+impl Default for BarSchema {
+    fn default() -> Self {
+        BarSchema {
+            symbol: String::from("symbol"),
+            timestamp: String::from("timestamp"),
+            open: String::from("open"),
+            high: String::from("high"),
+            low: String::from("low"),
+            close: String::from("close"),
+            volume: String::from("volume"),
+            vwap: String::from("vwap"),
+            trade_count: String::from("trade_count"),
+            timestamp_format: None,
         }
+    }
+}
 
-        let json_string = String::from_utf8(data)?;
-        let json_object: serde_json::Value = serde_json::from_str(json_string.as_str())?;
-
-        match json_object.get("next_page_token") {
-            None => anyhow::bail!(format!("Invalid API Response: {}", json_string)),
-
-            Some(next_page_token) => {
-                let mut bars = match json_object.get("bars") {
-                    None => anyhow::bail!(format!("Invalid API Response: {}", json_string)),
-                    Some(bars) => match bars.as_array() {
-                        None => {
-                            anyhow::bail!(format!("Failed to cast bars to array: {}", json_string))
-                        }
-                        Some(checked_bars) => {
-                            Ok::<Vec<serde_json::Value>, anyhow::Error>(checked_bars.clone())
-                        }
-                    },
-                }?;
-
-                match next_page_token.as_str() {
-                    None => Ok(bars),
+impl BarSchema {
+    // {"timestamp": "Date", "close": "Adj Close", "timestamp_format": "%Y-%m-%d %H:%M:%S"}, missing keys keep the defaults
+    pub fn from_file(filename: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(filename)?;
+        let json: serde_json::Value = serde_json::from_str(data.as_str())?;
 
-                    Some(page_token) => {
-                        bars.append(&mut StockFrame::grab_entire_json(
-                            ticker,
-                            uri,
-                            Some(page_token.parse()?),
-                        )?);
+        let mut schema = BarSchema::default();
 
-                        Ok(bars)
-                    }
-                }
+        for (key, value) in json
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Bar schema must be a json object"))?
+        {
+            let value = value
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Bar schema value for {} must be a string", key))?
+                .to_string();
+
+            match key.as_str() {
+                "symbol" => schema.symbol = value,
+                "timestamp" => schema.timestamp = value,
+                "open" => schema.open = value,
+                "high" => schema.high = value,
+                "low" => schema.low = value,
+                "close" => schema.close = value,
+                "volume" => schema.volume = value,
+                "vwap" => schema.vwap = value,
+                "trade_count" => schema.trade_count = value,
+                "timestamp_format" => schema.timestamp_format = Some(value),
+                _ => anyhow::bail!("Unknown column in bar schema: {}", key),
             }
         }
+
+        Ok(schema)
     }
 
-    fn grab_latest_data(
-        start: polars::export::chrono::NaiveDateTime,
-        end: polars::export::chrono::NaiveDateTime,
-        tickers: &Vec<String>,
-    ) -> polars::prelude::DataFrame {
-        let mut df = polars::prelude::DataFrame::default();
-
-        for ticker in tickers {
-            let grab_ticker_data = || -> Result<std::io::Cursor<String>, anyhow::Error> {
-                let uri = format!(
-                    "https://data.alpaca.markets/v2/stocks/{}/bars?{}{}{}",
-                    ticker,
-                    format!(
-                        "start={}&",
-                        start
-                            .and_utc()
-                            .to_rfc3339_opts(polars::export::chrono::SecondsFormat::Secs, true)
-                    ),
-                    format!(
-                        "end={}&",
-                        end.and_utc()
-                            .to_rfc3339_opts(polars::export::chrono::SecondsFormat::Secs, true)
-                    ),
-                    "timeframe=1Min"
-                );
-                let entire_json = StockFrame::grab_entire_json(ticker, &uri, None)?;
-                let json_tree = serde_json::to_string(&entire_json)?;
-                Ok(std::io::Cursor::new(json_tree))
-            };
+    // renames and casts a raw file into symbol, timestamp, open, high, low, close, volume, vwap, trade_count
+    pub fn normalize(
+        &self,
+        df: polars::prelude::DataFrame,
+        fallback_symbol: &str,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        let names: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .map(|name| String::from(*name))
+            .collect();
 
-            let cursor = match grab_ticker_data() {
-                Ok(cursor) => cursor,
-                Err(err) => {
-                    println!("Failed to grab data for ticker: {}, Error: {}", ticker, err);
-                    continue;
-                }
-            };
+        let required = |source: &String, target: &str| -> Result<polars::prelude::Expr, BarError> {
+            match names.contains(source) {
+                true => Ok(polars::prelude::col(source.as_str()).alias(target)),
+                false => Err(BarError::MissingColumn(format!("{} (mapped to {})", source, target))),
+            }
+        };
 
-            let mut tmp_df = <polars::prelude::DataFrame as polars::prelude::IntoLazy>::lazy(
-                <polars::prelude::JsonReader<std::io::Cursor<String>> as polars::prelude::SerReader<
-                    std::io::Cursor<String>,
-                >>::finish(
-                    <polars::prelude::JsonReader<std::io::Cursor<String>> as polars::prelude::SerReader<
-                        std::io::Cursor<String>,
-                    >>::new(cursor),
-                )
-                .unwrap(),
-            )
-            .with_columns([polars::prelude::lit(ticker.as_str()).alias("symbol")])
-            .collect()
-            .unwrap();
+        let optional = |source: &String, target: &str| -> polars::prelude::Expr {
+            match names.contains(source) {
+                true => polars::prelude::col(source.as_str()).alias(target),
+                false => polars::prelude::lit(polars::prelude::NULL).alias(target),
+            }
+        };
 
-            tmp_df
-                .set_column_names(
-                    vec![
-                        "close",
-                        "high",
-                        "low",
-                        "trade_count",
-                        "open",
-                        "timestamp",
-                        "volume",
-                        "vwap",
-                        "symbol",
-                    ]
-                    .as_slice(),
-                )
-                .expect("Collumn number mismatch");
-            df = df.vstack(&tmp_df).unwrap();
+        let symbol = match names.contains(&self.symbol) {
+            true => polars::prelude::col(self.symbol.as_str()).cast(polars::prelude::DataType::String),
+            false => polars::prelude::lit(fallback_symbol),
+        };
 
-            // prevent rate limiting
-            std::thread::sleep(core::time::Duration::from_secs(4));
-        }
+        let timestamp_dtype = df
+            .column(self.timestamp.as_str())
+            .map_err(|_| BarError::MissingColumn(self.timestamp.clone()))?
+            .dtype()
+            .clone();
+
+        let timestamp = match timestamp_dtype {
+            polars::prelude::DataType::String => polars::prelude::col(self.timestamp.as_str())
+                .str()
+                .strptime(
+                    polars::prelude::DataType::Datetime(polars::prelude::TimeUnit::Milliseconds, None),
+                    polars::prelude::StrptimeOptions {
+                        format: Some(self.timestamp_format.clone().unwrap_or(String::from("%+")).into()),
+                        strict: true,
+                        exact: self.timestamp_format.is_some(),
+                        cache: true,
+                    },
+                    polars::prelude::lit("raise"),
+                ),
+            polars::prelude::DataType::Datetime(_, _) | polars::prelude::DataType::Date => {
+                polars::prelude::col(self.timestamp.as_str()).cast(polars::prelude::DataType::Datetime(
+                    polars::prelude::TimeUnit::Milliseconds,
+                    None,
+                ))
+            }
+            dtype => {
+                return Err(BarError::InvalidData(format!(
+                    "Unsupported timestamp column type: {}",
+                    dtype
+                )))
+            }
+        };
+
+        let normalized = <polars::prelude::DataFrame as polars::prelude::IntoLazy>::lazy(df)
+            .select([
+                symbol.alias("symbol"),
+                timestamp.alias("timestamp"),
+                required(&self.open, "open")?,
+                required(&self.high, "high")?,
+                required(&self.low, "low")?,
+                required(&self.close, "close")?,
+                required(&self.volume, "volume")?,
+                optional(&self.vwap, "vwap"),
+                optional(&self.trade_count, "trade_count"),
+            ])
+            .with_columns([
+                polars::prelude::col("open").cast(polars::prelude::DataType::Float64),
+                polars::prelude::col("high").cast(polars::prelude::DataType::Float64),
+                polars::prelude::col("low").cast(polars::prelude::DataType::Float64),
+                polars::prelude::col("close").cast(polars::prelude::DataType::Float64),
+                polars::prelude::col("volume").cast(polars::prelude::DataType::Int64),
+                polars::prelude::col("vwap").cast(polars::prelude::DataType::Float64),
+                polars::prelude::col("trade_count").cast(polars::prelude::DataType::Int64),
+            ])
+            .collect()?;
 
-        df
+        Ok(normalized)
     }
+}
+
+#[derive(Clone)]
+pub struct StockFrame {
+    pub columns: Vec<String>,
+    pub tickers: Vec<String>,
+    pub frame: std::cell::RefCell<polars::prelude::DataFrame>,
+    // size of every bar, local files are taken to be minute bars unless told otherwise
+    pub timeframe: Timeframe,
+}
 
+impl StockFrame {
     pub fn new(
         mut tickers: Option<Vec<String>>,
         mut start: Option<polars::export::chrono::NaiveDateTime>,
         mut end: Option<polars::export::chrono::NaiveDateTime>,
-    ) -> Self {
+        timeframe: Option<Timeframe>,
+    ) -> Result<Self, BarError> {
+        let timeframe = timeframe.unwrap_or_default();
+
         if tickers.is_none() {
             tickers = Some(["AAPL", "TSLA"].iter().map(|s| String::from(*s)).collect());
         }
@@ -182,52 +310,95 @@ impl StockFrame {
 
         assert!(tickers.is_some());
 
-        let columns_list: Vec<String> = vec![
-            "symbol",
-            "timestamp",
-            "open",
-            "high",
-            "low",
-            "close",
-            "volume",
-            "vwap",
-            "trade_count",
-            "adx",
-            "atr",
-            "aroonosc",
-            "aroonu",
-            "aroond",
-            "bband_up",
-            "bband_mid",
-            "bband_low",
-            "macd",
-            "macdsignal",
-            "macdhist",
-            "rsi",
-            "stoch_slowk",
-            "stoch_slowd",
-            "sma",
-        ]
-        .iter()
-        .map(|s| String::from(*s))
-        .collect();
-        let tickers_list = tickers.unwrap();
-
-        let dataframe = <polars::prelude::DataFrame as polars::prelude::IntoLazy>::lazy(
-            StockFrame::grab_latest_data(start.unwrap(), end.unwrap(), &tickers_list),
+        let cache_timeframe = timeframe.to_string();
+
+        let provider = match std::env::var("MILKSHAKE_OFFLINE").is_ok() {
+            true => CachedProvider::offline(BAR_CACHE_DIR, cache_timeframe.as_str()),
+            false => {
+                let mut alpaca = AlpacaProvider::from_env()?;
+                alpaca.timeframe = timeframe;
+
+                CachedProvider::new(Box::new(alpaca), BAR_CACHE_DIR, cache_timeframe.as_str())
+            }
+        };
+
+        StockFrame::from_provider(
+            &provider,
+            tickers.unwrap(),
+            start.unwrap(),
+            end.unwrap(),
+            timeframe,
         )
-        .with_columns(
-            columns_list[9..]
-                .iter()
-                .map(|s| polars::prelude::lit(polars::prelude::NULL).alias(s))
-                .collect::<Vec<_>>()
-                .as_slice(),
+    }
+
+    pub fn from_provider(
+        provider: &dyn BarProvider,
+        tickers: Vec<String>,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+        timeframe: Timeframe,
+    ) -> Result<Self, BarError> {
+        let mut bars = polars::prelude::DataFrame::default();
+
+        for ticker in tickers.iter() {
+            let ticker_bars = provider.bars(ticker.as_str(), start, end)?;
+
+            if ticker_bars.height() == 0 {
+                return Err(BarError::NoData(ticker.clone()));
+            }
+
+            bars = bars.vstack(&ticker_bars)?;
+        }
+
+        Ok(StockFrame::from_bars(tickers, bars, timeframe))
+    }
+
+    // tickers and the date range default to everything in the file, the timeframe to minute bars
+    pub fn from_csv(
+        filename: &str,
+        schema: &BarSchema,
+        tickers: Option<Vec<String>>,
+        start: Option<polars::export::chrono::NaiveDateTime>,
+        end: Option<polars::export::chrono::NaiveDateTime>,
+        timeframe: Option<Timeframe>,
+    ) -> Result<Self, BarError> {
+        StockFrame::from_local(LocalProvider::csv(filename, schema.clone())?, tickers, start, end, timeframe)
+    }
+
+    pub fn from_parquet(
+        filename: &str,
+        schema: &BarSchema,
+        tickers: Option<Vec<String>>,
+        start: Option<polars::export::chrono::NaiveDateTime>,
+        end: Option<polars::export::chrono::NaiveDateTime>,
+        timeframe: Option<Timeframe>,
+    ) -> Result<Self, BarError> {
+        StockFrame::from_local(LocalProvider::parquet(filename, schema.clone())?, tickers, start, end, timeframe)
+    }
+
+    fn from_local(
+        provider: LocalProvider,
+        tickers: Option<Vec<String>>,
+        start: Option<polars::export::chrono::NaiveDateTime>,
+        end: Option<polars::export::chrono::NaiveDateTime>,
+        timeframe: Option<Timeframe>,
+    ) -> Result<Self, BarError> {
+        let (min, max) = provider.time_range()?;
+
+        StockFrame::from_provider(
+            &provider,
+            tickers.unwrap_or(provider.symbols()?),
+            start.unwrap_or(min),
+            end.unwrap_or(max),
+            timeframe.unwrap_or_default(),
         )
-        .collect()
-        .unwrap()
-        .select(&columns_list)
-        .unwrap()
-        .to_owned();
+    }
+
+    // puts the columns of raw bars in the expected order, indicators are added by calc_technical_indicators
+    fn from_bars(tickers_list: Vec<String>, bars: polars::prelude::DataFrame, timeframe: Timeframe) -> Self {
+        let columns_list: Vec<String> = BAR_COLUMNS.iter().map(|s| String::from(*s)).collect();
+
+        let dataframe = bars.select(&columns_list).unwrap();
 
         let dataframe_box = std::cell::RefCell::new(dataframe);
 
@@ -235,10 +406,188 @@ impl StockFrame {
             columns: columns_list,
             tickers: tickers_list,
             frame: dataframe_box,
+            timeframe,
+        }
+    }
+
+    // coarser bars from these ones, open and close are the first and last, volume and trade count add up
+    // and vwap is weighted by volume, bars start on multiples of the timeframe since the unix epoch
+    pub fn resample(&self, timeframe: Timeframe) -> Result<StockFrame, BarError> {
+        if timeframe < self.timeframe {
+            return Err(BarError::InvalidData(format!(
+                "Can't resample {} bars into {} bars",
+                self.timeframe, timeframe
+            )));
+        }
+
+        let mut df = self.frame.borrow().clone();
+
+        let buckets: Vec<Option<polars::export::chrono::NaiveDateTime>> = df
+            .column("timestamp")?
+            .datetime()?
+            .as_datetime_iter()
+            .map(|timestamp| {
+                timestamp.and_then(|timestamp| {
+                    polars::export::chrono::DateTime::from_timestamp_millis(
+                        timeframe.bucket(timestamp.and_utc().timestamp_millis()),
+                    )
+                    .map(|bucket| bucket.naive_utc())
+                })
+            })
+            .collect();
+
+        df.with_column(
+            <polars::prelude::Series as polars::prelude::NamedFrom<
+                Vec<Option<polars::export::chrono::NaiveDateTime>>,
+                _,
+            >>::new("bucket", buckets)
+            .cast(&polars::prelude::DataType::Datetime(
+                polars::prelude::TimeUnit::Milliseconds,
+                None,
+            ))?,
+        )?;
+
+        let volume = || polars::prelude::col("volume").cast(polars::prelude::DataType::Float64);
+
+        let bars = polars::prelude::IntoLazy::lazy(df)
+            .sort(["symbol", "timestamp"], Default::default())
+            .group_by_stable([polars::prelude::col("symbol"), polars::prelude::col("bucket")])
+            .agg([
+                polars::prelude::col("open").drop_nulls().first(),
+                polars::prelude::col("high").max(),
+                polars::prelude::col("low").min(),
+                polars::prelude::col("close").drop_nulls().last(),
+                polars::prelude::col("volume").sum(),
+                // bars without any volume have nothing to weigh with, so they get the plain mean
+                polars::prelude::when(volume().sum().gt(polars::prelude::lit(0f64)))
+                    .then((polars::prelude::col("vwap") * volume()).sum() / volume().sum())
+                    .otherwise(polars::prelude::col("vwap").mean())
+                    .alias("vwap"),
+                polars::prelude::col("trade_count").sum(),
+            ])
+            .rename(["bucket"], ["timestamp"])
+            .collect()?;
+
+        let mut resampled = StockFrame::from_bars(self.tickers.clone(), bars);
+        resampled.timeframe = timeframe;
+
+        Ok(resampled)
+    }
+
+    // adds every feature of other as {feature}_{timeframe}, each row gets the last bar of other that had
+    // already closed at its timestamp so nothing from the future leaks in
+    pub fn join_timeframe(&mut self, other: &StockFrame) -> Result<(), BarError> {
+        let mut df = self.frame.borrow().clone();
+
+        // where every ticker's bars in other start, in order
+        let mut starts: std::collections::HashMap<String, Vec<polars::export::chrono::NaiveDateTime>> =
+            std::collections::HashMap::new();
+
+        {
+            let other_frame = other.frame.borrow();
+
+            for (symbol, timestamp) in other_frame
+                .column("symbol")?
+                .str()?
+                .into_iter()
+                .zip(other_frame.column("timestamp")?.datetime()?.as_datetime_iter())
+            {
+                if let (Some(symbol), Some(timestamp)) = (symbol, timestamp) {
+                    starts.entry(String::from(symbol)).or_default().push(timestamp);
+                }
+            }
+        }
+
+        for ticker_starts in starts.values_mut() {
+            ticker_starts.sort();
         }
+
+        // the last bar that started before the one timestamp falls in, there are no bars between sessions
+        // so after a night or a holiday that is one from an earlier day
+        let keys: Vec<Option<polars::export::chrono::NaiveDateTime>> = df
+            .column("symbol")?
+            .str()?
+            .into_iter()
+            .zip(df.column("timestamp")?.datetime()?.as_datetime_iter())
+            .map(|(symbol, timestamp)| {
+                let ticker_starts = starts.get(symbol?)?;
+                let current = polars::export::chrono::DateTime::from_timestamp_millis(
+                    other.timeframe.bucket(timestamp?.and_utc().timestamp_millis()),
+                )?
+                .naive_utc();
+
+                match ticker_starts.partition_point(|start| *start < current) {
+                    0 => None,
+                    before => Some(ticker_starts[before - 1]),
+                }
+            })
+            .collect();
+
+        df.with_column(
+            <polars::prelude::Series as polars::prelude::NamedFrom<
+                Vec<Option<polars::export::chrono::NaiveDateTime>>,
+                _,
+            >>::new("timeframe_key", keys)
+            .cast(&polars::prelude::DataType::Datetime(
+                polars::prelude::TimeUnit::Milliseconds,
+                None,
+            ))?,
+        )?;
+
+        let features: Vec<String> = other
+            .columns
+            .iter()
+            .filter(|column| *column != "symbol" && *column != "timestamp")
+            .cloned()
+            .collect();
+
+        let renamed: Vec<String> = features
+            .iter()
+            .map(|feature| format!("{}_{}", feature, other.timeframe))
+            .collect();
+
+        let other_df = polars::prelude::IntoLazy::lazy(other.frame.borrow().clone()).select(
+            [
+                vec![
+                    polars::prelude::col("symbol"),
+                    polars::prelude::col("timestamp").alias("timeframe_key"),
+                ],
+                features
+                    .iter()
+                    .zip(renamed.iter())
+                    .map(|(feature, name)| polars::prelude::col(feature.as_str()).alias(name.as_str()))
+                    .collect(),
+            ]
+            .concat(),
+        );
+
+        let joined = polars::prelude::IntoLazy::lazy(df)
+            .join(
+                other_df,
+                [polars::prelude::col("symbol"), polars::prelude::col("timeframe_key")],
+                [polars::prelude::col("symbol"), polars::prelude::col("timeframe_key")],
+                polars::prelude::JoinArgs::new(polars::prelude::JoinType::Left),
+            )
+            .drop(["timeframe_key"])
+            .collect()?;
+
+        self.columns.extend(renamed);
+        self.frame.replace(joined);
+
+        Ok(())
     }
 
     pub fn parse_dt_column(&mut self) {
+        // frames loaded from local files already come with a parsed timestamp
+        if let Ok(polars::prelude::DataType::Datetime(_, _)) = self
+            .frame
+            .borrow()
+            .column("timestamp")
+            .map(|column| column.dtype().clone())
+        {
+            return;
+        }
+
         let lazy_df = <polars::prelude::DataFrame as polars::prelude::IntoLazy>::lazy(
             self.frame.borrow().clone(),
         );
@@ -311,7 +660,38 @@ impl StockFrame {
     }
 
     pub fn fill_date_range(&mut self) {
-        let df = self.frame.borrow().clone();
+        let mut df = self.frame.borrow().clone();
+
+        // bars go on the start of their bucket first so the range below lines up with them, alpaca stamps daily
+        // bars at new york midnight which is 04:00 or 05:00 utc depending on daylight saving time
+        let timeframe = self.timeframe;
+        let dtype = df.column("timestamp").unwrap().dtype().clone();
+        let snapped: Vec<Option<polars::export::chrono::NaiveDateTime>> = df
+            .column("timestamp")
+            .unwrap()
+            .datetime()
+            .unwrap()
+            .as_datetime_iter()
+            .map(|timestamp| {
+                timestamp.and_then(|timestamp| {
+                    polars::export::chrono::DateTime::from_timestamp_millis(
+                        timeframe.bucket(timestamp.and_utc().timestamp_millis()),
+                    )
+                    .map(|bucket| bucket.naive_utc())
+                })
+            })
+            .collect();
+
+        df.with_column(
+            <polars::prelude::Series as polars::prelude::NamedFrom<
+                Vec<Option<polars::export::chrono::NaiveDateTime>>,
+                _,
+            >>::new("timestamp", snapped)
+            .cast(&dtype)
+            .unwrap(),
+        )
+        .unwrap();
+        self.frame.replace(df.clone());
 
         let max = self.get_max_timestamp().timestamp_millis();
         let mut min = self.get_min_timestamp().timestamp_millis();
@@ -319,7 +699,7 @@ impl StockFrame {
 
         while min <= max {
             date_range.push(min);
-            min += 60000;
+            min += self.timeframe.millis();
         }
 
         let ts_range: Vec<polars::export::chrono::NaiveDateTime> = date_range
@@ -335,6 +715,15 @@ impl StockFrame {
             })
             .collect();
 
+        // only bars the exchange is open for, nights, weekends and holidays stay gaps
+        let in_session = NyseCalendar.overlaps_sessions(&ts_range, self.timeframe.duration());
+        let ts_range: Vec<polars::export::chrono::NaiveDateTime> = ts_range
+            .into_iter()
+            .zip(in_session)
+            .filter(|(_, in_session)| *in_session)
+            .map(|(timestamp, _)| timestamp)
+            .collect();
+
         let ts_range_series = <polars::prelude::Series as polars::prelude::NamedFrom<
             &[polars::export::chrono::NaiveDateTime],
             [polars::export::chrono::NaiveDateTime],
@@ -389,305 +778,85 @@ impl StockFrame {
         return Box::new(self.frame.get_mut().group_by(["symbol"]).unwrap());
     }
 
-    // bad TA-Lib wrapper
-    pub unsafe fn calc_technical_indicators(&mut self) {
-        // force sort by symbol
+    // adds the columns of every indicator, each ticker's bars are run through it in timestamp order
+    // and values land on the bar they belong to, bars before the indicator has enough history get nulls
+    // indicator columns from an earlier call are replaced
+    pub fn calc_technical_indicators(&mut self, indicators: &[Indicator]) -> Result<(), BarError> {
+        // force sort by symbol, the groups are sliced out of the frame
+        let sorted = self
+            .frame
+            .borrow()
+            .sort(["symbol", "timestamp"], Default::default())?;
+        self.frame.replace(sorted);
+
         let mut concat_df = polars::prelude::DataFrame::default();
-        let columns = self.columns.clone();
         let symbol_groups = self.update_symbol_groups();
 
         for idx in symbol_groups.get_groups().clone().iter() {
-            let symbol_df = symbol_groups
-                .df
-                .slice(idx.first() as i64, idx.len())
-                .clone();
-            let high: Vec<f64> = symbol_df
-                .column("high")
-                .unwrap()
-                .f64()
-                .unwrap()
-                .into_no_null_iter()
-                .collect();
-            let low: Vec<f64> = symbol_df
-                .column("low")
-                .unwrap()
-                .f64()
-                .unwrap()
-                .into_no_null_iter()
-                .collect();
-            let close: Vec<f64> = symbol_df
-                .column("close")
-                .unwrap()
-                .f64()
-                .unwrap()
-                .into_no_null_iter()
-                .collect();
-
-            let mut s: libc::c_int = 0;
-            let mut n: libc::c_int = 0;
-
-            let mut adx = vec![0f64; idx.len()];
-            let mut atr = vec![0f64; idx.len()];
-            let mut aroon_up = vec![0f64; idx.len()];
-            let mut aroon_down = vec![0f64; idx.len()];
-            let mut aroonosc = vec![0f64; idx.len()];
-            let mut bband_up = vec![0f64; idx.len()];
-            let mut bband_mid = vec![0f64; idx.len()];
-            let mut bband_low = vec![0f64; idx.len()];
-            let mut macd = vec![0f64; idx.len()];
-            let mut macdsignal = vec![0f64; idx.len()];
-            let mut macdhist = vec![0f64; idx.len()];
-            let mut rsi = vec![0f64; idx.len()];
-            let mut stoch_slowk = vec![0f64; idx.len()];
-            let mut stoch_slowd = vec![0f64; idx.len()];
-            let mut sma = vec![0f64; idx.len()];
-
-            assert_eq!(
-                crate::wrappers::talib::TA_ADX(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    high.as_ptr(),
-                    low.as_ptr(),
-                    close.as_ptr(),
-                    14,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    adx.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_ATR(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    high.as_ptr(),
-                    low.as_ptr(),
-                    close.as_ptr(),
-                    14,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    atr.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_AROON(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    high.as_ptr(),
-                    low.as_ptr(),
-                    14,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    aroon_down.as_mut_ptr(),
-                    aroon_up.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_AROONOSC(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    high.as_ptr(),
-                    low.as_ptr(),
-                    14,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    aroonosc.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_BBANDS(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    close.as_ptr(),
-                    5,
-                    2f64,
-                    2f64,
-                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    bband_up.as_mut_ptr(),
-                    bband_mid.as_mut_ptr(),
-                    bband_low.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_MACD(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    close.as_ptr(),
-                    12,
-                    26,
-                    9,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    macd.as_mut_ptr(),
-                    macdsignal.as_mut_ptr(),
-                    macdhist.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_RSI(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    close.as_ptr(),
-                    14,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    rsi.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
-
-            assert_eq!(
-                crate::wrappers::talib::TA_STOCH(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    high.as_ptr(),
-                    low.as_ptr(),
-                    close.as_ptr(),
-                    5,
-                    3,
-                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
-                    3,
-                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    stoch_slowk.as_mut_ptr(),
-                    stoch_slowd.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
+            let symbol_df = symbol_groups.df.slice(idx.first() as i64, idx.len());
+
+            let series = |name: &str| -> Result<Vec<f64>, BarError> {
+                symbol_df
+                    .column(name)?
+                    .f64()?
+                    .into_iter()
+                    .map(|value| {
+                        value.ok_or_else(|| {
+                            BarError::InvalidData(format!("{} has nulls, fill them before calculating indicators", name))
+                        })
+                    })
+                    .collect()
+            };
 
-            assert_eq!(
-                crate::wrappers::talib::TA_SMA(
-                    0,
-                    (close.len() - 1) as libc::c_int,
-                    close.as_ptr(),
-                    30,
-                    &mut s as *mut libc::c_int,
-                    &mut n as *mut libc::c_int,
-                    sma.as_mut_ptr(),
-                ),
-                crate::wrappers::talib::TA_RetCode_TA_SUCCESS
-            );
+            let high = series("high")?;
+            let low = series("low")?;
+            let close = series("close")?;
 
-            let mut new_df = symbol_df.clone();
-            new_df = new_df.drop_many(columns[9..].as_ref());
+            let mut new_df = symbol_df.select(BAR_COLUMNS)?;
 
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("adx", adx),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("atr", atr),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("aroonosc", aroonosc),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("aroonu", aroon_up),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("aroond", aroon_down),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("bband_up", bband_up),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("bband_mid", bband_mid),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("bband_low", bband_low),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("macd", macd),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("macdsignal", macdsignal),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("macdhist", macdhist),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("rsi", rsi),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("stoch_slowk", stoch_slowk),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("stoch_slowd", stoch_slowd),
-                )
-                .unwrap();
-            new_df
-                .with_column(
-                    <polars::prelude::Series as polars::prelude::NamedFromOwned<Vec<f64>>>::from_vec("sma", sma),
-                )
-                .unwrap();
+            for indicator in indicators {
+                for (name, values) in indicator.columns().iter().zip(indicator.compute(&high, &low, &close)?) {
+                    new_df.with_column(<polars::prelude::Series as polars::prelude::NamedFrom<
+                        Vec<Option<f64>>,
+                        _,
+                    >>::new(name, values))?;
+                }
+            }
 
-            concat_df = concat_df.vstack(&new_df).unwrap();
+            concat_df = concat_df.vstack(&new_df)?;
         }
 
+        self.columns = BAR_COLUMNS
+            .iter()
+            .map(|column| String::from(*column))
+            .chain(indicators.iter().flat_map(|indicator| indicator.columns()))
+            .collect();
         self.frame.replace(concat_df);
+
+        Ok(())
     }
 
-    // limit to trading hours (not including first 30 mins due to lack of data in that period)
+    // limit to bars that overlap an nyse session, open and close follow new york's daylight saving time,
+    // holidays are dropped and early close days end at 13:00 new york time
     pub fn clean(&mut self) {
-        let lazy = <polars::prelude::DataFrame as polars::prelude::IntoLazy>::lazy(
-            self.frame.borrow().clone(),
-        );
+        let df = self.frame.borrow().clone();
 
-        let new_df = lazy
-            .filter(
-                polars::prelude::col("timestamp")
-                    .dt()
-                    .hour()
-                    .lt_eq(polars::prelude::lit(20))
-                    .and(
-                        polars::prelude::col("timestamp")
-                            .dt()
-                            .hour()
-                            .gt_eq(polars::prelude::lit(14)),
-                    ),
-            )
-            .collect()
-            .unwrap();
+        let timestamps: Vec<polars::export::chrono::NaiveDateTime> = df
+            .column("timestamp")
+            .unwrap()
+            .datetime()
+            .unwrap()
+            .as_datetime_iter()
+            .map(|timestamp| timestamp.unwrap_or_default())
+            .collect();
+
+        let in_session = NyseCalendar.overlaps_sessions(&timestamps, self.timeframe.duration());
+        let mask = <polars::prelude::BooleanChunked as polars::prelude::NewChunkedArray<
+            polars::prelude::BooleanType,
+            bool,
+        >>::from_slice("in_session", &in_session);
+
+        let new_df = df.filter(&mask).unwrap();
 
         self.frame.replace(new_df);
     }
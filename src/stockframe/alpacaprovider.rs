@@ -0,0 +1,175 @@
+extern crate curl;
+extern crate serde_json;
+
+use crate::stockframe::timeframe::Timeframe;
+use crate::stockframe::{BarError, BarProvider, BarSchema};
+
+// alpaca's short bar keys and the columns they end up in
+const BAR_KEYS: [(&str, &str); 7] = [
+    ("o", "open"),
+    ("h", "high"),
+    ("l", "low"),
+    ("c", "close"),
+    ("v", "volume"),
+    ("vw", "vwap"),
+    ("n", "trade_count"),
+];
+
+pub struct AlpacaProvider {
+    pub key: String,
+    pub secret: String,
+    pub timeframe: Timeframe,
+
+    // pause after every ticker to stay under the rate limit
+    pub request_delay: std::time::Duration,
+}
+
+impl AlpacaProvider {
+    pub fn new(key: String, secret: String) -> Self {
+        AlpacaProvider {
+            key,
+            secret,
+            timeframe: Timeframe::Minute,
+            request_delay: std::time::Duration::from_secs(4),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, BarError> {
+        let key = std::env::var("ALPACA_KEY")
+            .map_err(|_| BarError::MissingCredentials(String::from("ALPACA_KEY")))?;
+        let secret = std::env::var("ALPACA_SECRET")
+            .map_err(|_| BarError::MissingCredentials(String::from("ALPACA_SECRET")))?;
+
+        Ok(AlpacaProvider::new(key, secret))
+    }
+
+    fn grab_entire_json(
+        &self,
+        uri: &String,
+        page_token: Option<String>,
+    ) -> Result<Vec<serde_json::Value>, BarError> {
+        let mut easy = curl::easy::Easy::new();
+
+        let mut data = Vec::new();
+        let mut headers = curl::easy::List::new();
+
+        headers.append(format!("APCA-API-KEY-ID: {}", self.key).as_str())?;
+        headers.append(format!("APCA-API-SECRET-KEY: {}", self.secret).as_str())?;
+
+        match page_token {
+            None => easy.url(uri.as_str()),
+            Some(token) => easy.url(format!("{}&page_token={}", uri, token).as_str()),
+        }?;
+
+        easy.http_headers(headers)?;
+
+        {
+            let mut transfer = easy.transfer();
+            transfer.write_function(|new_data| {
+                data.extend_from_slice(new_data);
+                Ok(new_data.len())
+            })?;
+
+            transfer.perform()?;
+        }
+
+        let json_string = String::from_utf8_lossy(&data).to_string();
+        let json_object: serde_json::Value = serde_json::from_str(json_string.as_str())
+            .map_err(|_| BarError::InvalidResponse(json_string.clone()))?;
+
+        let next_page_token = json_object
+            .get("next_page_token")
+            .ok_or_else(|| BarError::InvalidResponse(json_string.clone()))?;
+
+        // alpaca sends null instead of an empty list when there are no bars
+        let mut bars = match json_object.get("bars") {
+            None => return Err(BarError::InvalidResponse(json_string)),
+            Some(serde_json::Value::Null) => vec![],
+            Some(bars) => bars
+                .as_array()
+                .ok_or_else(|| BarError::InvalidResponse(json_string.clone()))?
+                .clone(),
+        };
+
+        if let Some(page_token) = next_page_token.as_str() {
+            bars.append(&mut self.grab_entire_json(uri, Some(String::from(page_token)))?);
+        }
+
+        Ok(bars)
+    }
+
+    // builds the frame from the keys of every bar, so the order alpaca sends them in doesn't matter
+    fn bars_to_frame(
+        ticker: &str,
+        bars: &[serde_json::Value],
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        let field = |bar: &serde_json::Value, key: &str| -> Result<serde_json::Value, BarError> {
+            bar.get(key)
+                .cloned()
+                .ok_or_else(|| BarError::MissingColumn(format!("{} in bar {}", key, bar)))
+        };
+
+        let timestamps = bars
+            .iter()
+            .map(|bar| {
+                field(bar, "t")?
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| BarError::InvalidData(format!("Timestamp is not a string: {}", bar)))
+            })
+            .collect::<Result<Vec<String>, BarError>>()?;
+
+        let mut columns = vec![
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<String>, _>>::new(
+                "timestamp",
+                timestamps,
+            ),
+        ];
+
+        for (key, name) in BAR_KEYS {
+            let values = bars
+                .iter()
+                .map(|bar| {
+                    field(bar, key)?.as_f64().ok_or_else(|| {
+                        BarError::InvalidData(format!("{} is not a number: {}", key, bar))
+                    })
+                })
+                .collect::<Result<Vec<f64>, BarError>>()?;
+
+            columns.push(
+                <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new(name, values),
+            );
+        }
+
+        let df = polars::prelude::DataFrame::new(columns)?;
+
+        BarSchema::default().normalize(df, ticker)
+    }
+}
+
+impl BarProvider for AlpacaProvider {
+    fn bars(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        let uri = format!(
+            "https://data.alpaca.markets/v2/stocks/{}/bars?start={}&end={}&timeframe={}",
+            ticker,
+            start
+                .and_utc()
+                .to_rfc3339_opts(polars::export::chrono::SecondsFormat::Secs, true),
+            end.and_utc()
+                .to_rfc3339_opts(polars::export::chrono::SecondsFormat::Secs, true),
+            self.timeframe,
+        );
+
+        let bars = self.grab_entire_json(&uri, None);
+
+        // prevent rate limiting
+        std::thread::sleep(self.request_delay);
+
+        AlpacaProvider::bars_to_frame(ticker, &bars?)
+    }
+}
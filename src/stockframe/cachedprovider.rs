@@ -0,0 +1,183 @@
+use crate::stockframe::{BarError, BarProvider};
+
+// keeps every day of bars a provider hands out as {dir}/{ticker}/{timeframe}/{date}.parquet
+// only the days that aren't on disk yet get fetched, and without an inner provider (offline) missing days are an error
+pub struct CachedProvider {
+    pub inner: Option<Box<dyn BarProvider>>,
+    pub dir: std::path::PathBuf,
+    pub timeframe: String,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Box<dyn BarProvider>, dir: &str, timeframe: &str) -> Self {
+        CachedProvider {
+            inner: Some(inner),
+            dir: std::path::PathBuf::from(dir),
+            timeframe: String::from(timeframe),
+        }
+    }
+
+    pub fn offline(dir: &str, timeframe: &str) -> Self {
+        CachedProvider {
+            inner: None,
+            dir: std::path::PathBuf::from(dir),
+            timeframe: String::from(timeframe),
+        }
+    }
+
+    fn day_path(&self, ticker: &str, day: polars::export::chrono::NaiveDate) -> std::path::PathBuf {
+        self.dir
+            .join(ticker)
+            .join(self.timeframe.as_str())
+            .join(format!("{}.parquet", day.format("%Y-%m-%d")))
+    }
+
+    // consecutive days without a cache file, as inclusive (first, last) intervals
+    fn missing_intervals(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDate,
+        end: polars::export::chrono::NaiveDate,
+    ) -> Vec<(polars::export::chrono::NaiveDate, polars::export::chrono::NaiveDate)> {
+        let mut intervals: Vec<(polars::export::chrono::NaiveDate, polars::export::chrono::NaiveDate)> = vec![];
+
+        for day in start.iter_days().take_while(|day| *day <= end) {
+            if self.day_path(ticker, day).exists() {
+                continue;
+            }
+
+            match intervals.last_mut() {
+                Some((_, last)) if *last + polars::export::chrono::Duration::days(1) == day => *last = day,
+                _ => intervals.push((day, day)),
+            }
+        }
+
+        intervals
+    }
+
+    fn fetch(
+        &self,
+        inner: &dyn BarProvider,
+        ticker: &str,
+        first: polars::export::chrono::NaiveDate,
+        last: polars::export::chrono::NaiveDate,
+    ) -> Result<(), BarError> {
+        let bars = inner.bars(
+            ticker,
+            first.and_hms_opt(0, 0, 0).unwrap(),
+            last.and_hms_opt(23, 59, 59).unwrap(),
+        )?;
+
+        std::fs::create_dir_all(self.dir.join(ticker).join(self.timeframe.as_str()))?;
+
+        for day in first.iter_days().take_while(|day| *day <= last) {
+            let day_start = day.and_hms_opt(0, 0, 0).unwrap();
+            let day_end = day_start + polars::export::chrono::Duration::days(1);
+
+            let mut day_bars = polars::prelude::IntoLazy::lazy(bars.clone())
+                .filter(
+                    polars::prelude::col("timestamp")
+                        .gt_eq(polars::prelude::lit(day_start))
+                        .and(polars::prelude::col("timestamp").lt(polars::prelude::lit(day_end))),
+                )
+                .collect()?;
+
+            // empty days (weekends, holidays) are written too so they aren't fetched again
+            polars::prelude::ParquetWriter::new(std::fs::File::create(self.day_path(ticker, day))?)
+                .finish(&mut day_bars)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_day(
+        &self,
+        ticker: &str,
+        day: polars::export::chrono::NaiveDate,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        let df = <polars::prelude::ParquetReader<std::fs::File> as polars::prelude::SerReader<
+            std::fs::File,
+        >>::finish(<polars::prelude::ParquetReader<std::fs::File> as polars::prelude::SerReader<
+            std::fs::File,
+        >>::new(std::fs::File::open(self.day_path(ticker, day))?))?;
+
+        Ok(df)
+    }
+}
+
+impl BarProvider for CachedProvider {
+    fn bars(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        // an end at midnight doesn't reach into that day
+        let last_day = match end.time() == polars::export::chrono::NaiveTime::MIN {
+            true => end.date() - polars::export::chrono::Duration::days(1),
+            false => end.date(),
+        };
+
+        // today isn't over yet, so only days up to yesterday are ever cached
+        let yesterday =
+            polars::export::chrono::Utc::now().date_naive() - polars::export::chrono::Duration::days(1);
+        let last_cached_day = std::cmp::min(last_day, yesterday);
+
+        let mut missing = self.missing_intervals(ticker, start.date(), last_cached_day);
+
+        // offline there is nothing to get today from either
+        if let (true, None) = (last_day > last_cached_day, &self.inner) {
+            missing.push((
+                std::cmp::max(start.date(), last_cached_day + polars::export::chrono::Duration::days(1)),
+                last_day,
+            ));
+        }
+
+        if !missing.is_empty() {
+            match &self.inner {
+                None => {
+                    return Err(BarError::Offline {
+                        ticker: String::from(ticker),
+                        missing,
+                    })
+                }
+                Some(inner) => {
+                    for (first, last) in missing.iter() {
+                        self.fetch(inner.as_ref(), ticker, *first, *last)?;
+                    }
+                }
+            }
+        }
+
+        let mut bars = polars::prelude::DataFrame::default();
+
+        for day in start.date().iter_days().take_while(|day| *day <= last_cached_day) {
+            bars = bars.vstack(&self.read_day(ticker, day)?)?;
+        }
+
+        // today comes straight from the inner provider
+        if let (true, Some(inner)) = (last_day > last_cached_day, &self.inner) {
+            let first = (last_cached_day + polars::export::chrono::Duration::days(1))
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            let fresh = inner.bars(ticker, std::cmp::max(start, first), end)?;
+
+            bars = bars.vstack(&fresh)?;
+        }
+
+        if bars.width() == 0 {
+            return Ok(bars);
+        }
+
+        let bars = polars::prelude::IntoLazy::lazy(bars)
+            .filter(
+                polars::prelude::col("timestamp")
+                    .gt_eq(polars::prelude::lit(start))
+                    .and(polars::prelude::col("timestamp").lt_eq(polars::prelude::lit(end))),
+            )
+            .sort(["timestamp"], Default::default())
+            .collect()?;
+
+        Ok(bars)
+    }
+}
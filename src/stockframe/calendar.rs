@@ -0,0 +1,246 @@
+extern crate polars;
+
+// regular and early session hours, new york time
+const OPEN: (u32, u32) = (9, 30);
+const CLOSE: (u32, u32) = (16, 0);
+const EARLY_CLOSE: (u32, u32) = (13, 0);
+
+// closures that don't follow from the holiday rules, every one since 2000 so sessions are right from then on:
+// september 11th, the national days of mourning for reagan, ford, bush and carter and hurricane sandy
+const SPECIAL_CLOSURES: [(i32, u32, u32); 10] = [
+    (2001, 9, 11),
+    (2001, 9, 12),
+    (2001, 9, 13),
+    (2001, 9, 14),
+    (2004, 6, 11),
+    (2007, 1, 2),
+    (2012, 10, 29),
+    (2012, 10, 30),
+    (2018, 12, 5),
+    (2025, 1, 9),
+];
+
+// one trading day, open and close are utc like the bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Session {
+    pub open: polars::export::chrono::NaiveDateTime,
+    pub close: polars::export::chrono::NaiveDateTime,
+}
+
+// nyse trading days: weekdays without the exchange holidays, 9:30 to 16:00 new york time or 13:00 on
+// early close days, new york's daylight saving time is worked out here so nothing needs a tz database
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NyseCalendar;
+
+fn date(year: i32, month: u32, day: u32) -> polars::export::chrono::NaiveDate {
+    polars::export::chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn weekday(date: polars::export::chrono::NaiveDate) -> polars::export::chrono::Weekday {
+    polars::export::chrono::Datelike::weekday(&date)
+}
+
+// n-th (from 1) given weekday of the month
+fn nth_weekday(
+    year: i32,
+    month: u32,
+    day: polars::export::chrono::Weekday,
+    n: u32,
+) -> polars::export::chrono::NaiveDate {
+    polars::export::chrono::NaiveDate::from_weekday_of_month_opt(year, month, day, n as u8).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, day: polars::export::chrono::Weekday) -> polars::export::chrono::NaiveDate {
+    let mut last = match month {
+        12 => date(year + 1, 1, 1),
+        _ => date(year, month + 1, 1),
+    }
+    .pred_opt()
+    .unwrap();
+
+    while weekday(last) != day {
+        last = last.pred_opt().unwrap();
+    }
+
+    last
+}
+
+// gregorian easter sunday, the anonymous algorithm
+fn easter(year: i32) -> polars::export::chrono::NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    date(year, month as u32, day as u32)
+}
+
+// holidays on a saturday are taken the friday before and on a sunday the monday after
+fn observed(holiday: polars::export::chrono::NaiveDate) -> polars::export::chrono::NaiveDate {
+    match weekday(holiday) {
+        polars::export::chrono::Weekday::Sat => holiday.pred_opt().unwrap(),
+        polars::export::chrono::Weekday::Sun => holiday.succ_opt().unwrap(),
+        _ => holiday,
+    }
+}
+
+fn thanksgiving(year: i32) -> polars::export::chrono::NaiveDate {
+    nth_weekday(year, 11, polars::export::chrono::Weekday::Thu, 4)
+}
+
+// new york is utc-4 from the second sunday of march to the first sunday of november and utc-5 otherwise,
+// sessions are far from the 2am switch so the date is enough
+pub fn utc_offset(day: polars::export::chrono::NaiveDate) -> polars::export::chrono::Duration {
+    let year = polars::export::chrono::Datelike::year(&day);
+
+    // the rules before the energy policy act of 2005
+    let (start, end) = match year < 2007 {
+        true => (
+            nth_weekday(year, 4, polars::export::chrono::Weekday::Sun, 1),
+            last_weekday(year, 10, polars::export::chrono::Weekday::Sun),
+        ),
+        false => (
+            nth_weekday(year, 3, polars::export::chrono::Weekday::Sun, 2),
+            nth_weekday(year, 11, polars::export::chrono::Weekday::Sun, 1),
+        ),
+    };
+
+    match day >= start && day < end {
+        true => polars::export::chrono::Duration::hours(-4),
+        false => polars::export::chrono::Duration::hours(-5),
+    }
+}
+
+impl NyseCalendar {
+    pub fn holidays(&self, year: i32) -> Vec<polars::export::chrono::NaiveDate> {
+        let mut holidays = vec![
+            nth_weekday(year, 1, polars::export::chrono::Weekday::Mon, 3),
+            nth_weekday(year, 2, polars::export::chrono::Weekday::Mon, 3),
+            easter(year) - polars::export::chrono::Duration::days(2),
+            last_weekday(year, 5, polars::export::chrono::Weekday::Mon),
+            observed(date(year, 7, 4)),
+            nth_weekday(year, 9, polars::export::chrono::Weekday::Mon, 1),
+            thanksgiving(year),
+            observed(date(year, 12, 25)),
+        ];
+
+        // new year's day on a saturday isn't made up for, the year before still ends with a full session
+        match weekday(date(year, 1, 1)) {
+            polars::export::chrono::Weekday::Sat => {}
+            _ => holidays.push(observed(date(year, 1, 1))),
+        }
+
+        if year >= 2022 {
+            holidays.push(observed(date(year, 6, 19)));
+        }
+
+        holidays.sort();
+        holidays
+    }
+
+    pub fn is_holiday(&self, day: polars::export::chrono::NaiveDate) -> bool {
+        self.holidays(polars::export::chrono::Datelike::year(&day)).contains(&day)
+            || SPECIAL_CLOSURES
+                .iter()
+                .any(|(year, month, closure)| date(*year, *month, *closure) == day)
+    }
+
+    pub fn is_trading_day(&self, day: polars::export::chrono::NaiveDate) -> bool {
+        !matches!(
+            weekday(day),
+            polars::export::chrono::Weekday::Sat | polars::export::chrono::Weekday::Sun
+        ) && !self.is_holiday(day)
+    }
+
+    // the day before independence day, the day after thanksgiving and christmas eve close at 13:00
+    // unless they are a friday holiday or weekend already
+    pub fn is_early_close(&self, day: polars::export::chrono::NaiveDate) -> bool {
+        let year = polars::export::chrono::Datelike::year(&day);
+        let monday_to_thursday = !matches!(
+            weekday(day),
+            polars::export::chrono::Weekday::Fri
+                | polars::export::chrono::Weekday::Sat
+                | polars::export::chrono::Weekday::Sun
+        );
+
+        (day == date(year, 7, 3) && monday_to_thursday)
+            || day == thanksgiving(year).succ_opt().unwrap()
+            || (day == date(year, 12, 24) && monday_to_thursday)
+    }
+
+    pub fn session(&self, day: polars::export::chrono::NaiveDate) -> Option<Session> {
+        if !self.is_trading_day(day) {
+            return None;
+        }
+
+        let (close_hour, close_minute) = match self.is_early_close(day) {
+            true => EARLY_CLOSE,
+            false => CLOSE,
+        };
+
+        let offset = utc_offset(day);
+
+        Some(Session {
+            open: day.and_hms_opt(OPEN.0, OPEN.1, 0).unwrap() - offset,
+            close: day.and_hms_opt(close_hour, close_minute, 0).unwrap() - offset,
+        })
+    }
+
+    // every session that opens between start and end
+    pub fn sessions(
+        &self,
+        start: polars::export::chrono::NaiveDate,
+        end: polars::export::chrono::NaiveDate,
+    ) -> Vec<Session> {
+        start
+            .iter_days()
+            .take_while(|day| *day <= end)
+            .filter_map(|day| self.session(day))
+            .collect()
+    }
+
+    // whether a bar starting at start (utc) and lasting duration has any part of a session in it,
+    // bars are at most a day long and sessions never cross utc midnight so only two days can overlap
+    pub fn overlaps_session(
+        &self,
+        start: polars::export::chrono::NaiveDateTime,
+        duration: polars::export::chrono::Duration,
+    ) -> bool {
+        self.overlaps_sessions(&[start], duration)[0]
+    }
+
+    // overlaps_session for many bars, every day's session is only worked out once
+    pub fn overlaps_sessions(
+        &self,
+        starts: &[polars::export::chrono::NaiveDateTime],
+        duration: polars::export::chrono::Duration,
+    ) -> Vec<bool> {
+        let mut sessions: std::collections::HashMap<polars::export::chrono::NaiveDate, Option<Session>> =
+            std::collections::HashMap::new();
+
+        starts
+            .iter()
+            .map(|start| {
+                let end = *start + duration;
+                let last = end - polars::export::chrono::Duration::milliseconds(1);
+
+                [start.date(), last.date()].iter().any(|day| {
+                    match *sessions.entry(*day).or_insert_with(|| self.session(*day)) {
+                        Some(session) => session.open < end && *start < session.close,
+                        None => false,
+                    }
+                })
+            })
+            .collect()
+    }
+}
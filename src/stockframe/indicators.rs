@@ -0,0 +1,196 @@
+extern crate anyhow;
+
+pub mod native;
+#[cfg(feature = "talib")]
+pub mod talib;
+
+use crate::stockframe::BarError;
+
+// one technical indicator and its parameters, written like rsi(14), bbands(20,2) or ema(50)
+// a bare name like rsi uses TA-Lib's defaults
+// values come from TA-Lib when the talib feature is on and from the native implementations otherwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Indicator {
+    Adx(usize),
+    Atr(usize),
+    Aroon(usize),
+    AroonOsc(usize),
+    // period and how many standard deviations the bands are from the middle
+    Bbands(usize, f64),
+    // fast, slow and signal period
+    Macd(usize, usize, usize),
+    Rsi(usize),
+    // fast k, slow k and slow d period, both smoothings are simple moving averages
+    Stoch(usize, usize, usize),
+    Sma(usize),
+    Ema(usize),
+}
+
+impl Indicator {
+    // what StockFrame always calculated
+    pub fn defaults() -> Vec<Indicator> {
+        vec![
+            Indicator::Adx(14),
+            Indicator::Atr(14),
+            Indicator::AroonOsc(14),
+            Indicator::Aroon(14),
+            Indicator::Bbands(5, 2f64),
+            Indicator::Macd(12, 26, 9),
+            Indicator::Rsi(14),
+            Indicator::Stoch(5, 3, 3),
+            Indicator::Sma(30),
+        ]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Indicator::Adx(_) => "adx",
+            Indicator::Atr(_) => "atr",
+            Indicator::Aroon(_) => "aroon",
+            Indicator::AroonOsc(_) => "aroonosc",
+            Indicator::Bbands(_, _) => "bbands",
+            Indicator::Macd(_, _, _) => "macd",
+            Indicator::Rsi(_) => "rsi",
+            Indicator::Stoch(_, _, _) => "stoch",
+            Indicator::Sma(_) => "sma",
+            Indicator::Ema(_) => "ema",
+        }
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        match self {
+            Indicator::Adx(period)
+            | Indicator::Atr(period)
+            | Indicator::Aroon(period)
+            | Indicator::AroonOsc(period)
+            | Indicator::Rsi(period)
+            | Indicator::Sma(period)
+            | Indicator::Ema(period) => vec![period.to_string()],
+            Indicator::Bbands(period, deviations) => vec![period.to_string(), deviations.to_string()],
+            Indicator::Macd(a, b, c) | Indicator::Stoch(a, b, c) => vec![a.to_string(), b.to_string(), c.to_string()],
+        }
+    }
+
+    // names of the columns the indicator adds, the parameters are part of them so an indicator can be used
+    // with different periods at once, e.g. rsi(14) adds rsi_14 and bbands(20,2) adds bband_up_20_2, ...
+    pub fn columns(&self) -> Vec<String> {
+        let outputs: &[&str] = match self {
+            Indicator::Aroon(_) => &["aroonu", "aroond"],
+            Indicator::Bbands(_, _) => &["bband_up", "bband_mid", "bband_low"],
+            Indicator::Macd(_, _, _) => &["macd", "macdsignal", "macdhist"],
+            Indicator::Stoch(_, _, _) => &["stoch_slowk", "stoch_slowd"],
+            _ => &[self.name()],
+        };
+
+        let suffix = self.parameters().join("_");
+
+        outputs.iter().map(|output| format!("{}_{}", output, suffix)).collect()
+    }
+
+    // one column per name in columns(), as long as the bars, with nulls until the indicator has enough bars
+    #[cfg(feature = "talib")]
+    pub fn compute(&self, high: &[f64], low: &[f64], close: &[f64]) -> Result<Vec<Vec<Option<f64>>>, BarError> {
+        talib::compute(self, high, low, close)
+    }
+
+    #[cfg(not(feature = "talib"))]
+    pub fn compute(&self, high: &[f64], low: &[f64], close: &[f64]) -> Result<Vec<Vec<Option<f64>>>, BarError> {
+        Ok(native::compute(self, high, low, close))
+    }
+}
+
+// puts values on the bars they were calculated for, values[0] belongs to bar begin
+pub fn align(values: &[f64], begin: usize, len: usize) -> Vec<Option<f64>> {
+    let mut aligned = vec![None; len];
+
+    for (offset, value) in values.iter().enumerate().take(len.saturating_sub(begin)) {
+        aligned[begin + offset] = Some(*value);
+    }
+
+    aligned
+}
+
+impl std::fmt::Display for Indicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}({})", self.name(), self.parameters().join(","))
+    }
+}
+
+impl std::str::FromStr for Indicator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let (name, arguments) = match s.split_once('(') {
+            Some((name, rest)) => match rest.strip_suffix(')') {
+                Some(arguments) => (name.trim(), Some(arguments)),
+                None => anyhow::bail!("Indicator {} is missing a closing parenthesis", s),
+            },
+            None => (s, None),
+        };
+
+        let arguments: Vec<f64> = match arguments {
+            Some(arguments) => arguments
+                .split(',')
+                .map(|argument| {
+                    argument
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| anyhow::anyhow!("Indicator {} has a bad argument: {}", s, argument))
+                })
+                .collect::<anyhow::Result<Vec<f64>>>()?,
+            None => vec![],
+        };
+
+        // the given arguments followed by the defaults of the ones that were left out
+        let argument = |position: usize, default: f64| *arguments.get(position).unwrap_or(&default);
+
+        // TA-Lib rejects smoothing periods below 2, only the atr and the slow stochastic lines go down to 1
+        let period = |position: usize, default: usize, minimum: usize| -> anyhow::Result<usize> {
+            let value = argument(position, default as f64);
+
+            match value.fract() == 0f64 && value >= minimum as f64 && value <= 100000f64 {
+                true => Ok(value as usize),
+                false => anyhow::bail!("Indicator {} needs a whole period of at least {}, got {}", s, minimum, value),
+            }
+        };
+
+        let (indicator, parameters) = match name {
+            "adx" => (Indicator::Adx(period(0, 14, 2)?), 1),
+            "atr" => (Indicator::Atr(period(0, 14, 1)?), 1),
+            "aroon" => (Indicator::Aroon(period(0, 14, 2)?), 1),
+            "aroonosc" => (Indicator::AroonOsc(period(0, 14, 2)?), 1),
+            "bbands" => {
+                let deviations = argument(1, 2f64);
+
+                if !deviations.is_finite() || deviations <= 0f64 {
+                    anyhow::bail!("Indicator {} needs a positive number of deviations", s);
+                }
+
+                (Indicator::Bbands(period(0, 5, 2)?, deviations), 2)
+            }
+            "macd" => (
+                Indicator::Macd(period(0, 12, 2)?, period(1, 26, 2)?, period(2, 9, 1)?),
+                3,
+            ),
+            "rsi" => (Indicator::Rsi(period(0, 14, 2)?), 1),
+            "stoch" => (
+                Indicator::Stoch(period(0, 5, 1)?, period(1, 3, 1)?, period(2, 3, 1)?),
+                3,
+            ),
+            "sma" => (Indicator::Sma(period(0, 30, 2)?), 1),
+            "ema" => (Indicator::Ema(period(0, 30, 2)?), 1),
+            _ => anyhow::bail!(
+                "Unknown indicator {}, expected one of adx, atr, aroon, aroonosc, bbands, macd, rsi, stoch, sma, ema",
+                name
+            ),
+        };
+
+        if arguments.len() > parameters {
+            anyhow::bail!("Indicator {} takes at most {} arguments", name, parameters);
+        }
+
+        Ok(indicator)
+    }
+}
@@ -0,0 +1,430 @@
+use crate::stockframe::indicators::{align, Indicator};
+
+// the indicators in plain rust, they follow TA-Lib's lookbacks, seeding and order of operations so the
+// values match what TA-Lib returns with its default settings
+// every function returns the bar its first value belongs to and the values from there on
+
+// TA_IS_ZERO, TA-Lib treats anything this close to zero as zero
+fn is_zero(value: f64) -> bool {
+    -0.00000001 < value && value < 0.00000001
+}
+
+// indicator's columns calculated natively
+pub fn compute(indicator: &Indicator, high: &[f64], low: &[f64], close: &[f64]) -> Vec<Vec<Option<f64>>> {
+    let (begin, outputs) = match *indicator {
+        Indicator::Adx(period) => {
+            let (begin, adx) = adx(high, low, close, period);
+            (begin, vec![adx])
+        }
+        Indicator::Atr(period) => {
+            let (begin, atr) = atr(high, low, close, period);
+            (begin, vec![atr])
+        }
+        Indicator::Aroon(period) => {
+            let (begin, up, down) = aroon(high, low, period);
+            (begin, vec![up, down])
+        }
+        Indicator::AroonOsc(period) => {
+            let (begin, oscillator) = aroonosc(high, low, period);
+            (begin, vec![oscillator])
+        }
+        Indicator::Bbands(period, deviations) => {
+            let (begin, upper, middle, lower) = bbands(close, period, deviations);
+            (begin, vec![upper, middle, lower])
+        }
+        Indicator::Macd(fast, slow, signal) => {
+            let (begin, macd, signal, histogram) = macd(close, fast, slow, signal);
+            (begin, vec![macd, signal, histogram])
+        }
+        Indicator::Rsi(period) => {
+            let (begin, rsi) = rsi(close, period);
+            (begin, vec![rsi])
+        }
+        Indicator::Stoch(fast_k, slow_k, slow_d) => {
+            let (begin, k, d) = stoch(high, low, close, fast_k, slow_k, slow_d);
+            (begin, vec![k, d])
+        }
+        Indicator::Sma(period) => {
+            let (begin, sma) = sma(close, period);
+            (begin, vec![sma])
+        }
+        Indicator::Ema(period) => {
+            let (begin, ema) = ema(close, period);
+            (begin, vec![ema])
+        }
+    };
+
+    outputs
+        .iter()
+        .map(|values| align(values, begin, close.len()))
+        .collect()
+}
+
+// kept as a running sum like TA-Lib does
+pub fn sma(values: &[f64], period: usize) -> (usize, Vec<f64>) {
+    let begin = period - 1;
+
+    if values.len() <= begin {
+        return (begin, vec![]);
+    }
+
+    let mut total: f64 = values[..begin].iter().sum();
+    let mut out = Vec::with_capacity(values.len() - begin);
+
+    for today in begin..values.len() {
+        total += values[today];
+        out.push(total / period as f64);
+        total -= values[today - begin];
+    }
+
+    (begin, out)
+}
+
+// seeded with the sma of the first period values
+pub fn ema(values: &[f64], period: usize) -> (usize, Vec<f64>) {
+    let begin = period - 1;
+
+    if values.len() <= begin {
+        return (begin, vec![]);
+    }
+
+    let k = 2f64 / (period as f64 + 1f64);
+    let mut previous = values[..period].iter().sum::<f64>() / period as f64;
+    let mut out = vec![previous];
+
+    for value in &values[period..] {
+        previous = ((value - previous) * k) + previous;
+        out.push(previous);
+    }
+
+    (begin, out)
+}
+
+fn true_range(high: f64, low: f64, previous_close: f64) -> f64 {
+    let mut range = high - low;
+
+    if (high - previous_close).abs() > range {
+        range = (high - previous_close).abs();
+    }
+
+    if (low - previous_close).abs() > range {
+        range = (low - previous_close).abs();
+    }
+
+    range
+}
+
+// wilder's smoothing of the true range, seeded with the sma of the first period ranges
+pub fn atr(high: &[f64], low: &[f64], close: &[f64], period: usize) -> (usize, Vec<f64>) {
+    let ranges: Vec<f64> = (1..close.len())
+        .map(|today| true_range(high[today], low[today], close[today - 1]))
+        .collect();
+
+    // over a single bar it is just the true range
+    if period == 1 {
+        return (1, ranges);
+    }
+
+    if close.len() <= period {
+        return (period, vec![]);
+    }
+
+    let (_, first) = sma(&ranges[..period], period);
+    let mut previous = first[0];
+    let mut out = vec![previous];
+
+    for range in &ranges[period..] {
+        previous *= (period - 1) as f64;
+        previous += range;
+        previous /= period as f64;
+        out.push(previous);
+    }
+
+    (period, out)
+}
+
+// wilder's rsi, average gain and loss are seeded with the plain mean of the first period changes
+pub fn rsi(close: &[f64], period: usize) -> (usize, Vec<f64>) {
+    if close.len() <= period {
+        return (period, vec![]);
+    }
+
+    let n = period as f64;
+    let mut gain = 0f64;
+    let mut loss = 0f64;
+
+    for today in 1..=period {
+        let change = close[today] - close[today - 1];
+
+        if change < 0f64 {
+            loss -= change;
+        } else {
+            gain += change;
+        }
+    }
+
+    gain /= n;
+    loss /= n;
+
+    let strength = |gain: f64, loss: f64| match is_zero(gain + loss) {
+        true => 0f64,
+        false => 100f64 * (gain / (gain + loss)),
+    };
+
+    let mut out = vec![strength(gain, loss)];
+
+    for today in period + 1..close.len() {
+        let change = close[today] - close[today - 1];
+
+        loss *= n - 1f64;
+        gain *= n - 1f64;
+
+        if change < 0f64 {
+            loss -= change;
+        } else {
+            gain += change;
+        }
+
+        loss /= n;
+        gain /= n;
+
+        out.push(strength(gain, loss));
+    }
+
+    (period, out)
+}
+
+// upper, middle and lower band around the sma, the deviation is the population one
+pub fn bbands(close: &[f64], period: usize, deviations: f64) -> (usize, Vec<f64>, Vec<f64>, Vec<f64>) {
+    let (begin, middle) = sma(close, period);
+
+    if middle.is_empty() {
+        return (begin, vec![], vec![], vec![]);
+    }
+
+    let mut squares: f64 = close[..begin].iter().map(|value| value * value).sum();
+    let mut upper = Vec::with_capacity(middle.len());
+    let mut lower = Vec::with_capacity(middle.len());
+
+    for (offset, mean) in middle.iter().enumerate() {
+        let today = begin + offset;
+
+        squares += close[today] * close[today];
+        let mut variance = squares / period as f64;
+        squares -= close[today - begin] * close[today - begin];
+        variance -= mean * mean;
+
+        // rounding can leave a tiny negative variance for flat prices
+        let deviation = match variance < 0.00000001 {
+            true => 0f64,
+            false => variance.sqrt(),
+        };
+
+        upper.push(mean + deviations * deviation);
+        lower.push(mean - deviations * deviation);
+    }
+
+    (begin, upper, middle, lower)
+}
+
+// macd line, its signal and the histogram between them
+pub fn macd(close: &[f64], fast: usize, slow: usize, signal: usize) -> (usize, Vec<f64>, Vec<f64>, Vec<f64>) {
+    // TA-Lib takes the shorter period as the fast one
+    let (fast, slow) = match slow < fast {
+        true => (slow, fast),
+        false => (fast, slow),
+    };
+
+    let begin = (slow - 1) + (signal - 1);
+
+    if close.len() <= begin {
+        return (begin, vec![], vec![], vec![]);
+    }
+
+    // both averages start on the bar the slow one has its first value, each seeded with the sma up to there
+    let (_, slow_ema) = ema(close, slow);
+    let (_, fast_ema) = ema(&close[slow - fast..], fast);
+
+    let line: Vec<f64> = fast_ema.iter().zip(slow_ema.iter()).map(|(fast, slow)| fast - slow).collect();
+    let (_, signal_line) = ema(&line, signal);
+    let line = line[signal - 1..].to_vec();
+
+    let histogram = line
+        .iter()
+        .zip(signal_line.iter())
+        .map(|(line, signal)| line - signal)
+        .collect();
+
+    (begin, line, signal_line, histogram)
+}
+
+// slow k and slow d, both smoothed with an sma
+pub fn stoch(
+    high: &[f64],
+    low: &[f64],
+    close: &[f64],
+    fast_k: usize,
+    slow_k: usize,
+    slow_d: usize,
+) -> (usize, Vec<f64>, Vec<f64>) {
+    let begin = (fast_k - 1) + (slow_k - 1) + (slow_d - 1);
+
+    if close.len() <= begin {
+        return (begin, vec![], vec![]);
+    }
+
+    let fast: Vec<f64> = (fast_k - 1..close.len())
+        .map(|today| {
+            let window = today + 1 - fast_k..=today;
+            let highest = high[window.clone()].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let lowest = low[window].iter().copied().fold(f64::INFINITY, f64::min);
+
+            let diff = (highest - lowest) / 100f64;
+
+            match diff != 0f64 {
+                true => (close[today] - lowest) / diff,
+                false => 0f64,
+            }
+        })
+        .collect();
+
+    let (_, k) = sma(&fast, slow_k);
+    let (_, d) = sma(&k, slow_d);
+    let k = k[slow_d - 1..].to_vec();
+
+    (begin, k, d)
+}
+
+// latest highest high and lowest low of the last period + 1 bars, for every bar that has them
+fn aroon_extremes(high: &[f64], low: &[f64], period: usize) -> Vec<(usize, usize)> {
+    (period..high.len())
+        .map(|today| {
+            let window = today - period..=today;
+
+            let highest = window
+                .clone()
+                .fold(today - period, |best, t| if high[t] >= high[best] { t } else { best });
+            let lowest = window.fold(today - period, |best, t| if low[t] <= low[best] { t } else { best });
+
+            (highest, lowest)
+        })
+        .collect()
+}
+
+// how recent the highest high and the lowest low are, 100 for the current bar and 0 for period bars ago
+pub fn aroon(high: &[f64], low: &[f64], period: usize) -> (usize, Vec<f64>, Vec<f64>) {
+    let factor = 100f64 / period as f64;
+
+    let (up, down) = aroon_extremes(high, low, period)
+        .iter()
+        .enumerate()
+        .map(|(offset, (highest, lowest))| {
+            let today = period + offset;
+
+            (
+                factor * (period - (today - highest)) as f64,
+                factor * (period - (today - lowest)) as f64,
+            )
+        })
+        .unzip();
+
+    (period, up, down)
+}
+
+pub fn aroonosc(high: &[f64], low: &[f64], period: usize) -> (usize, Vec<f64>) {
+    let factor = 100f64 / period as f64;
+
+    let oscillator = aroon_extremes(high, low, period)
+        .iter()
+        .map(|(highest, lowest)| factor * (*highest as f64 - *lowest as f64))
+        .collect();
+
+    (period, oscillator)
+}
+
+// wilder's adx, directional movement and true range are summed over period - 1 bars, smoothed from then on,
+// and the first adx is the mean of the next period dx values
+pub fn adx(high: &[f64], low: &[f64], close: &[f64], period: usize) -> (usize, Vec<f64>) {
+    let begin = 2 * period - 1;
+
+    if close.len() <= begin {
+        return (begin, vec![]);
+    }
+
+    let n = period as f64;
+
+    // plus and minus directional movement of a bar over the one before it
+    let movement = |today: usize| {
+        let up = high[today] - high[today - 1];
+        let down = low[today - 1] - low[today];
+
+        if down > 0f64 && up < down {
+            (0f64, down)
+        } else if up > 0f64 && up > down {
+            (up, 0f64)
+        } else {
+            (0f64, 0f64)
+        }
+    };
+
+    // TA-Lib skips bars without any range or movement
+    let dx = |plus_dm: f64, minus_dm: f64, tr: f64| {
+        if is_zero(tr) {
+            return None;
+        }
+
+        let minus_di = 100f64 * (minus_dm / tr);
+        let plus_di = 100f64 * (plus_dm / tr);
+        let sum = minus_di + plus_di;
+
+        match is_zero(sum) {
+            true => None,
+            false => Some(100f64 * ((minus_di - plus_di).abs() / sum)),
+        }
+    };
+
+    let mut plus_dm = 0f64;
+    let mut minus_dm = 0f64;
+    let mut tr = 0f64;
+
+    for today in 1..period {
+        let (plus, minus) = movement(today);
+
+        plus_dm += plus;
+        minus_dm += minus;
+        tr += true_range(high[today], low[today], close[today - 1]);
+    }
+
+    let mut smooth = |today: usize| {
+        let (plus, minus) = movement(today);
+
+        minus_dm -= minus_dm / n;
+        plus_dm -= plus_dm / n;
+        minus_dm += minus;
+        plus_dm += plus;
+        tr = tr - (tr / n) + true_range(high[today], low[today], close[today - 1]);
+
+        dx(plus_dm, minus_dm, tr)
+    };
+
+    let mut sum_dx = 0f64;
+
+    for today in period..=begin {
+        if let Some(dx) = smooth(today) {
+            sum_dx += dx;
+        }
+    }
+
+    let mut previous = sum_dx / n;
+    let mut out = vec![previous];
+
+    for today in begin + 1..close.len() {
+        if let Some(dx) = smooth(today) {
+            previous = ((previous * (n - 1f64)) + dx) / n;
+        }
+
+        out.push(previous);
+    }
+
+    (begin, out)
+}
@@ -0,0 +1,177 @@
+extern crate libc;
+
+use crate::stockframe::indicators::{align, Indicator};
+use crate::stockframe::BarError;
+
+// indicator's columns calculated by TA-Lib
+pub fn compute(
+    indicator: &Indicator,
+    high: &[f64],
+    low: &[f64],
+    close: &[f64],
+) -> Result<Vec<Vec<Option<f64>>>, BarError> {
+    let outputs = indicator.columns().len();
+    let name = indicator.to_string();
+
+    let period = |period: usize| period as libc::c_int;
+
+    talib(
+        name.as_str(),
+        close.len(),
+        outputs,
+        |end, begin, count, out| unsafe {
+            match *indicator {
+                Indicator::Adx(p) => crate::wrappers::talib::TA_ADX(
+                    0,
+                    end,
+                    high.as_ptr(),
+                    low.as_ptr(),
+                    close.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+                Indicator::Atr(p) => crate::wrappers::talib::TA_ATR(
+                    0,
+                    end,
+                    high.as_ptr(),
+                    low.as_ptr(),
+                    close.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+                // TA-Lib hands aroon down out first
+                Indicator::Aroon(p) => crate::wrappers::talib::TA_AROON(
+                    0,
+                    end,
+                    high.as_ptr(),
+                    low.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[1].as_mut_ptr(),
+                    out[0].as_mut_ptr(),
+                ),
+                Indicator::AroonOsc(p) => crate::wrappers::talib::TA_AROONOSC(
+                    0,
+                    end,
+                    high.as_ptr(),
+                    low.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+                Indicator::Bbands(p, deviations) => crate::wrappers::talib::TA_BBANDS(
+                    0,
+                    end,
+                    close.as_ptr(),
+                    period(p),
+                    deviations,
+                    deviations,
+                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                    out[1].as_mut_ptr(),
+                    out[2].as_mut_ptr(),
+                ),
+                Indicator::Macd(fast, slow, signal) => crate::wrappers::talib::TA_MACD(
+                    0,
+                    end,
+                    close.as_ptr(),
+                    period(fast),
+                    period(slow),
+                    period(signal),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                    out[1].as_mut_ptr(),
+                    out[2].as_mut_ptr(),
+                ),
+                Indicator::Rsi(p) => crate::wrappers::talib::TA_RSI(
+                    0,
+                    end,
+                    close.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+                Indicator::Stoch(fast_k, slow_k, slow_d) => crate::wrappers::talib::TA_STOCH(
+                    0,
+                    end,
+                    high.as_ptr(),
+                    low.as_ptr(),
+                    close.as_ptr(),
+                    period(fast_k),
+                    period(slow_k),
+                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
+                    period(slow_d),
+                    crate::wrappers::talib::TA_MAType_TA_MAType_SMA,
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                    out[1].as_mut_ptr(),
+                ),
+                Indicator::Sma(p) => crate::wrappers::talib::TA_SMA(
+                    0,
+                    end,
+                    close.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+                Indicator::Ema(p) => crate::wrappers::talib::TA_EMA(
+                    0,
+                    end,
+                    close.as_ptr(),
+                    period(p),
+                    begin,
+                    count,
+                    out[0].as_mut_ptr(),
+                ),
+            }
+        },
+    )
+}
+
+// runs a TA-Lib function over the whole series, it fills the output buffers from their start
+// and says in begin which bar the first value belongs to
+fn talib(
+    name: &str,
+    len: usize,
+    outputs: usize,
+    call: impl FnOnce(
+        libc::c_int,
+        &mut libc::c_int,
+        &mut libc::c_int,
+        &mut [Vec<f64>],
+    ) -> crate::wrappers::talib::TA_RetCode,
+) -> Result<Vec<Vec<Option<f64>>>, BarError> {
+    if len == 0 {
+        return Ok(vec![vec![]; outputs]);
+    }
+
+    let mut begin: libc::c_int = 0;
+    let mut count: libc::c_int = 0;
+    let mut out = vec![vec![0f64; len]; outputs];
+
+    let code = call((len - 1) as libc::c_int, &mut begin, &mut count, &mut out);
+
+    if code != crate::wrappers::talib::TA_RetCode_TA_SUCCESS {
+        return Err(BarError::Indicator(format!(
+            "TA-Lib returned {} for {}",
+            code, name
+        )));
+    }
+
+    Ok(out
+        .iter()
+        .map(|values| align(&values[..count as usize], begin as usize, len))
+        .collect())
+}
@@ -0,0 +1,105 @@
+use crate::stockframe::{BarError, BarProvider, BarSchema};
+
+// bars from a local CSV or Parquet file, the whole file is read and normalized once up front
+pub struct LocalProvider {
+    pub filename: String,
+    pub bars: polars::prelude::DataFrame,
+}
+
+impl LocalProvider {
+    pub fn csv(filename: &str, schema: BarSchema) -> Result<Self, BarError> {
+        // keep every column as a string so timestamps go through the schema's format
+        let df = polars::prelude::CsvReadOptions::default()
+            .with_has_header(true)
+            .with_infer_schema_length(Some(0))
+            .try_into_reader_with_file_path(Some(filename.into()))?
+            .finish()?;
+
+        LocalProvider::from_frame(filename, df, schema)
+    }
+
+    pub fn parquet(filename: &str, schema: BarSchema) -> Result<Self, BarError> {
+        let df = <polars::prelude::ParquetReader<std::fs::File> as polars::prelude::SerReader<
+            std::fs::File,
+        >>::finish(<polars::prelude::ParquetReader<std::fs::File> as polars::prelude::SerReader<
+            std::fs::File,
+        >>::new(std::fs::File::open(filename)?))?;
+
+        LocalProvider::from_frame(filename, df, schema)
+    }
+
+    // a file without a symbol column holds a single ticker named after the file
+    fn from_frame(
+        filename: &str,
+        df: polars::prelude::DataFrame,
+        schema: BarSchema,
+    ) -> Result<Self, BarError> {
+        let fallback_symbol = std::path::Path::new(filename)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(filename)
+            .to_string();
+
+        Ok(LocalProvider {
+            filename: String::from(filename),
+            bars: schema.normalize(df, fallback_symbol.as_str())?,
+        })
+    }
+
+    pub fn symbols(&self) -> Result<Vec<String>, BarError> {
+        let mut symbols: Vec<String> = self
+            .bars
+            .column("symbol")?
+            .unique()?
+            .str()?
+            .into_no_null_iter()
+            .map(String::from)
+            .collect();
+        symbols.sort();
+
+        Ok(symbols)
+    }
+
+    pub fn time_range(
+        &self,
+    ) -> Result<
+        (
+            polars::export::chrono::NaiveDateTime,
+            polars::export::chrono::NaiveDateTime,
+        ),
+        BarError,
+    > {
+        let timestamps: Vec<polars::export::chrono::NaiveDateTime> = self
+            .bars
+            .column("timestamp")?
+            .datetime()?
+            .as_datetime_iter()
+            .flatten()
+            .collect();
+
+        match (timestamps.iter().min(), timestamps.iter().max()) {
+            (Some(min), Some(max)) => Ok((*min, *max)),
+            _ => Err(BarError::InvalidData(format!("{} has no timestamps", self.filename))),
+        }
+    }
+}
+
+impl BarProvider for LocalProvider {
+    fn bars(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        let bars = polars::prelude::IntoLazy::lazy(self.bars.clone())
+            .filter(
+                polars::prelude::col("symbol")
+                    .eq(polars::prelude::lit(ticker))
+                    .and(polars::prelude::col("timestamp").gt_eq(polars::prelude::lit(start)))
+                    .and(polars::prelude::col("timestamp").lt_eq(polars::prelude::lit(end))),
+            )
+            .collect()?;
+
+        Ok(bars)
+    }
+}
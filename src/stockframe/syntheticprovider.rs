@@ -0,0 +1,190 @@
+extern crate rand;
+extern crate rand_distr;
+
+use crate::stockframe::{BarError, BarProvider};
+
+// the path is at initial_price at midnight on the first day of this year, every other day is reached from there
+const EPOCH_YEAR: i32 = 2000;
+const MINUTES_PER_DAY: i64 = 1440;
+
+// geometric brownian motion minute bars, handy for testing without api keys or data files
+// every ticker gets its own path, and the same seed always produces the same bars, whatever range is asked for
+pub struct SyntheticProvider {
+    pub seed: u64,
+    // price at the epoch
+    pub initial_price: f64,
+    // annualized drift and volatility of the close price
+    pub drift: f64,
+    pub volatility: f64,
+    pub mean_volume: f64,
+}
+
+impl SyntheticProvider {
+    pub fn new(
+        seed: Option<u64>,
+        initial_price: Option<f64>,
+        drift: Option<f64>,
+        volatility: Option<f64>,
+        mean_volume: Option<f64>,
+    ) -> Self {
+        SyntheticProvider {
+            seed: seed.unwrap_or(0),
+            initial_price: initial_price.unwrap_or(100f64),
+            drift: drift.unwrap_or(0.05),
+            volatility: volatility.unwrap_or(0.2),
+            mean_volume: mean_volume.unwrap_or(1000f64),
+        }
+    }
+
+    fn ticker_seed(&self, ticker: &str) -> u64 {
+        ticker
+            .bytes()
+            .fold(self.seed, |seed, byte| seed.wrapping_mul(31).wrapping_add(byte as u64))
+    }
+
+    // every day draws from its own rng, so a day's bars don't depend on where the request started
+    fn day_rng(&self, ticker: &str, day: i64) -> rand::prelude::StdRng {
+        <rand::prelude::StdRng as rand::prelude::SeedableRng>::seed_from_u64(
+            self.ticker_seed(ticker)
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(day as u64),
+        )
+    }
+}
+
+impl BarProvider for SyntheticProvider {
+    fn bars(
+        &self,
+        ticker: &str,
+        start: polars::export::chrono::NaiveDateTime,
+        end: polars::export::chrono::NaiveDateTime,
+    ) -> Result<polars::prelude::DataFrame, BarError> {
+        if end < start {
+            return Err(BarError::InvalidData(format!("{} is before {}", end, start)));
+        }
+
+        let normal =
+            rand_distr::Normal::new(0f64, 1f64).expect("Failed to make normal distribution");
+        let volume_noise = rand_distr::LogNormal::new(0f64, 0.5f64)
+            .expect("Failed to make log normal distribution");
+
+        // one minute as a fraction of a trading year
+        let dt = 1f64 / (252f64 * 390f64);
+        let sigma = self.volatility * dt.sqrt();
+        let drift = (self.drift - 0.5 * self.volatility.powi(2)) * dt;
+        let minutes = MINUTES_PER_DAY as f64;
+
+        // the first draw of every day is its whole move, so getting to a day costs one draw per day before it
+        let day_shock = |day: i64| -> f64 {
+            rand::prelude::Distribution::sample(&normal, &mut self.day_rng(ticker, day))
+        };
+        let day_change = |shock: f64| drift * minutes + sigma * minutes.sqrt() * shock;
+
+        let epoch = polars::export::chrono::NaiveDate::from_ymd_opt(EPOCH_YEAR, 1, 1).unwrap();
+        let first_day = (start.date() - epoch).num_days();
+        let last_day = (end.date() - epoch).num_days();
+
+        let mut log_price = match first_day >= 0 {
+            true => (0..first_day).fold(0f64, |log_price, day| {
+                log_price + day_change(day_shock(day))
+            }),
+            false => (first_day..0).fold(0f64, |log_price, day| {
+                log_price - day_change(day_shock(day))
+            }),
+        };
+
+        let mut timestamps = vec![];
+        let mut open = vec![];
+        let mut high = vec![];
+        let mut low = vec![];
+        let mut close = vec![];
+        let mut volume = vec![];
+        let mut vwap = vec![];
+        let mut trade_count = vec![];
+
+        for day in first_day..=last_day {
+            let mut rng = self.day_rng(ticker, day);
+            let day_total: f64 = rand::prelude::Distribution::sample(&normal, &mut rng);
+            let next_log_price = log_price + day_change(day_total);
+
+            // a brownian bridge, the minutes are pulled onto the day's move so the day ends where the next starts
+            let shocks: Vec<f64> = (0..MINUTES_PER_DAY)
+                .map(|_| rand::prelude::Distribution::sample(&normal, &mut rng))
+                .collect();
+            let shock_mean = shocks.iter().sum::<f64>() / minutes;
+
+            let midnight = (epoch + polars::export::chrono::Duration::days(day))
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            let mut minute_log_price = log_price;
+
+            for (minute, shock) in shocks.iter().enumerate() {
+                let minute_end_log_price = match minute as i64 == MINUTES_PER_DAY - 1 {
+                    true => next_log_price,
+                    false => {
+                        minute_log_price
+                            + drift
+                            + sigma * (shock - shock_mean + day_total / minutes.sqrt())
+                    }
+                };
+                let price = self.initial_price * minute_log_price.exp();
+                let next_price = self.initial_price * minute_end_log_price.exp();
+
+                // drawn for every minute, including the ones outside start..end, to keep the rng in step
+                let wick_up: f64 = rand::prelude::Distribution::sample(&normal, &mut rng);
+                let wick_down: f64 = rand::prelude::Distribution::sample(&normal, &mut rng);
+                let noise: f64 = rand::prelude::Distribution::sample(&volume_noise, &mut rng);
+
+                minute_log_price = minute_end_log_price;
+
+                let timestamp = midnight + polars::export::chrono::Duration::minutes(minute as i64);
+
+                if timestamp < start || timestamp > end {
+                    continue;
+                }
+
+                let bar_high = f64::max(price, next_price) * (1f64 + 0.5 * sigma * wick_up.abs());
+                let bar_low = f64::min(price, next_price) * (1f64 - 0.5 * sigma * wick_down.abs());
+                let bar_volume = (self.mean_volume * noise).round();
+
+                timestamps.push(timestamp);
+                open.push(price);
+                high.push(bar_high);
+                low.push(bar_low);
+                close.push(next_price);
+                volume.push(bar_volume as i64);
+                vwap.push((bar_high + bar_low + next_price) / 3f64);
+                trade_count.push(f64::max(1f64, (bar_volume / 100f64).round()) as i64);
+            }
+
+            log_price = next_log_price;
+        }
+
+        let bars = polars::prelude::DataFrame::new(vec![
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<&str>, _>>::new(
+                "symbol",
+                vec![ticker; timestamps.len()],
+            ),
+            <polars::prelude::Series as polars::prelude::NamedFrom<
+                Vec<polars::export::chrono::NaiveDateTime>,
+                _,
+            >>::new("timestamp", timestamps)
+            .cast(&polars::prelude::DataType::Datetime(
+                polars::prelude::TimeUnit::Milliseconds,
+                None,
+            ))?,
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("open", open),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("high", high),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("low", low),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("close", close),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<i64>, _>>::new("volume", volume),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<f64>, _>>::new("vwap", vwap),
+            <polars::prelude::Series as polars::prelude::NamedFrom<Vec<i64>, _>>::new(
+                "trade_count",
+                trade_count,
+            ),
+        ])?;
+
+        Ok(bars)
+    }
+}
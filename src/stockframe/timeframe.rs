@@ -0,0 +1,62 @@
+extern crate anyhow;
+
+// bar sizes alpaca serves, named the way its api and the cache directories spell them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Timeframe {
+    #[default]
+    Minute,
+    FiveMinutes,
+    FifteenMinutes,
+    Hour,
+    Day,
+}
+
+impl Timeframe {
+    pub fn duration(&self) -> polars::export::chrono::Duration {
+        match self {
+            Timeframe::Minute => polars::export::chrono::Duration::minutes(1),
+            Timeframe::FiveMinutes => polars::export::chrono::Duration::minutes(5),
+            Timeframe::FifteenMinutes => polars::export::chrono::Duration::minutes(15),
+            Timeframe::Hour => polars::export::chrono::Duration::hours(1),
+            Timeframe::Day => polars::export::chrono::Duration::days(1),
+        }
+    }
+
+    pub fn millis(&self) -> i64 {
+        self.duration().num_milliseconds()
+    }
+
+    // start of the bar timestamp falls in, bars are aligned to the unix epoch (utc midnight for days)
+    pub fn bucket(&self, timestamp: i64) -> i64 {
+        timestamp.div_euclid(self.millis()) * self.millis()
+    }
+}
+
+impl std::fmt::Display for Timeframe {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Timeframe::Minute => "1Min",
+            Timeframe::FiveMinutes => "5Min",
+            Timeframe::FifteenMinutes => "15Min",
+            Timeframe::Hour => "1Hour",
+            Timeframe::Day => "1Day",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Timeframe {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1Min" => Ok(Timeframe::Minute),
+            "5Min" => Ok(Timeframe::FiveMinutes),
+            "15Min" => Ok(Timeframe::FifteenMinutes),
+            "1Hour" => Ok(Timeframe::Hour),
+            "1Day" => Ok(Timeframe::Day),
+            _ => anyhow::bail!("Unknown timeframe {}, expected one of 1Min, 5Min, 15Min, 1Hour, 1Day", s),
+        }
+    }
+}
@@ -9,16 +9,362 @@ use crate::optimizer::adam::ADAM;
 use crate::optimizer::cmaes::CMAES;
 use crate::optimizer::MilkshakeOptimizer;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    ReLU,
+    Tanh,
+    ELU,
+    SiLU,
+    Identity,
+}
+
+impl Activation {
+    pub fn apply(&self, xs: &tch::Tensor) -> tch::Tensor {
+        match self {
+            Activation::ReLU => xs.relu(),
+            Activation::Tanh => xs.tanh(),
+            Activation::ELU => xs.elu(),
+            Activation::SiLU => xs.silu(),
+            Activation::Identity => xs.shallow_clone(),
+        }
+    }
+}
+
+impl std::str::FromStr for Activation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "relu" => Ok(Activation::ReLU),
+            "tanh" => Ok(Activation::Tanh),
+            "elu" => Ok(Activation::ELU),
+            "silu" | "swish" => Ok(Activation::SiLU),
+            "identity" | "linear" | "none" => Ok(Activation::Identity),
+            _ => anyhow::bail!("Invalid activation: {}", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Activation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Activation::ReLU => write!(f, "relu"),
+            Activation::Tanh => write!(f, "tanh"),
+            Activation::ELU => write!(f, "elu"),
+            Activation::SiLU => write!(f, "silu"),
+            Activation::Identity => write!(f, "identity"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Initialization {
+    // whatever tch::nn::linear does out of the box (kaiming uniform)
+    Default,
+    Orthogonal(f64),
+    Xavier,
+    // uniform(-bound, bound) on weights and bias, usually only used for the final layer
+    Uniform(f64),
+}
+
+impl Initialization {
+    pub fn linear_config(&self, input: i64, output: i64) -> tch::nn::LinearConfig {
+        match self {
+            Initialization::Default => Default::default(),
+
+            Initialization::Orthogonal(gain) => tch::nn::LinearConfig {
+                ws_init: tch::nn::Init::Orthogonal { gain: *gain },
+                bs_init: Some(tch::nn::Init::Const(0f64)),
+                bias: true,
+            },
+
+            Initialization::Xavier => {
+                let bound = (6f64 / (input + output) as f64).sqrt();
+
+                tch::nn::LinearConfig {
+                    ws_init: tch::nn::Init::Uniform { lo: -bound, up: bound },
+                    bs_init: Some(tch::nn::Init::Const(0f64)),
+                    bias: true,
+                }
+            }
+
+            Initialization::Uniform(bound) => tch::nn::LinearConfig {
+                ws_init: tch::nn::Init::Uniform { lo: -bound, up: *bound },
+                bs_init: Some(tch::nn::Init::Uniform { lo: -bound, up: *bound }),
+                bias: true,
+            },
+        }
+    }
+}
+
+// accepts "default", "xavier", "orthogonal", "orthogonal(1.41)" and "uniform(0.003)"
+impl std::str::FromStr for Initialization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let (name, arg) = match s.split_once('(') {
+            None => (s.as_str(), None),
+            Some((name, rest)) => match rest.strip_suffix(')') {
+                None => anyhow::bail!("Invalid initialization: {}", s),
+                Some(arg) => (name, Some(arg.trim().parse::<f64>()?)),
+            },
+        };
+
+        match name.trim() {
+            "default" => Ok(Initialization::Default),
+            "orthogonal" => Ok(Initialization::Orthogonal(arg.unwrap_or(1f64))),
+            "xavier" => Ok(Initialization::Xavier),
+            "uniform" => Ok(Initialization::Uniform(arg.unwrap_or(0.003f64))),
+            _ => anyhow::bail!("Invalid initialization: {}", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Initialization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Initialization::Default => write!(f, "default"),
+            Initialization::Orthogonal(gain) => write!(f, "orthogonal({})", gain),
+            Initialization::Xavier => write!(f, "xavier"),
+            Initialization::Uniform(bound) => write!(f, "uniform({})", bound),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSpec {
+    pub size: i64,
+    pub activation: Activation,
+    pub layer_norm: bool,
+    pub dropout: f64,
+    pub init: Initialization,
+}
+
+impl LayerSpec {
+    pub fn new(size: i64) -> Self {
+        LayerSpec {
+            size,
+            activation: Activation::ReLU,
+            layer_norm: false,
+            dropout: 0f64,
+            init: Initialization::Default,
+        }
+    }
+}
+
+// "256:tanh:layernorm:dropout(0.1):orthogonal", everything after the size is optional and unordered
+impl std::str::FromStr for LayerSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s.split(':').map(|token| token.trim());
+
+        let size = tokens
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Empty layer spec"))?
+            .parse::<i64>()
+            .map_err(|_| anyhow::anyhow!("Layer spec must start with a layer size: {}", s))?;
+
+        let mut spec = LayerSpec::new(size);
+
+        for token in tokens {
+            let lowercase = token.to_lowercase();
+
+            if lowercase == "layernorm" || lowercase == "ln" {
+                spec.layer_norm = true;
+            } else if let Some(p) = lowercase.strip_prefix("dropout(").and_then(|p| p.strip_suffix(')')) {
+                spec.dropout = p.parse::<f64>()?;
+                anyhow::ensure!((0f64..1f64).contains(&spec.dropout), "Dropout must be in [0, 1): {}", s);
+            } else if let Ok(activation) = token.parse::<Activation>() {
+                spec.activation = activation;
+            } else {
+                spec.init = token.parse::<Initialization>()?;
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+impl std::fmt::Display for LayerSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.size, self.activation)?;
+
+        if self.layer_norm {
+            write!(f, ":layernorm")?;
+        }
+
+        if self.dropout > 0f64 {
+            write!(f, ":dropout({})", self.dropout)?;
+        }
+
+        write!(f, ":{}", self.init)
+    }
+}
+
+// hidden layers of an mlp plus the init of its output layer, the output layer itself never has an activation
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSpec {
+    pub hidden: Vec<LayerSpec>,
+    pub output_init: Initialization,
+}
+
+impl From<Vec<i64>> for NetworkSpec {
+    fn from(shape: Vec<i64>) -> Self {
+        NetworkSpec {
+            hidden: shape.into_iter().map(LayerSpec::new).collect(),
+            output_init: Initialization::Default,
+        }
+    }
+}
+
+// comma separated layer specs, with an optional trailing "out:<init>" entry for the output layer
+// e.g. "256:relu:layernorm,256:relu,out:uniform(0.003)"
+impl std::str::FromStr for NetworkSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = NetworkSpec::from(vec![]);
+
+        for layer in s.split(',').map(|layer| layer.trim()).filter(|layer| !layer.is_empty()) {
+            match layer.strip_prefix("out:") {
+                Some(init) => spec.output_init = init.parse()?,
+                None => spec.hidden.push(layer.parse()?),
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+impl std::fmt::Display for NetworkSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for layer in &self.hidden {
+            write!(f, "{},", layer)?;
+        }
+
+        write!(f, "out:{}", self.output_init)
+    }
+}
+
+impl NetworkSpec {
+    // a network in a config file is either a spec string or a list of layer spec strings
+    pub fn from_json(value: &serde_json::Value) -> anyhow::Result<Self> {
+        match value {
+            serde_json::Value::String(spec) => spec.parse(),
+            serde_json::Value::Array(layers) => layers
+                .iter()
+                .map(|layer| {
+                    layer
+                        .as_str()
+                        .ok_or_else(|| anyhow::anyhow!("Layer spec must be a string: {}", layer))
+                })
+                .collect::<anyhow::Result<Vec<&str>>>()?
+                .join(",")
+                .parse(),
+            _ => anyhow::bail!("Network spec must be a string or a list of strings: {}", value),
+        }
+    }
+}
+
+// architecture config file, any network that is left out falls back to the defaults
+// {"actor": "256:relu,256:relu,out:uniform(0.003)", "q1": ["256:relu:layernorm", "256:relu"], "q2": "..."}
+// observations that are images get a conv encoder in front of all networks
+// "encoder": {"input_shape": [3, 64, 64], "conv_layers": [{"out_channels": 32, "kernel_size": 8, "stride": 4}]}
+#[derive(Debug, Clone, Default)]
+pub struct ArchitectureConfig {
+    pub actor: Option<NetworkSpec>,
+    pub q1: Option<NetworkSpec>,
+    pub q2: Option<NetworkSpec>,
+    pub encoder: Option<EncoderShape>,
+}
+
+impl ArchitectureConfig {
+    pub fn from_file(filename: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(filename)?;
+        let json: serde_json::Value = serde_json::from_str(data.as_str())?;
+
+        let mut config = ArchitectureConfig::default();
+
+        for (key, value) in json
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Architecture config must be a json object"))?
+        {
+            match key.as_str() {
+                "actor" => config.actor = Some(NetworkSpec::from_json(value)?),
+                "q1" => config.q1 = Some(NetworkSpec::from_json(value)?),
+                "q2" => config.q2 = Some(NetworkSpec::from_json(value)?),
+                "encoder" => {
+                    let encoder: EncoderShape = serde_json::from_value(value.clone())
+                        .map_err(|err| anyhow::anyhow!("Invalid encoder in architecture config: {}", err))?;
+                    encoder.output_dim()?;
+
+                    config.encoder = Some(encoder);
+                }
+                _ => anyhow::bail!("Unknown network in architecture config: {}", key),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
 #[derive(Debug)]
 pub struct MilkshakeLayer {
     pub layer: tch::nn::Linear,
+    pub layer_norm: Option<tch::nn::LayerNorm>,
     pub input: i64,
     pub output: i64,
+    pub activation: Activation,
+    pub dropout: f64,
+    pub init: Initialization,
 }
 
-impl tch::nn::Module for MilkshakeLayer {
-    fn forward(&self, xs: &tch::Tensor) -> tch::Tensor {
-        self.layer.forward(xs)
+impl MilkshakeLayer {
+    pub fn new(
+        path: &tch::nn::Path,
+        input: i64,
+        output: i64,
+        activation: Activation,
+        layer_norm: bool,
+        dropout: f64,
+        init: Initialization,
+    ) -> Self {
+        let layer = tch::nn::linear(path, input, output, init.linear_config(input, output));
+
+        let layer_norm = match layer_norm {
+            true => Some(tch::nn::layer_norm(path, vec![output], Default::default())),
+            false => None,
+        };
+
+        MilkshakeLayer {
+            layer,
+            layer_norm,
+            input,
+            output,
+            activation,
+            dropout,
+            init,
+        }
+    }
+}
+
+impl tch::nn::ModuleT for MilkshakeLayer {
+    fn forward_t(&self, xs: &tch::Tensor, train: bool) -> tch::Tensor {
+        let mut alpha = <tch::nn::Linear as tch::nn::Module>::forward(&self.layer, xs);
+
+        if let Some(layer_norm) = &self.layer_norm {
+            alpha = <tch::nn::LayerNorm as tch::nn::Module>::forward(layer_norm, &alpha);
+        }
+
+        alpha = self.activation.apply(&alpha);
+
+        if self.dropout > 0f64 {
+            alpha = alpha.dropout(self.dropout, train);
+        }
+
+        alpha
     }
 }
 
@@ -27,7 +373,7 @@ impl serde::Serialize for MilkshakeLayer {
     where
         S: serde::Serializer,
     {
-        let mut struct_serializer = serializer.serialize_struct("MilkshakeLayer", 2)?;
+        let mut struct_serializer = serializer.serialize_struct("MilkshakeLayer", 6)?;
 
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(
             &mut struct_serializer,
@@ -41,6 +387,29 @@ impl serde::Serialize for MilkshakeLayer {
             &self.output,
         )?;
 
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(
+            &mut struct_serializer,
+            "activation",
+            &self.activation.to_string(),
+        )?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(
+            &mut struct_serializer,
+            "layer_norm",
+            &self.layer_norm.is_some(),
+        )?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(
+            &mut struct_serializer,
+            "dropout",
+            &self.dropout,
+        )?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(
+            &mut struct_serializer,
+            "init",
+            &self.init.to_string(),
+        )?;
 
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::end(struct_serializer)
     }
@@ -49,22 +418,89 @@ impl serde::Serialize for MilkshakeLayer {
 #[derive(Debug)]
 pub struct MilkshakeNetwork {
     pub layers: Vec<MilkshakeLayer>,
+    // loaded from a checkpoint without layer specs, those only ever ran the first and the last layer
+    pub legacy: bool,
 }
 
-impl tch::nn::Module for MilkshakeNetwork {
-    fn forward(&self, xs: &tch::Tensor) -> tch::Tensor {
-        let mut alpha = self
-            .layers
-            .first()
-            .unwrap()
-            .forward(&xs.totype(tch::Kind::Float))
-            .relu();
-
-        for layer in &self.layers[1..1] {
-            alpha = layer.forward(&alpha).relu();
+impl MilkshakeNetwork {
+    pub fn new(path: &tch::nn::Path, input_dim: i64, output_dim: i64, spec: &NetworkSpec) -> Self {
+        let mut layers = Vec::new();
+        let mut input = input_dim;
+
+        for layer in &spec.hidden {
+            layers.push(MilkshakeLayer::new(
+                path,
+                input,
+                layer.size,
+                layer.activation,
+                layer.layer_norm,
+                layer.dropout,
+                layer.init,
+            ));
+
+            input = layer.size;
+        }
+
+        layers.push(MilkshakeLayer::new(
+            path,
+            input,
+            output_dim,
+            Activation::Identity,
+            false,
+            0f64,
+            spec.output_init,
+        ));
+
+        MilkshakeNetwork { layers, legacy: false }
+    }
+
+    // rebuilds the network from a save file, the weights get loaded into the varstore afterwards
+    fn from_dummy(path: &tch::nn::Path, dummy_layers: Vec<DummyLayer>) -> Self {
+        let num_layers = dummy_layers.len();
+        let legacy = !dummy_layers.is_empty() && dummy_layers.iter().all(|layer| layer.activation.is_none());
+        let mut layers = Vec::new();
+
+        for (idx, layer) in dummy_layers.into_iter().enumerate() {
+            // checkpoints from before layer specs existed were relu mlps with a linear output layer
+            let activation = layer.activation.unwrap_or(match idx + 1 == num_layers {
+                true => Activation::Identity,
+                false => Activation::ReLU,
+            });
+
+            layers.push(MilkshakeLayer::new(
+                path,
+                layer.input_dim,
+                layer.output_dim,
+                activation,
+                layer.layer_norm.unwrap_or(false),
+                layer.dropout.unwrap_or(0f64),
+                layer.init.unwrap_or(Initialization::Default),
+            ));
         }
 
-        self.layers.last().unwrap().forward(&alpha)
+        MilkshakeNetwork { layers, legacy }
+    }
+}
+
+impl tch::nn::ModuleT for MilkshakeNetwork {
+    fn forward_t(&self, xs: &tch::Tensor, train: bool) -> tch::Tensor {
+        let mut alpha = xs.totype(tch::Kind::Float);
+
+        // the weights of legacy checkpoints were trained with the middle layers skipped
+        if self.legacy {
+            let first = self.layers.first().unwrap();
+            let last = self.layers.last().unwrap();
+
+            alpha = <tch::nn::Linear as tch::nn::Module>::forward(&first.layer, &alpha).relu();
+
+            return <tch::nn::Linear as tch::nn::Module>::forward(&last.layer, &alpha);
+        }
+
+        for layer in &self.layers {
+            alpha = <MilkshakeLayer as tch::nn::ModuleT>::forward_t(layer, &alpha, train);
+        }
+
+        alpha
     }
 }
 
@@ -76,19 +512,46 @@ impl serde::Serialize for MilkshakeNetwork {
         let mut seq_serializer = serializer.serialize_seq(Some(self.layers.len()))?;
 
         for layer in &self.layers {
-            <<S as serde::Serializer>::SerializeSeq as serde::ser::SerializeSeq>::serialize_element(&mut seq_serializer, layer)?;
+            match self.legacy {
+                true => <<S as serde::Serializer>::SerializeSeq as serde::ser::SerializeSeq>::serialize_element(&mut seq_serializer, &LegacyLayer(layer))?,
+                false => <<S as serde::Serializer>::SerializeSeq as serde::ser::SerializeSeq>::serialize_element(&mut seq_serializer, layer)?,
+            }
         }
 
         <<S as serde::Serializer>::SerializeSeq as serde::ser::SerializeSeq>::end(seq_serializer)
     }
 }
 
-struct DummyLayer { input_dim: i64,  output_dim: i64 }
+// legacy layers are saved with just their dims again so they still load as legacy
+struct LegacyLayer<'a>(&'a MilkshakeLayer);
+
+impl serde::Serialize for LegacyLayer<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut struct_serializer = serializer.serialize_struct("MilkshakeLayer", 2)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "input_dim", &self.0.input)?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "output_dim", &self.0.output)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::end(struct_serializer)
+    }
+}
+
+struct DummyLayer {
+    input_dim: i64,
+    output_dim: i64,
+    activation: Option<Activation>,
+    layer_norm: Option<bool>,
+    dropout: Option<f64>,
+    init: Option<Initialization>,
+}
 
 impl<'de> serde::Deserialize<'de> for DummyLayer {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
-        enum DummyLayerField { input_dim, output_dim }
-        const DUMMY_LAYER_FIELDS: &[&str] = &["input_dim", "output_dim"];
+        enum DummyLayerField { input_dim, output_dim, activation, layer_norm, dropout, init }
+        const DUMMY_LAYER_FIELDS: &[&str] = &["input_dim", "output_dim", "activation", "layer_norm", "dropout", "init"];
 
         impl<'de> serde::Deserialize<'de> for DummyLayerField {
             fn deserialize<D>(deserializer: D) -> Result<DummyLayerField, D::Error>
@@ -111,6 +574,10 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
                         match value {
                             "input_dim" => Ok(DummyLayerField::input_dim),
                             "output_dim" => Ok(DummyLayerField::output_dim),
+                            "activation" => Ok(DummyLayerField::activation),
+                            "layer_norm" => Ok(DummyLayerField::layer_norm),
+                            "dropout" => Ok(DummyLayerField::dropout),
+                            "init" => Ok(DummyLayerField::init),
                             _ => Err(serde::de::Error::unknown_field(value, DUMMY_LAYER_FIELDS)),
                         }
                     }
@@ -121,6 +588,13 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
             }
         }
 
+        fn parse_field<T: std::str::FromStr<Err = anyhow::Error>, E: serde::de::Error>(value: Option<String>) -> Result<Option<T>, E> {
+            match value {
+                None => Ok(None),
+                Some(value) => value.parse::<T>().map(Some).map_err(|err| E::custom(err)),
+            }
+        }
+
         struct DummyLayerVisitor;
 
         impl<'de> serde::de::Visitor<'de> for DummyLayerVisitor {
@@ -138,8 +612,12 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
                     .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
                 let output_dim = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let activation = parse_field(seq.next_element::<String>()?)?;
+                let layer_norm = seq.next_element()?;
+                let dropout = seq.next_element()?;
+                let init = parse_field(seq.next_element::<String>()?)?;
 
-                Ok(DummyLayer { input_dim, output_dim })
+                Ok(DummyLayer { input_dim, output_dim, activation, layer_norm, dropout, init })
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<DummyLayer, V::Error>
@@ -148,6 +626,10 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
             {
                 let mut input_dim = None;
                 let mut output_dim = None;
+                let mut activation: Option<String> = None;
+                let mut layer_norm = None;
+                let mut dropout = None;
+                let mut init: Option<String> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         DummyLayerField::input_dim => {
@@ -163,12 +645,40 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
                             }
                             output_dim = Some(map.next_value()?);
                         }
+                        DummyLayerField::activation => {
+                            if activation.is_some() {
+                                return Err(serde::de::Error::duplicate_field("activation"));
+                            }
+                            activation = Some(map.next_value()?);
+                        }
+                        DummyLayerField::layer_norm => {
+                            if layer_norm.is_some() {
+                                return Err(serde::de::Error::duplicate_field("layer_norm"));
+                            }
+                            layer_norm = Some(map.next_value()?);
+                        }
+                        DummyLayerField::dropout => {
+                            if dropout.is_some() {
+                                return Err(serde::de::Error::duplicate_field("dropout"));
+                            }
+                            dropout = Some(map.next_value()?);
+                        }
+                        DummyLayerField::init => {
+                            if init.is_some() {
+                                return Err(serde::de::Error::duplicate_field("init"));
+                            }
+                            init = Some(map.next_value()?);
+                        }
                     }
                 }
                 let input_dim = input_dim.ok_or_else(|| serde::de::Error::missing_field("input_dim"))?;
                 let output_dim = output_dim.ok_or_else(|| serde::de::Error::missing_field("output_dim"))?;
 
-                Ok(DummyLayer { input_dim, output_dim })
+                // older checkpoints only stored the layer dims
+                let activation = parse_field(activation)?;
+                let init = parse_field(init)?;
+
+                Ok(DummyLayer { input_dim, output_dim, activation, layer_norm, dropout, init })
             }
         }
 
@@ -176,47 +686,438 @@ impl<'de> serde::Deserialize<'de> for DummyLayer {
     }
 }
 
+#[derive(Debug)]
+pub struct MilkshakeConvLayer {
+    pub layer: tch::nn::Conv2D,
+    pub in_channels: i64,
+    pub out_channels: i64,
+    pub kernel_size: i64,
+    pub stride: i64,
+}
+
+impl tch::nn::Module for MilkshakeConvLayer {
+    fn forward(&self, xs: &tch::Tensor) -> tch::Tensor {
+        <tch::nn::Conv2D as tch::nn::Module>::forward(&self.layer, xs)
+    }
+}
+
+impl serde::Serialize for MilkshakeConvLayer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut struct_serializer = serializer.serialize_struct("MilkshakeConvLayer", 3)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "out_channels", &self.out_channels)?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "kernel_size", &self.kernel_size)?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "stride", &self.stride)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::end(struct_serializer)
+    }
+}
+
+// conv stack that turns a flattened [channels, height, width] observation into a feature vector for the mlp
+#[derive(Debug)]
+pub struct MilkshakeEncoder {
+    pub input_shape: Vec<i64>,
+    pub layers: Vec<MilkshakeConvLayer>,
+}
+
+impl MilkshakeEncoder {
+    pub fn new(path: tch::nn::Path, encoder_shape: EncoderShape) -> anyhow::Result<Self> {
+        encoder_shape.output_dim()?;
+
+        let mut in_channels = encoder_shape.input_shape[0];
+        let mut layers = Vec::new();
+
+        for conv in encoder_shape.conv_layers {
+            let config = tch::nn::ConvConfig {
+                stride: conv.stride,
+                ..Default::default()
+            };
+
+            layers.push(MilkshakeConvLayer {
+                layer: tch::nn::conv2d(&path, in_channels, conv.out_channels, conv.kernel_size, config),
+                in_channels,
+                out_channels: conv.out_channels,
+                kernel_size: conv.kernel_size,
+                stride: conv.stride,
+            });
+
+            in_channels = conv.out_channels;
+        }
+
+        Ok(MilkshakeEncoder {
+            input_shape: encoder_shape.input_shape,
+            layers,
+        })
+    }
+
+    pub fn input_dim(&self) -> i64 {
+        self.input_shape.iter().product()
+    }
+
+    // no padding so each conv shrinks the image by (size - kernel) / stride + 1, new checked it stays above 0
+    pub fn output_dim(&self) -> i64 {
+        let mut channels = self.input_shape[0];
+        let mut height = self.input_shape[1];
+        let mut width = self.input_shape[2];
+
+        for layer in &self.layers {
+            channels = layer.out_channels;
+            height = (height - layer.kernel_size) / layer.stride + 1;
+            width = (width - layer.kernel_size) / layer.stride + 1;
+        }
+
+        channels * height * width
+    }
+}
+
+impl tch::nn::Module for MilkshakeEncoder {
+    fn forward(&self, xs: &tch::Tensor) -> tch::Tensor {
+        let mut alpha = xs
+            .totype(tch::Kind::Float)
+            .view([-1, self.input_shape[0], self.input_shape[1], self.input_shape[2]]);
+
+        for layer in &self.layers {
+            alpha = <MilkshakeConvLayer as tch::nn::Module>::forward(layer, &alpha).relu();
+        }
+
+        alpha.flatten(1, -1)
+    }
+}
+
+impl serde::Serialize for MilkshakeEncoder {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut struct_serializer = serializer.serialize_struct("MilkshakeEncoder", 2)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "input_shape", &self.input_shape)?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "conv_layers", &self.layers)?;
+
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::end(struct_serializer)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvShape {
+    pub out_channels: i64,
+    pub kernel_size: i64,
+    pub stride: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EncoderShape {
+    pub input_shape: Vec<i64>,
+    pub conv_layers: Vec<ConvShape>,
+}
+
+impl EncoderShape {
+    pub fn input_dim(&self) -> i64 {
+        self.input_shape.iter().product()
+    }
+
+    // size of the feature vector, or why the conv layers can't be built on the input shape
+    pub fn output_dim(&self) -> anyhow::Result<i64> {
+        let (mut channels, mut height, mut width) = match self.input_shape[..] {
+            [channels, height, width] if channels > 0 && height > 0 && width > 0 => (channels, height, width),
+            _ => anyhow::bail!(
+                "Encoder input shape must be a positive [channels, height, width], got {:?}",
+                self.input_shape
+            ),
+        };
+
+        for (index, conv) in self.conv_layers.iter().enumerate() {
+            if conv.out_channels <= 0 || conv.kernel_size <= 0 || conv.stride <= 0 {
+                anyhow::bail!(
+                    "Encoder conv layer {} needs positive out_channels, kernel_size and stride, got {}/{}/{}",
+                    index,
+                    conv.out_channels,
+                    conv.kernel_size,
+                    conv.stride
+                );
+            }
+
+            if conv.kernel_size > height || conv.kernel_size > width {
+                anyhow::bail!(
+                    "Encoder conv layer {} has a kernel of {} but its input is only {}x{}",
+                    index,
+                    conv.kernel_size,
+                    height,
+                    width
+                );
+            }
+
+            channels = conv.out_channels;
+            height = (height - conv.kernel_size) / conv.stride + 1;
+            width = (width - conv.kernel_size) / conv.stride + 1;
+        }
+
+        Ok(channels * height * width)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for ConvShape {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+        enum ConvShapeField { out_channels, kernel_size, stride }
+        const CONV_SHAPE_FIELDS: &[&str] = &["out_channels", "kernel_size", "stride"];
+
+        impl<'de> serde::Deserialize<'de> for ConvShapeField {
+            fn deserialize<D>(deserializer: D) -> Result<ConvShapeField, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct FieldVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for FieldVisitor {
+                    type Value = ConvShapeField;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("expecting a field of `MilkshakeConvLayer`")
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> Result<ConvShapeField, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        match value {
+                            "out_channels" => Ok(ConvShapeField::out_channels),
+                            "kernel_size" => Ok(ConvShapeField::kernel_size),
+                            "stride" => Ok(ConvShapeField::stride),
+                            _ => Err(serde::de::Error::unknown_field(value, CONV_SHAPE_FIELDS)),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_identifier(FieldVisitor)
+            }
+        }
+
+        struct ConvShapeVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ConvShapeVisitor {
+            type Value = ConvShape;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("struct MilkshakeConvLayer")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<ConvShape, V::Error>
+            where
+                V: serde::de::SeqAccess<'de>,
+            {
+                let out_channels = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let kernel_size = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let stride = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+                Ok(ConvShape { out_channels, kernel_size, stride })
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<ConvShape, V::Error>
+            where
+                V: serde::de::MapAccess<'de>,
+            {
+                let mut out_channels = None;
+                let mut kernel_size = None;
+                let mut stride = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        ConvShapeField::out_channels => {
+                            if out_channels.is_some() {
+                                return Err(serde::de::Error::duplicate_field("out_channels"));
+                            }
+
+                            out_channels = Some(map.next_value()?);
+                        }
+
+                        ConvShapeField::kernel_size => {
+                            if kernel_size.is_some() {
+                                return Err(serde::de::Error::duplicate_field("kernel_size"));
+                            }
+
+                            kernel_size = Some(map.next_value()?);
+                        }
+
+                        ConvShapeField::stride => {
+                            if stride.is_some() {
+                                return Err(serde::de::Error::duplicate_field("stride"));
+                            }
+
+                            stride = Some(map.next_value()?);
+                        }
+                    }
+                }
+
+                let out_channels = out_channels.ok_or_else(|| serde::de::Error::missing_field("out_channels"))?;
+                let kernel_size = kernel_size.ok_or_else(|| serde::de::Error::missing_field("kernel_size"))?;
+                let stride = stride.ok_or_else(|| serde::de::Error::missing_field("stride"))?;
+
+                Ok(ConvShape { out_channels, kernel_size, stride })
+            }
+        }
+
+        deserializer.deserialize_struct("MilkshakeConvLayer", CONV_SHAPE_FIELDS, ConvShapeVisitor)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for EncoderShape {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+        enum EncoderShapeField { input_shape, conv_layers }
+        const ENCODER_SHAPE_FIELDS: &[&str] = &["input_shape", "conv_layers"];
+
+        impl<'de> serde::Deserialize<'de> for EncoderShapeField {
+            fn deserialize<D>(deserializer: D) -> Result<EncoderShapeField, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct FieldVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for FieldVisitor {
+                    type Value = EncoderShapeField;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("expecting a field of `MilkshakeEncoder`")
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> Result<EncoderShapeField, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        match value {
+                            "input_shape" => Ok(EncoderShapeField::input_shape),
+                            "conv_layers" => Ok(EncoderShapeField::conv_layers),
+                            _ => Err(serde::de::Error::unknown_field(value, ENCODER_SHAPE_FIELDS)),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_identifier(FieldVisitor)
+            }
+        }
+
+        struct EncoderShapeVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EncoderShapeVisitor {
+            type Value = EncoderShape;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("struct MilkshakeEncoder")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<EncoderShape, V::Error>
+            where
+                V: serde::de::SeqAccess<'de>,
+            {
+                let input_shape = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let conv_layers = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+                Ok(EncoderShape { input_shape, conv_layers })
+            }
+
+            fn visit_map<V>(self, mut map: V) -> Result<EncoderShape, V::Error>
+            where
+                V: serde::de::MapAccess<'de>,
+            {
+                let mut input_shape = None;
+                let mut conv_layers = None;
+
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        EncoderShapeField::input_shape => {
+                            if input_shape.is_some() {
+                                return Err(serde::de::Error::duplicate_field("input_shape"));
+                            }
+
+                            input_shape = Some(map.next_value()?);
+                        }
+
+                        EncoderShapeField::conv_layers => {
+                            if conv_layers.is_some() {
+                                return Err(serde::de::Error::duplicate_field("conv_layers"));
+                            }
+
+                            conv_layers = Some(map.next_value()?);
+                        }
+                    }
+                }
+
+                let input_shape = input_shape.ok_or_else(|| serde::de::Error::missing_field("input_shape"))?;
+                let conv_layers = conv_layers.ok_or_else(|| serde::de::Error::missing_field("conv_layers"))?;
+
+                Ok(EncoderShape { input_shape, conv_layers })
+            }
+        }
+
+        deserializer.deserialize_struct("MilkshakeEncoder", ENCODER_SHAPE_FIELDS, EncoderShapeVisitor)
+    }
+}
+
+// the flattened image has to be exactly the observation
+fn check_encoder_input(encoder: &MilkshakeEncoder, state_dim: i64) -> anyhow::Result<()> {
+    match encoder.input_dim() == state_dim {
+        true => Ok(()),
+        false => anyhow::bail!(
+            "Encoder input shape {:?} does not match the state dim {}",
+            encoder.input_shape,
+            state_dim
+        ),
+    }
+}
+
 pub struct Actor {
     pub vs: std::rc::Rc<std::cell::RefCell<tch::nn::VarStore>>,
+    pub encoder: Option<MilkshakeEncoder>,
     pub actor: MilkshakeNetwork,
     pub max_action: f64,
 }
 
 impl Actor {
-    pub fn new(state_dim: i64, action_dim: i64, nn_shape: Vec<i64>, max_action: f64) -> Self {
+    pub fn new(
+        state_dim: i64,
+        action_dim: i64,
+        nn_spec: NetworkSpec,
+        encoder_shape: Option<EncoderShape>,
+        max_action: f64,
+    ) -> anyhow::Result<Self> {
         let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-        let mut shape = nn_shape.clone();
-        shape.insert(0, state_dim);
-        shape.insert(shape.len(), action_dim);
-
-        let mut layers = Vec::new();
+        let encoder = encoder_shape
+            .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+            .transpose()?;
 
-        for x in 1..shape.len() {
-            layers.push(MilkshakeLayer {
-                layer: tch::nn::linear(
-                    vs.borrow().root(),
-                    shape[x - 1],
-                    shape[x],
-                    Default::default(),
-                ),
-
-                input: shape[x - 1],
-                output: shape[x],
-            });
-        }
+        let mlp_input_dim = match &encoder {
+            None => state_dim,
+            Some(encoder) => {
+                check_encoder_input(encoder, state_dim)?;
+                encoder.output_dim()
+            }
+        };
 
-        let actor = MilkshakeNetwork { layers };
+        let actor = MilkshakeNetwork::new(&vs.borrow().root(), mlp_input_dim, action_dim, &nn_spec);
 
-        Actor {
+        Ok(Actor {
             vs,
+            encoder,
             actor,
             max_action,
-        }
+        })
     }
 
-    pub fn forward(&self, xs: &tch::Tensor) -> tch::Tensor {
-        self.max_action * <MilkshakeNetwork as tch::nn::Module>::forward(&self.actor, &xs).tanh()
+    pub fn forward(&self, xs: &tch::Tensor, train: bool) -> tch::Tensor {
+        let xs = match &self.encoder {
+            None => xs.shallow_clone(),
+            Some(encoder) => <MilkshakeEncoder as tch::nn::Module>::forward(encoder, xs),
+        };
+
+        self.max_action * <MilkshakeNetwork as tch::nn::ModuleT>::forward_t(&self.actor, &xs, train).tanh()
     }
 }
 
@@ -228,9 +1129,10 @@ impl serde::Serialize for Actor {
         let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
         self.vs.borrow().save_to_stream(&mut cursor).expect("Failed to save actor varstore to byte buffer");
 
-        let mut struct_serializer = serializer.serialize_struct("Actor", 3)?;
+        let mut struct_serializer = serializer.serialize_struct("Actor", 4)?;
 
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "actor_varstore", cursor.into_inner().as_slice())?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "actor_encoder", &self.encoder)?;
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "actor_network", &self.actor)?;
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "max_action", &self.max_action)?;
 
@@ -243,8 +1145,8 @@ impl<'de> serde::Deserialize<'de> for Actor {
     where
         D: serde::Deserializer<'de>,
     {
-        enum ActorField { actor_varstore, actor_network, max_action }
-        const ACTOR_FIELDS: &[&str] = &["actor_varstore", "actor_network", "max_action"];
+        enum ActorField { actor_varstore, actor_encoder, actor_network, max_action }
+        const ACTOR_FIELDS: &[&str] = &["actor_varstore", "actor_encoder", "actor_network", "max_action"];
 
         impl<'de> serde::Deserialize<'de> for ActorField {
             fn deserialize<D>(deserializer: D) -> Result<ActorField, D::Error>
@@ -266,6 +1168,7 @@ impl<'de> serde::Deserialize<'de> for Actor {
                     {
                         match value {
                             "actor_varstore" => Ok(ActorField::actor_varstore),
+                            "actor_encoder" => Ok(ActorField::actor_encoder),
                             "actor_network" => Ok(ActorField::actor_network),
                             "max_action" => Ok(ActorField::max_action),
                             _ => Err(serde::de::Error::unknown_field(value, ACTOR_FIELDS)),
@@ -293,25 +1196,26 @@ impl<'de> serde::Deserialize<'de> for Actor {
             {
                 let actor_varstore: Vec<u8> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-                let actor_network: Vec<DummyLayer> = seq.next_element()?
+                let actor_encoder: Option<EncoderShape> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
-                let max_action = seq.next_element()?
+                let actor_network: Vec<DummyLayer> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+                let max_action = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
 
                 let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-                let mut layers = Vec::new();
-                for layer in actor_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let encoder = actor_encoder
+                    .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+                    .transpose()
+                    .map_err(serde::de::Error::custom)?;
+
+                let actor = MilkshakeNetwork::from_dummy(&vs.borrow().root(), actor_network);
 
                 let cursor = std::io::Cursor::new(actor_varstore);
                 vs.borrow_mut().load_from_stream(cursor).expect("Failed to load actor varstore from save file");
 
-                let actor = MilkshakeNetwork { layers };
-
-                Ok(Actor { vs, actor, max_action })
+                Ok(Actor { vs, encoder, actor, max_action })
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Actor, V::Error>
@@ -320,6 +1224,7 @@ impl<'de> serde::Deserialize<'de> for Actor {
             {
                 let mut max_action = None;
                 let mut actor_network = None;
+                let mut actor_encoder = None;
                 let mut actor_varstore = None;
 
                 while let Some(key) = map.next_key()? {
@@ -332,6 +1237,14 @@ impl<'de> serde::Deserialize<'de> for Actor {
                             actor_varstore = Some(map.next_value()?);
                         }
 
+                        ActorField::actor_encoder => {
+                            if actor_encoder.is_some() {
+                                return Err(serde::de::Error::duplicate_field("actor_encoder"));
+                            }
+
+                            actor_encoder = Some(map.next_value()?);
+                        }
+
                         ActorField::actor_network => {
                             if actor_network.is_some() {
                                 return Err(serde::de::Error::duplicate_field("actor_network"));
@@ -354,20 +1267,22 @@ impl<'de> serde::Deserialize<'de> for Actor {
                 let actor_network: Vec<DummyLayer> = actor_network.ok_or_else(|| serde::de::Error::missing_field("actor_network"))?;
                 let max_action = max_action.ok_or_else(|| serde::de::Error::missing_field("max_action"))?;
 
+                // checkpoints saved before encoders existed have no actor_encoder field
+                let actor_encoder: Option<EncoderShape> = actor_encoder.unwrap_or(None);
+
                 let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-                let mut layers = Vec::new();
-                for layer in actor_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let encoder = actor_encoder
+                    .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+                    .transpose()
+                    .map_err(serde::de::Error::custom)?;
+
+                let actor = MilkshakeNetwork::from_dummy(&vs.borrow().root(), actor_network);
 
                 let cursor = std::io::Cursor::new(actor_varstore);
                 vs.borrow_mut().load_from_stream(cursor).expect("Failed to load actor varstore from save file");
 
-                let actor = MilkshakeNetwork { layers };
-
-                Ok(Actor { vs, actor, max_action })
+                Ok(Actor { vs, encoder, actor, max_action })
             }
         }
 
@@ -377,71 +1292,61 @@ impl<'de> serde::Deserialize<'de> for Actor {
 
 pub struct Critic {
     pub vs: std::rc::Rc<std::cell::RefCell<tch::nn::VarStore>>,
+    pub encoder: Option<MilkshakeEncoder>,
     pub q1: MilkshakeNetwork,
     pub q2: MilkshakeNetwork,
 }
 
 impl Critic {
-    pub fn new(state_dim: i64, action_dim: i64, q1_shape: Vec<i64>, q2_shape: Vec<i64>) -> Self {
+    pub fn new(
+        state_dim: i64,
+        action_dim: i64,
+        q1_spec: NetworkSpec,
+        q2_spec: NetworkSpec,
+        encoder_shape: Option<EncoderShape>,
+    ) -> anyhow::Result<Self> {
         let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-        let mut q1_shape = q1_shape.clone();
-        q1_shape.insert(0, state_dim + action_dim);
-        q1_shape.insert(q1_shape.len(), 1);
-
-        let mut q1_layers = Vec::new();
-
-        for x in 1..q1_shape.len() {
-            q1_layers.push(MilkshakeLayer {
-                layer: tch::nn::linear(
-                    vs.borrow().root(),
-                    q1_shape[x - 1],
-                    q1_shape[x],
-                    Default::default(),
-                ),
-
-                input: q1_shape[x - 1],
-                output: q1_shape[x],
-            });
-        }
-
-        let mut q2_shape = q2_shape.clone();
-        q2_shape.insert(0, state_dim + action_dim);
-        q2_shape.insert(q2_shape.len(), 1);
+        // both q heads share a single encoder
+        let encoder = encoder_shape
+            .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+            .transpose()?;
 
-        let mut q2_layers = Vec::new();
+        let state_dim = match &encoder {
+            None => state_dim,
+            Some(encoder) => {
+                check_encoder_input(encoder, state_dim)?;
+                encoder.output_dim()
+            }
+        };
 
-        for x in 1..q2_shape.len() {
-            q2_layers.push(MilkshakeLayer {
-                layer: tch::nn::linear(
-                    vs.borrow().root(),
-                    q2_shape[x - 1],
-                    q2_shape[x],
-                    Default::default(),
-                ),
+        let q1 = MilkshakeNetwork::new(&vs.borrow().root(), state_dim + action_dim, 1, &q1_spec);
+        let q2 = MilkshakeNetwork::new(&vs.borrow().root(), state_dim + action_dim, 1, &q2_spec);
 
-                input: q2_shape[x - 1],
-                output: q2_shape[x],
-            });
-        }
+        Ok(Critic { vs, encoder, q1, q2 })
+    }
 
-        let q1 = MilkshakeNetwork { layers: q1_layers };
-        let q2 = MilkshakeNetwork { layers: q2_layers };
+    fn encode(&self, state: &tch::Tensor, action: &tch::Tensor) -> tch::Tensor {
+        let state = match &self.encoder {
+            None => state.totype(tch::Kind::Float),
+            Some(encoder) => <MilkshakeEncoder as tch::nn::Module>::forward(encoder, state),
+        };
 
-        Critic { vs, q1, q2 }
+        tch::Tensor::cat(&[&state, &action.totype(tch::Kind::Float)], 1)
     }
 
-    pub fn forward(&self, state: &tch::Tensor, action: &tch::Tensor) -> (tch::Tensor, tch::Tensor) {
-        let xs = tch::Tensor::cat(&[state, action], 1);
+    pub fn forward(&self, state: &tch::Tensor, action: &tch::Tensor, train: bool) -> (tch::Tensor, tch::Tensor) {
+        let xs = self.encode(state, action);
 
-        let q1 = <MilkshakeNetwork as tch::nn::Module>::forward(&self.q1, &xs);
-        let q2 = <MilkshakeNetwork as tch::nn::Module>::forward(&self.q2, &xs);
+        let q1 = <MilkshakeNetwork as tch::nn::ModuleT>::forward_t(&self.q1, &xs, train);
+        let q2 = <MilkshakeNetwork as tch::nn::ModuleT>::forward_t(&self.q2, &xs, train);
 
         (q1, q2)
     }
 
-    pub fn Q1(&self, xs: &tch::Tensor) -> tch::Tensor {
-        <MilkshakeNetwork as tch::nn::Module>::forward(&self.q1, &xs)
+    pub fn Q1(&self, state: &tch::Tensor, action: &tch::Tensor, train: bool) -> tch::Tensor {
+        let xs = self.encode(state, action);
+        <MilkshakeNetwork as tch::nn::ModuleT>::forward_t(&self.q1, &xs, train)
     }
 }
 
@@ -453,9 +1358,10 @@ impl serde::Serialize for Critic {
         let mut cursor = std::io::Cursor::new(Vec::<u8>::new());
         self.vs.borrow().save_to_stream(&mut cursor).expect("Failed to save critic varstore to byte buffer");
 
-        let mut struct_serializer = serializer.serialize_struct("Critic", 3)?;
+        let mut struct_serializer = serializer.serialize_struct("Critic", 4)?;
 
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "critic_varstore", cursor.into_inner().as_slice())?;
+        <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "critic_encoder", &self.encoder)?;
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "q1_network", &self.q1)?;
         <<S as serde::Serializer>::SerializeStruct as serde::ser::SerializeStruct>::serialize_field(&mut struct_serializer, "q2_network", &self.q2)?;
 
@@ -468,8 +1374,8 @@ impl<'de> serde::Deserialize<'de> for Critic {
     where
         D: serde::Deserializer<'de>,
     {
-        enum CriticField { critic_varstore, q1_network, q2_network }
-        const CRITIC_FIELDS: &[&str] = &["critic_varstore", "q1_network", "q2_network"];
+        enum CriticField { critic_varstore, critic_encoder, q1_network, q2_network }
+        const CRITIC_FIELDS: &[&str] = &["critic_varstore", "critic_encoder", "q1_network", "q2_network"];
 
         impl<'de> serde::Deserialize<'de> for CriticField {
             fn deserialize<D>(deserializer: D) -> Result<CriticField, D::Error>
@@ -491,6 +1397,7 @@ impl<'de> serde::Deserialize<'de> for Critic {
                     {
                         match value {
                             "critic_varstore" => Ok(CriticField::critic_varstore),
+                            "critic_encoder" => Ok(CriticField::critic_encoder),
                             "q1_network" => Ok(CriticField::q1_network),
                             "q2_network" => Ok(CriticField::q2_network),
                             _ => Err(serde::de::Error::unknown_field(value, CRITIC_FIELDS)),
@@ -517,32 +1424,27 @@ impl<'de> serde::Deserialize<'de> for Critic {
             {
                 let critic_varstore: Vec<u8> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-                let q1_network: Vec<DummyLayer> = seq.next_element()?
+                let critic_encoder: Option<EncoderShape> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
-                let q2_network: Vec<DummyLayer> = seq.next_element()?
+                let q1_network: Vec<DummyLayer> = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+                let q2_network: Vec<DummyLayer> = seq.next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
 
                 let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-                let mut q1_layers = Vec::new();
-                for layer in q1_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    q1_layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let encoder = critic_encoder
+                    .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+                    .transpose()
+                    .map_err(serde::de::Error::custom)?;
 
-                let mut q2_layers = Vec::new();
-                for layer in q2_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    q2_layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let q1 = MilkshakeNetwork::from_dummy(&vs.borrow().root(), q1_network);
+                let q2 = MilkshakeNetwork::from_dummy(&vs.borrow().root(), q2_network);
 
                 let cursor = std::io::Cursor::new(critic_varstore);
                 vs.borrow_mut().load_from_stream(cursor).expect("Failed to load critic varstore from save file");
 
-                let q1 = MilkshakeNetwork { layers: q1_layers };
-                let q2 = MilkshakeNetwork { layers: q2_layers };
-
-                Ok(Critic { vs, q1, q2 })
+                Ok(Critic { vs, encoder, q1, q2 })
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Critic, V::Error>
@@ -550,6 +1452,7 @@ impl<'de> serde::Deserialize<'de> for Critic {
                 V: serde::de::MapAccess<'de>,
             {
                 let mut critic_varstore = None;
+                let mut critic_encoder = None;
                 let mut q1_network = None;
                 let mut q2_network = None;
 
@@ -563,6 +1466,14 @@ impl<'de> serde::Deserialize<'de> for Critic {
                             critic_varstore = Some(map.next_value()?);
                         }
 
+                        CriticField::critic_encoder => {
+                            if critic_encoder.is_some() {
+                                return Err(serde::de::Error::duplicate_field("critic_encoder"));
+                            }
+
+                            critic_encoder = Some(map.next_value()?);
+                        }
+
                         CriticField::q1_network => {
                             if q1_network.is_some() {
                                 return Err(serde::de::Error::duplicate_field("q1_network"));
@@ -585,27 +1496,23 @@ impl<'de> serde::Deserialize<'de> for Critic {
                 let q1_network: Vec<DummyLayer> = q1_network.ok_or_else(|| serde::de::Error::missing_field("q1_network"))?;
                 let q2_network: Vec<DummyLayer> = q2_network.ok_or_else(|| serde::de::Error::missing_field("q2_network"))?;
 
+                // checkpoints saved before encoders existed have no critic_encoder field
+                let critic_encoder: Option<EncoderShape> = critic_encoder.unwrap_or(None);
+
                 let vs = std::rc::Rc::new(std::cell::RefCell::new(tch::nn::VarStore::new(**device)));
 
-                let mut q1_layers = Vec::new();
-                for layer in q1_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    q1_layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let encoder = critic_encoder
+                    .map(|encoder_shape| MilkshakeEncoder::new(vs.borrow().root() / "encoder", encoder_shape))
+                    .transpose()
+                    .map_err(serde::de::Error::custom)?;
 
-                let mut q2_layers = Vec::new();
-                for layer in q2_network {
-                    let child = tch::nn::linear(vs.borrow().root(), layer.input_dim, layer.output_dim, Default::default());
-                    q2_layers.push(MilkshakeLayer {layer: child, input: layer.input_dim, output: layer.output_dim});
-                }
+                let q1 = MilkshakeNetwork::from_dummy(&vs.borrow().root(), q1_network);
+                let q2 = MilkshakeNetwork::from_dummy(&vs.borrow().root(), q2_network);
 
                 let cursor = std::io::Cursor::new(critic_varstore);
                 vs.borrow_mut().load_from_stream(cursor).expect("Failed to load critic varstore from save file");
 
-                let q1 = MilkshakeNetwork { layers: q1_layers };
-                let q2 = MilkshakeNetwork { layers: q2_layers };
-
-                Ok(Critic { vs, q1, q2 })
+                Ok(Critic { vs, encoder, q1, q2 })
             }
         }
 
@@ -640,18 +1547,19 @@ impl TD3 {
         max_action: f64,
         actor_opt: &str,
         critic_opt: &str,
-        actor_shape: Option<Vec<i64>>,
-        q1_shape: Option<Vec<i64>>,
-        q2_shape: Option<Vec<i64>>,
+        actor_spec: Option<NetworkSpec>,
+        q1_spec: Option<NetworkSpec>,
+        q2_spec: Option<NetworkSpec>,
+        encoder_shape: Option<EncoderShape>,
         tau: Option<f64>,
         discount: Option<f64>,
         policy_noise: Option<f64>,
         noise_clip: Option<f64>,
         policy_freq: Option<i64>,
     ) -> anyhow::Result<Self> {
-        let actor_shape = actor_shape.unwrap_or(vec![64, 64]);
-        let q1_shape = q1_shape.unwrap_or(vec![64, 64]);
-        let q2_shape = q2_shape.unwrap_or(vec![64, 64]);
+        let actor_spec = actor_spec.unwrap_or(NetworkSpec::from(vec![64, 64]));
+        let q1_spec = q1_spec.unwrap_or(NetworkSpec::from(vec![64, 64]));
+        let q2_spec = q2_spec.unwrap_or(NetworkSpec::from(vec![64, 64]));
 
         let tau = tau.unwrap_or(0.005);
         let discount = discount.unwrap_or(0.99);
@@ -659,11 +1567,11 @@ impl TD3 {
         let noise_clip = noise_clip.unwrap_or(0.5);
         let policy_freq = policy_freq.unwrap_or(2);
 
-        let actor = Actor::new(state_dim, action_dim, actor_shape.clone(), max_action);
-        let actor_target = Actor::new(state_dim, action_dim, actor_shape.clone(), max_action);
+        let actor = Actor::new(state_dim, action_dim, actor_spec.clone(), encoder_shape.clone(), max_action)?;
+        let actor_target = Actor::new(state_dim, action_dim, actor_spec.clone(), encoder_shape.clone(), max_action)?;
 
-        let critic = Critic::new(state_dim, action_dim, q1_shape.clone(), q2_shape.clone());
-        let critic_target = Critic::new(state_dim, action_dim, q1_shape.clone(), q2_shape.clone());
+        let critic = Critic::new(state_dim, action_dim, q1_spec.clone(), q2_spec.clone(), encoder_shape.clone())?;
+        let critic_target = Critic::new(state_dim, action_dim, q1_spec.clone(), q2_spec.clone(), encoder_shape.clone())?;
 
         let actor_opt: anyhow::Result<Box<dyn MilkshakeOptimizer>> = match actor_opt {
             "ADAM" => Ok(Box::new(ADAM::new(0.0003f64, actor.vs.clone()))),
@@ -705,7 +1613,7 @@ impl TD3 {
 
     pub fn select_action(&self, state: Vec<f64>) -> Vec<f64> {
         let state = tch::Tensor::from_slice(&state).to_device(**device);
-        let tensor = self.actor.forward(&state).to_device(tch::Device::Cpu);
+        let tensor = self.actor.forward(&state, false).to_device(tch::Device::Cpu);
         let len = tensor.size().iter().fold(1, |sum, val| sum * *val as usize);
 
         let mut vec = vec![0f32; len];
@@ -728,10 +1636,10 @@ impl TD3 {
             let noise =
                 (action.rand_like() * self.policy_noise).clamp(-self.noise_clip, self.noise_clip);
 
-            let next_action = (self.actor_target.forward(next_state) + noise)
+            let next_action = (self.actor_target.forward(next_state, false) + noise)
                 .clamp(-self.max_action, self.max_action);
 
-            let q = self.critic_target.forward(next_state, &next_action);
+            let q = self.critic_target.forward(next_state, &next_action, false);
 
             let target_q1 = &q.0;
             let target_q2 = &q.1;
@@ -755,7 +1663,7 @@ impl TD3 {
                         .expect("Failed to copy test solution to critic");
                 }
 
-                let q = self.critic.forward(state, action);
+                let q = self.critic.forward(state, action, true);
 
                 let current_q1 = &q.0;
                 let current_q2 = &q.1;
@@ -801,7 +1709,7 @@ impl TD3 {
 
                     let loss = -1 * self
                         .critic
-                        .Q1(&tch::Tensor::cat(&[state, &self.actor.forward(state)], 1))
+                        .Q1(state, &self.actor.forward(state, true), true)
                         .mean(tch::Kind::Float);
 
                     losses.push(loss);
@@ -893,6 +1801,16 @@ impl serde::Serialize for TD3 {
     }
 }
 
+// actors and critics are loaded on their own, so only here can their encoders be checked against the state dim
+fn check_encoders<E: serde::de::Error>(actors: &[&Actor], critics: &[&Critic], state_dim: i64) -> Result<(), E> {
+    actors
+        .iter()
+        .filter_map(|actor| actor.encoder.as_ref())
+        .chain(critics.iter().filter_map(|critic| critic.encoder.as_ref()))
+        .try_for_each(|encoder| check_encoder_input(encoder, state_dim))
+        .map_err(E::custom)
+}
+
 impl<'de> serde::Deserialize<'de> for TD3 {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -985,6 +1903,8 @@ impl<'de> serde::Deserialize<'de> for TD3 {
                 let total_it = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(12, &self))?;
 
+                check_encoders(&[&actor, &actor_target], &[&critic, &critic_target], state_dim)?;
+
                 let actor_opt: Box<dyn MilkshakeOptimizer> = Box::new(ADAM::new(0.0003f64, actor.vs.clone()));
                 let critic_opt: Box<dyn MilkshakeOptimizer> = Box::new(ADAM::new(0.0003f64, critic.vs.clone()));
 
@@ -1154,6 +2074,8 @@ impl<'de> serde::Deserialize<'de> for TD3 {
                 let policy_freq = policy_freq.ok_or_else(|| serde::de::Error::missing_field("policy_freq"))?;
                 let total_it = total_it.ok_or_else(|| serde::de::Error::missing_field("total_it"))?;
 
+                check_encoders(&[&actor, &actor_target], &[&critic, &critic_target], state_dim)?;
+
                 Ok(
                     TD3 {
                         actor,
@@ -1,3 +1,852 @@
 #[cfg(test)]
 mod tests {
+    use crate::environment::stockenv::costs::{Commission, CostModel, Slippage, Spread, StandardCostModel, ZeroCost};
+    use crate::environment::stockenv::margin::MarginConfig;
+    use crate::environment::stockenv::orders::{Bar, Order, OrderConfig, OrderKind};
+    use crate::environment::stockenv::report::BacktestReport;
+    use crate::environment::stockenv::rewards::{CvarPenalty, RewardFunction, WeightedReward};
+    use crate::environment::stockenv::{StockEnv, StockEnvConfig};
+    use crate::environment::Environment;
+    use crate::recording::Recording;
+    use crate::stockframe::calendar::NyseCalendar;
+    use crate::stockframe::indicators::{align, native, Indicator};
+    use crate::stockframe::timeframe::Timeframe;
+    use crate::stockframe::cachedprovider::CachedProvider;
+    use crate::stockframe::syntheticprovider::SyntheticProvider;
+    use crate::stockframe::{BarError, BarProvider, BarSchema, StockFrame};
+    use crate::td3::{Activation, Actor, ConvShape, Critic, EncoderShape, Initialization, LayerSpec, NetworkSpec, TD3};
+
+    #[test]
+    fn network_spec_round_trip() {
+        let spec: NetworkSpec = "256:tanh:layernorm:dropout(0.1):orthogonal(1.41),128,out:uniform(0.003)"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            spec.hidden[0],
+            LayerSpec {
+                size: 256,
+                activation: Activation::Tanh,
+                layer_norm: true,
+                dropout: 0.1,
+                init: Initialization::Orthogonal(1.41),
+            }
+        );
+        assert_eq!(spec.hidden[1], LayerSpec::new(128));
+        assert_eq!(spec.output_init, Initialization::Uniform(0.003));
+
+        assert_eq!(spec.to_string().parse::<NetworkSpec>().unwrap(), spec);
+    }
+
+    #[test]
+    fn network_spec_rejects_garbage() {
+        assert!("relu:256".parse::<NetworkSpec>().is_err());
+        assert!("256:sigmoidish".parse::<NetworkSpec>().is_err());
+        assert!("256:dropout(1.5)".parse::<NetworkSpec>().is_err());
+    }
+
+    #[test]
+    fn stockframe_from_csv_maps_schema() {
+        let filename = std::env::temp_dir().join("milkshake_bars_AAPL.csv");
+        std::fs::write(
+            &filename,
+            "Date,Open,High,Low,Close,Volume\n\
+             2024-01-02 14:30:00,10,11,9,10.5,100\n\
+             2024-01-02 14:31:00,10.5,12,10,11.5,200\n",
+        )
+        .unwrap();
+
+        let schema = BarSchema {
+            timestamp: String::from("Date"),
+            open: String::from("Open"),
+            high: String::from("High"),
+            low: String::from("Low"),
+            close: String::from("Close"),
+            volume: String::from("Volume"),
+            timestamp_format: Some(String::from("%Y-%m-%d %H:%M:%S")),
+            ..Default::default()
+        };
+
+        let stockframe =
+            StockFrame::from_csv(filename.to_str().unwrap(), &schema, None, None, None, None).unwrap();
+        let frame = stockframe.frame.borrow();
+
+        assert_eq!(stockframe.tickers, vec![String::from("milkshake_bars_AAPL")]);
+        assert_eq!(frame.get_column_names()[..9], ["symbol", "timestamp", "open", "high", "low", "close", "volume", "vwap", "trade_count"]);
+        assert_eq!(frame.column("close").unwrap().f64().unwrap().get(1), Some(11.5));
+        assert_eq!(
+            stockframe.get_max_timestamp(),
+            polars::export::chrono::NaiveDate::from_ymd_opt(2024, 1, 2)
+                .unwrap()
+                .and_hms_opt(14, 31, 0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn weighted_reward_sums_terms() {
+        let mut reward = WeightedReward::from_json(&serde_json::json!([
+            {"type": "log_return", "weight": 2.0},
+            {"type": "drawdown"}
+        ]))
+        .unwrap();
+
+        let value = reward.reward(&[100.0, 110.0, 99.0]);
+
+        assert!((value - (2.0 * (0.9f64).ln() - 0.1)).abs() < 1e-12);
+        assert_eq!(reward.info[1].0, "drawdown");
+
+        assert!(WeightedReward::from_json(&serde_json::json!([{"type": "sharpish"}])).is_err());
+        assert!(WeightedReward::from_json(&serde_json::json!([{"type": "cvar", "alpha": 2.0}])).is_err());
+
+        // returns out of a wiped out account are skipped instead of becoming inf or nan
+        let mut cvar = CvarPenalty { alpha: 0.5, window: 4 };
+        let value = cvar.reward(&[100.0, 0.0, 50.0, 40.0, 44.0]);
+
+        assert!((value - -1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn resample_aggregates_ohlcv() {
+        let filename = std::env::temp_dir().join("milkshake_resample_SPY.csv");
+        std::fs::write(
+            &filename,
+            "timestamp,open,high,low,close,volume,vwap\n\
+             2024-01-02 14:30:00,10,11,9,10.5,100,10\n\
+             2024-01-02 14:31:00,10.5,12,10,11.5,300,11\n\
+             2024-01-02 14:35:00,11.5,11.5,8,9,50,9\n",
+        )
+        .unwrap();
+
+        let schema = BarSchema {
+            timestamp_format: Some(String::from("%Y-%m-%d %H:%M:%S")),
+            ..Default::default()
+        };
+
+        let stockframe = StockFrame::from_csv(filename.to_str().unwrap(), &schema, None, None, None, None).unwrap();
+        let resampled = stockframe.resample(Timeframe::FiveMinutes).unwrap();
+        let frame = resampled.frame.borrow();
+
+        assert_eq!(resampled.timeframe, Timeframe::FiveMinutes);
+        assert_eq!(frame.height(), 2);
+        assert_eq!(frame.column("open").unwrap().f64().unwrap().get(0), Some(10.0));
+        assert_eq!(frame.column("high").unwrap().f64().unwrap().get(0), Some(12.0));
+        assert_eq!(frame.column("low").unwrap().f64().unwrap().get(0), Some(9.0));
+        assert_eq!(frame.column("close").unwrap().f64().unwrap().get(0), Some(11.5));
+        assert_eq!(frame.column("vwap").unwrap().f64().unwrap().get(0), Some(10.75));
+        assert_eq!(frame.column("close").unwrap().f64().unwrap().get(1), Some(9.0));
+
+        assert!(resampled.resample(Timeframe::Minute).is_err());
+        assert!("2Min".parse::<Timeframe>().is_err());
+    }
+
+    #[test]
+    fn indicator_specs_parse_and_align() {
+        assert_eq!("rsi(14)".parse::<Indicator>().unwrap(), Indicator::Rsi(14));
+        assert_eq!("bbands(20, 2)".parse::<Indicator>().unwrap(), Indicator::Bbands(20, 2.0));
+        assert_eq!("macd".parse::<Indicator>().unwrap(), Indicator::Macd(12, 26, 9));
+        assert_eq!(
+            Indicator::Bbands(20, 2.0).columns(),
+            vec!["bband_up_20_2", "bband_mid_20_2", "bband_low_20_2"]
+        );
+        assert_eq!(Indicator::Ema(50).to_string().parse::<Indicator>().unwrap(), Indicator::Ema(50));
+
+        assert!("rsi(1)".parse::<Indicator>().is_err());
+        assert!("ema(2.5)".parse::<Indicator>().is_err());
+        assert!("sma(10,2)".parse::<Indicator>().is_err());
+        assert!("vwma(10)".parse::<Indicator>().is_err());
+
+        assert_eq!(align(&[1.0, 2.0], 3, 5), vec![None, None, None, Some(1.0), Some(2.0)]);
+    }
+
+    // random walk bars with some flat stretches, seeded so failures can be repeated
+    fn random_bars(len: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let mut rng = <rand::prelude::StdRng as rand::prelude::SeedableRng>::seed_from_u64(7);
+        let step = rand::distributions::Uniform::from(-1f64..1f64);
+        let (mut high, mut low, mut close) = (vec![], vec![], vec![]);
+        let mut price = 100f64;
+
+        for t in 0..len {
+            if t % 50 < 45 {
+                price += rand::prelude::Distribution::sample(&step, &mut rng);
+            }
+
+            let wick: f64 = rand::prelude::Distribution::sample(&step, &mut rng);
+
+            high.push(price + wick.abs());
+            low.push(price - wick.abs());
+            close.push(price);
+        }
+
+        (high, low, close)
+    }
+
+    #[test]
+    fn native_indicators_warm_up() {
+        assert_eq!(native::sma(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), (2, vec![2.0, 3.0, 4.0]));
+        assert_eq!(native::ema(&[1.0, 2.0, 3.0, 4.0], 3), (2, vec![2.0, 3.0]));
+
+        let rising: Vec<f64> = (0..10).map(|t| t as f64).collect();
+        let (begin, up, down) = native::aroon(&rising, &rising, 5);
+
+        assert_eq!(begin, 5);
+        assert!(up.iter().all(|value| *value == 100.0) && down.iter().all(|value| *value == 0.0));
+        assert_eq!(native::rsi(&rising, 14), (14, vec![]));
+
+        let (high, low, close) = random_bars(40);
+        let columns = Indicator::Macd(12, 26, 9).compute(&high, &low, &close).unwrap();
+
+        assert_eq!(columns.len(), 3);
+        assert!(columns[0][..33].iter().all(|value| value.is_none()));
+        assert!(columns[0][33..].iter().all(|value| value.is_some()));
+    }
+
+    #[cfg(feature = "talib")]
+    #[test]
+    fn native_indicators_match_talib() {
+        let (high, low, close) = random_bars(500);
+
+        for spec in [
+            "adx(14)", "adx(3)", "atr(14)", "atr(1)", "aroon(14)", "aroonosc(14)", "bbands(5,2)", "bbands(20,1.5)",
+            "macd(12,26,9)", "macd(26,12,9)", "rsi(14)", "rsi(2)", "stoch(5,3,3)", "stoch(14,1,3)", "sma(30)",
+            "ema(50)",
+        ] {
+            let indicator: Indicator = spec.parse().unwrap();
+            let expected = crate::stockframe::indicators::talib::compute(&indicator, &high, &low, &close).unwrap();
+            let actual = native::compute(&indicator, &high, &low, &close);
+
+            for (expected, actual) in expected.iter().zip(actual.iter()) {
+                for (t, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+                    match (expected, actual) {
+                        (Some(expected), Some(actual)) => assert!(
+                            (expected - actual).abs() <= 1e-9 * f64::max(1.0, expected.abs()),
+                            "{} differs at bar {}: talib {} native {}",
+                            spec,
+                            t,
+                            expected,
+                            actual
+                        ),
+                        (None, None) => {}
+                        _ => panic!("{} warms up differently at bar {}", spec, t),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nyse_calendar_sessions() {
+        let day = |year, month, day| polars::export::chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let at = |date: polars::export::chrono::NaiveDate, hour, minute| date.and_hms_opt(hour, minute, 0).unwrap();
+
+        // new york is on est in january and on edt from the second sunday of march
+        assert_eq!(NyseCalendar.session(day(2024, 1, 2)).unwrap().open, at(day(2024, 1, 2), 14, 30));
+        assert_eq!(NyseCalendar.session(day(2024, 3, 8)).unwrap().open, at(day(2024, 3, 8), 14, 30));
+        assert_eq!(NyseCalendar.session(day(2024, 3, 11)).unwrap().open, at(day(2024, 3, 11), 13, 30));
+
+        // good friday, juneteenth, christmas observed on monday, and a saturday new year that isn't made up for
+        assert!(NyseCalendar.session(day(2024, 3, 29)).is_none());
+        assert!(NyseCalendar.session(day(2024, 6, 19)).is_none());
+        assert!(NyseCalendar.session(day(2022, 12, 26)).is_none());
+        assert!(NyseCalendar.session(day(2021, 12, 31)).is_some());
+
+        // closures outside the rules
+        assert!(NyseCalendar.session(day(2001, 9, 14)).is_none());
+        assert!(NyseCalendar.session(day(2001, 9, 17)).is_some());
+        assert!(NyseCalendar.session(day(2007, 1, 2)).is_none());
+
+        assert_eq!(NyseCalendar.session(day(2024, 7, 3)).unwrap().close, at(day(2024, 7, 3), 17, 0));
+        assert_eq!(NyseCalendar.session(day(2024, 11, 29)).unwrap().close, at(day(2024, 11, 29), 18, 0));
+
+        let hour = polars::export::chrono::Duration::hours(1);
+
+        assert!(NyseCalendar.overlaps_session(at(day(2024, 7, 1), 13, 0), hour));
+        assert!(!NyseCalendar.overlaps_session(at(day(2024, 1, 2), 13, 0), hour));
+        assert!(NyseCalendar.overlaps_session(at(day(2024, 1, 2), 0, 0), polars::export::chrono::Duration::days(1)));
+        assert!(!NyseCalendar.overlaps_session(at(day(2024, 1, 6), 0, 0), polars::export::chrono::Duration::days(1)));
+    }
+
+    // counts how often the cache goes to the provider behind it
+    struct CountingProvider {
+        inner: SyntheticProvider,
+        calls: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl BarProvider for CountingProvider {
+        fn bars(
+            &self,
+            ticker: &str,
+            start: polars::export::chrono::NaiveDateTime,
+            end: polars::export::chrono::NaiveDateTime,
+        ) -> Result<polars::prelude::DataFrame, BarError> {
+            self.calls.set(self.calls.get() + 1);
+            self.inner.bars(ticker, start, end)
+        }
+    }
+
+    #[test]
+    fn synthetic_bars_do_not_depend_on_the_start() {
+        let provider = SyntheticProvider::new(Some(1), None, None, None, None);
+        let at = |day, hour| {
+            polars::export::chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+
+        let full = provider.bars("SPY", at(2, 0), at(4, 12)).unwrap();
+        let part = provider.bars("SPY", at(3, 6), at(4, 12)).unwrap();
+
+        // the part starts 30 hours into the full range and runs to the same end
+        assert_eq!(full.height(), part.height() + 30 * 60);
+        assert!(full.slice(30 * 60, part.height()).equals(&part));
+    }
+
+    #[test]
+    fn cached_provider_fetches_missing_days_once() {
+        let dir = std::env::temp_dir().join(format!("milkshake_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let dir = dir.to_str().unwrap();
+
+        let midnight = |day| {
+            polars::export::chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+
+        let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+        let cache = CachedProvider::new(
+            Box::new(CountingProvider {
+                inner: SyntheticProvider::new(Some(1), None, None, None, None),
+                calls: calls.clone(),
+            }),
+            dir,
+            "1Min",
+        );
+
+        // an end at midnight doesn't need that day
+        let bars = cache.bars("SPY", midnight(2), midnight(4)).unwrap();
+        assert_eq!((calls.get(), bars.height()), (1, 2 * 1440));
+
+        // only the day that isn't cached yet gets fetched
+        let bars = cache.bars("SPY", midnight(2), midnight(5)).unwrap();
+        assert_eq!((calls.get(), bars.height()), (2, 3 * 1440));
+
+        let bars = cache.bars("SPY", midnight(3), midnight(5)).unwrap();
+        assert_eq!((calls.get(), bars.height()), (2, 2 * 1440));
+
+        let offline = CachedProvider::offline(dir, "1Min");
+        assert_eq!(offline.bars("SPY", midnight(2), midnight(5)).unwrap().height(), 3 * 1440);
+
+        match offline.bars("SPY", midnight(2), midnight(8)) {
+            Err(BarError::Offline { ticker, missing }) => {
+                assert_eq!(ticker, "SPY");
+                assert_eq!(missing, vec![(midnight(5).date(), midnight(7).date())]);
+            }
+            _ => panic!("offline cache should be missing 2024-01-05 to 2024-01-07"),
+        }
+
+        // nothing after yesterday is ever cached, offline that has to fail too
+        let today = polars::export::chrono::Utc::now().date_naive();
+        let tomorrow = today + polars::export::chrono::Duration::days(1);
+        match offline.bars(
+            "SPY",
+            today.and_hms_opt(0, 0, 0).unwrap(),
+            tomorrow.and_hms_opt(12, 0, 0).unwrap(),
+        ) {
+            Err(BarError::Offline { missing, .. }) => assert_eq!(missing, vec![(today, tomorrow)]),
+            _ => panic!("offline cache should be missing today and tomorrow"),
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fill_date_range_keeps_one_daily_bar_across_dst() {
+        let filename = std::env::temp_dir().join("milkshake_daily_SPY.csv");
+
+        // new york midnight in utc, 05:00 before the switch on 2024-03-10 and 04:00 after it
+        std::fs::write(
+            &filename,
+            "timestamp,open,high,low,close,volume\n\
+             2024-03-07 05:00:00,10,11,9,10,100\n\
+             2024-03-08 05:00:00,10,11,9,10,100\n\
+             2024-03-11 04:00:00,10,11,9,10,100\n\
+             2024-03-13 04:00:00,10,11,9,10,100\n",
+        )
+        .unwrap();
+
+        let schema = BarSchema {
+            timestamp_format: Some(String::from("%Y-%m-%d %H:%M:%S")),
+            ..Default::default()
+        };
+
+        let mut stockframe =
+            StockFrame::from_csv(filename.to_str().unwrap(), &schema, None, None, None, Some(Timeframe::Day)).unwrap();
+        stockframe.fill_date_range();
+
+        let frame = stockframe.frame.borrow();
+        let mut timestamps: Vec<polars::export::chrono::NaiveDateTime> = frame
+            .column("timestamp")
+            .unwrap()
+            .datetime()
+            .unwrap()
+            .as_datetime_iter()
+            .flatten()
+            .collect();
+        timestamps.sort();
+
+        let midnight = |day| {
+            polars::export::chrono::NaiveDate::from_ymd_opt(2024, 3, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+
+        // the weekend stays a gap and the missing 12th is filled in once
+        assert_eq!(timestamps, vec![midnight(7), midnight(8), midnight(11), midnight(12), midnight(13)]);
+    }
+
+    fn sample_recording() -> Recording {
+        Recording {
+            initial_qpos: vec![0f64, 0f64],
+            initial_qvel: vec![0f64],
+            time: vec![0.01, 0.02],
+            qpos: vec![vec![0.1, 0.2], vec![0.3, 0.4]],
+            qvel: vec![vec![1f64], vec![2f64]],
+            actions: vec![vec![0.5], vec![-0.5]],
+            rewards: vec![1f64, 0.5],
+        }
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let filename = std::env::temp_dir().join("milkshake_recording.json");
+        let filename = filename.to_str().unwrap();
+
+        let recording = sample_recording();
+        recording.save(filename).unwrap();
+        let loaded = Recording::from_file(filename).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.initial_qpos, recording.initial_qpos);
+        assert_eq!(loaded.initial_qvel, recording.initial_qvel);
+        assert_eq!(loaded.time, recording.time);
+        assert_eq!(loaded.qpos, recording.qpos);
+        assert_eq!(loaded.qvel, recording.qvel);
+        assert_eq!(loaded.actions, recording.actions);
+        assert_eq!(loaded.rewards, recording.rewards);
+    }
+
+    #[test]
+    fn recording_rejects_mismatched_lengths() {
+        let filename = std::env::temp_dir().join("milkshake_recording_malformed.json");
+        let filename = filename.to_str().unwrap();
+
+        // one action more than there are states, replaying it would index past the last state
+        let mut recording = sample_recording();
+        recording.actions.push(vec![0f64]);
+        recording.save(filename).unwrap();
+        assert!(Recording::from_file(filename).is_err());
+
+        let mut recording = sample_recording();
+        recording.rewards.pop();
+        recording.save(filename).unwrap();
+        assert!(Recording::from_file(filename).is_err());
+
+        // states without their time would all play back in the same frame
+        let mut recording = sample_recording();
+        recording.time.clear();
+        recording.save(filename).unwrap();
+        assert!(Recording::from_file(filename).is_err());
+
+        // a recording of just actions is fine
+        let mut recording = sample_recording();
+        recording.time.clear();
+        recording.qpos.clear();
+        recording.qvel.clear();
+        recording.save(filename).unwrap();
+        assert_eq!(Recording::from_file(filename).unwrap().len(), 2);
+    }
+
+    // a 1x6x6 image, one 3x3 conv leaves 2x4x4 features
+    fn encoder_shape(stride: i64) -> EncoderShape {
+        EncoderShape {
+            input_shape: vec![1, 6, 6],
+            conv_layers: vec![ConvShape {
+                out_channels: 2,
+                kernel_size: 3,
+                stride,
+            }],
+        }
+    }
+
+    fn td3_with_encoder(encoder_shape: EncoderShape, state_dim: i64) -> anyhow::Result<TD3> {
+        TD3::new(
+            state_dim,
+            2,
+            1f64,
+            "ADAM",
+            "ADAM",
+            None,
+            None,
+            None,
+            Some(encoder_shape),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn encoder_checkpoint_round_trip() {
+        let state: Vec<f64> = (0..36).map(|i| i as f64 / 36f64).collect();
+        let states = tch::Tensor::from_slice(&state).view([1, 36]).to_device(**crate::device);
+        let actions = tch::Tensor::from_slice(&[0.5f64, -0.5]).view([1, 2]).to_device(**crate::device);
+
+        let actor = Actor::new(36, 2, NetworkSpec::from(vec![8]), Some(encoder_shape(1)), 1f64).unwrap();
+        let loaded: Actor = serde_json::from_str(&serde_json::to_string(&actor).unwrap()).unwrap();
+        assert_eq!(loaded.encoder.as_ref().unwrap().output_dim(), 32);
+        assert!(actor.forward(&states, false).allclose(&loaded.forward(&states, false), 1e-6, 1e-6, false));
+
+        let critic = Critic::new(
+            36,
+            2,
+            NetworkSpec::from(vec![8]),
+            NetworkSpec::from(vec![8]),
+            Some(encoder_shape(1)),
+        )
+        .unwrap();
+        let loaded: Critic = serde_json::from_str(&serde_json::to_string(&critic).unwrap()).unwrap();
+        let (q1, q2) = critic.forward(&states, &actions, false);
+        let (loaded_q1, loaded_q2) = loaded.forward(&states, &actions, false);
+        assert!(q1.allclose(&loaded_q1, 1e-6, 1e-6, false));
+        assert!(q2.allclose(&loaded_q2, 1e-6, 1e-6, false));
+
+        let td3 = td3_with_encoder(encoder_shape(1), 36).unwrap();
+        let json = serde_json::to_value(&td3).unwrap();
+        let loaded: TD3 = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(td3.select_action(state.clone()), loaded.select_action(state));
+
+        // an encoder that doesn't fit the state is an error instead of a panic
+        let mut json = json;
+        json["state_dim"] = serde_json::json!(35);
+        assert!(serde_json::from_value::<TD3>(json).is_err());
+    }
+
+    #[test]
+    fn encoder_rejects_bad_shapes() {
+        assert!(td3_with_encoder(encoder_shape(0), 36).is_err());
+        assert!(td3_with_encoder(encoder_shape(1), 35).is_err());
+
+        let mut too_small = encoder_shape(1);
+        too_small.conv_layers[0].kernel_size = 7;
+        assert!(td3_with_encoder(too_small, 36).is_err());
+
+        let mut flat = encoder_shape(1);
+        flat.input_shape = vec![36];
+        assert!(td3_with_encoder(flat, 36).is_err());
+    }
+
+    #[test]
+    fn layer_specs_checkpoint_round_trip() {
+        let spec: NetworkSpec = "16:tanh:layernorm:dropout(0.1):orthogonal,8:elu:xavier,out:uniform(0.003)"
+            .parse()
+            .unwrap();
+        let states = tch::Tensor::from_slice(&[0.1f64, -0.2, 0.3, 0.4]).view([1, 4]).to_device(**crate::device);
+
+        let actor = Actor::new(4, 2, spec.clone(), None, 1f64).unwrap();
+        let json = serde_json::to_value(&actor).unwrap();
+        let loaded: Actor = serde_json::from_value(json.clone()).unwrap();
+
+        assert!(!loaded.actor.legacy);
+        assert_eq!(loaded.actor.layers.len(), 3);
+
+        for (layer, spec) in loaded.actor.layers.iter().zip(&spec.hidden) {
+            assert_eq!(layer.output, spec.size);
+            assert_eq!(layer.activation, spec.activation);
+            assert_eq!(layer.layer_norm.is_some(), spec.layer_norm);
+            assert_eq!(layer.dropout, spec.dropout);
+            assert_eq!(layer.init, spec.init);
+        }
+
+        assert_eq!(loaded.actor.layers[2].activation, Activation::Identity);
+        assert_eq!(loaded.actor.layers[2].init, spec.output_init);
+        assert!(actor.forward(&states, false).allclose(&loaded.forward(&states, false), 1e-6, 1e-6, false));
+
+        // without specs the layers are a checkpoint from before them, which skipped the middle layers
+        let mut legacy_json = serde_json::to_value(&Actor::new(4, 2, NetworkSpec::from(vec![8, 8]), None, 1f64).unwrap()).unwrap();
+        for layer in legacy_json["actor_network"].as_array_mut().unwrap() {
+            let layer = layer.as_object_mut().unwrap();
+            layer.retain(|key, _| key == "input_dim" || key == "output_dim");
+        }
+
+        let legacy: Actor = serde_json::from_value(legacy_json.clone()).unwrap();
+        assert!(legacy.actor.legacy);
+
+        let first = &legacy.actor.layers[0].layer;
+        let last = &legacy.actor.layers[2].layer;
+        let expected = <tch::nn::Linear as tch::nn::Module>::forward(
+            last,
+            &<tch::nn::Linear as tch::nn::Module>::forward(first, &states.totype(tch::Kind::Float)).relu(),
+        )
+        .tanh();
+        assert!(legacy.forward(&states, false).allclose(&expected, 1e-6, 1e-6, false));
+
+        // and saving it again keeps it a legacy checkpoint
+        assert_eq!(serde_json::to_value(&legacy).unwrap()["actor_network"], legacy_json["actor_network"]);
+    }
+
+    // one ticker of minute bars from the 2024-01-02 open given as (open, high, low, close), reset and ready to step
+    fn bar_env(
+        name: &str,
+        bars: &[(f64, f64, f64, f64)],
+        margin: Option<MarginConfig>,
+        orders: Option<OrderConfig>,
+        cost_model: std::rc::Rc<dyn CostModel>,
+    ) -> StockEnv {
+        let filename = std::env::temp_dir().join(format!("milkshake_{}.csv", name));
+
+        let mut csv = String::from("timestamp,open,high,low,close,volume\n");
+        for (minute, (open, high, low, close)) in bars.iter().enumerate() {
+            csv.push_str(&format!(
+                "2024-01-02 14:{:02}:00,{},{},{},{},1000\n",
+                30 + minute,
+                open,
+                high,
+                low,
+                close
+            ));
+        }
+        std::fs::write(&filename, csv).unwrap();
+
+        let schema = BarSchema {
+            timestamp_format: Some(String::from("%Y-%m-%d %H:%M:%S")),
+            ..Default::default()
+        };
+
+        let stockframe = StockFrame::from_csv(filename.to_str().unwrap(), &schema, None, None, None, None).unwrap();
+        let config = StockEnvConfig {
+            tickers: stockframe.tickers.clone(),
+            indicators: vec![Indicator::Atr(14)],
+            cost_model,
+            margin,
+            orders,
+            ..Default::default()
+        };
+
+        let mut env = StockEnv::from_stockframe(stockframe, config).unwrap();
+        env.reset();
+        env
+    }
+
+    #[test]
+    fn order_fill_prices() {
+        let bar = |open, high, low| Bar { open, high, low };
+        let limit = |action, price| Order {
+            ticker: 0,
+            kind: OrderKind::Limit,
+            action,
+            price,
+            stop_distance: None,
+            take_profit: None,
+            bars_left: Some(1),
+        };
+
+        // a buy limit fills at its price, or at the open when the bar gaps below it
+        assert_eq!(limit(1.0, 70.0).fill_price(&bar(75.0, 76.0, 69.0)), Some(70.0));
+        assert_eq!(limit(1.0, 70.0).fill_price(&bar(68.0, 69.0, 67.0)), Some(68.0));
+        assert_eq!(limit(1.0, 70.0).fill_price(&bar(75.0, 76.0, 71.0)), None);
+        assert_eq!(limit(-1.0, 90.0).fill_price(&bar(92.0, 93.0, 91.0)), Some(92.0));
+
+        // a long's stop sells on the way down and its take profit on the way up, gaps fill at the open
+        let (stop_loss, take_profit) = Order::bracket(0, 10.0, 80.0, Some(0.125), Some(0.25));
+        let (stop_loss, take_profit) = (stop_loss.unwrap(), take_profit.unwrap());
+        assert_eq!((stop_loss.price, take_profit.price), (70.0, 100.0));
+        assert_eq!(stop_loss.fill_price(&bar(75.0, 76.0, 70.0)), Some(70.0));
+        assert_eq!(stop_loss.fill_price(&bar(65.0, 66.0, 64.0)), Some(65.0));
+        assert_eq!(take_profit.fill_price(&bar(105.0, 106.0, 104.0)), Some(105.0));
+        assert_eq!(take_profit.fill_price(&bar(95.0, 99.0, 94.0)), None);
+
+        // a short's stop buys back on the way up
+        let (stop_loss, _) = Order::bracket(0, -10.0, 80.0, Some(0.125), None);
+        assert_eq!(stop_loss.unwrap().fill_price(&bar(95.0, 96.0, 94.0)), Some(95.0));
+
+        // limit_bars is a number of bars, not a fraction of one
+        assert_eq!(OrderConfig::from_json(&serde_json::json!({"limit_bars": 2})).unwrap().limit_bars, 2);
+        assert!(OrderConfig::from_json(&serde_json::json!({"limit_bars": 0.5})).is_err());
+        assert!(OrderConfig::from_json(&serde_json::json!({"limit_bars": 0})).is_err());
+    }
+
+    #[test]
+    fn resting_orders_fill_against_bars() {
+        let flat = (80.0, 80.5, 79.5, 80.0);
+        let orders = OrderConfig {
+            max_limit_offset: 0.25,
+            max_stop_distance: 0.25,
+            max_take_profit: 0.25,
+            limit_bars: 2,
+        };
+        let env = |name, bars: &[(f64, f64, f64, f64)]| {
+            bar_env(name, bars, None, Some(orders), std::rc::Rc::new(ZeroCost))
+        };
+        let nothing = vec![0.0; 4];
+
+        // 125 shares at 80 with a stop at 70 and a take profit at 100, then a bar that reaches both
+        let mut bracket = env("orders_bracket", &[flat, flat, (80.0, 101.0, 69.0, 80.0)]);
+        bracket.step(vec![1.0, 0.0, 0.5, 1.0]);
+        assert_eq!(bracket.holding(0), 125.0);
+        assert_eq!(bracket.order_book.resting(), 2);
+
+        // the stop is assumed to have come first
+        bracket.step(nothing.clone());
+        assert_eq!(bracket.holding(0), 0.0);
+        assert_eq!(bracket.filled_orders.len(), 1);
+        assert_eq!(bracket.filled_orders[0].1.kind, OrderKind::StopLoss);
+        assert_eq!(bracket.filled_orders[0].2, 70.0);
+        assert_eq!(bracket.closed_trades, vec![-1250.0]);
+        assert_eq!(bracket.order_book.resting(), 0);
+
+        // selling the position leaves its stop and take profit behind, they go away instead of filling
+        let mut sold = env("orders_sold", &[flat, flat, flat, (68.0, 69.0, 60.0, 65.0)]);
+        sold.step(vec![1.0, 0.0, 0.5, 1.0]);
+        sold.step(vec![-1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(sold.holding(0), 0.0);
+        sold.step(nothing.clone());
+        assert!(sold.filled_orders.is_empty());
+        assert_eq!(sold.order_book.resting(), 0);
+        assert_eq!(sold.state[0], 10000.0);
+
+        // a buy limit at 70 that the next two bars don't reach is cancelled before the price gets there
+        let mut expired = env("orders_expired", &[flat, flat, flat, flat, (68.0, 69.0, 60.0, 65.0)]);
+        expired.step(vec![1.0, 0.5, 0.0, 0.0]);
+        assert_eq!(expired.order_book.limit[0].unwrap().price, 70.0);
+        expired.step(nothing.clone());
+        assert_eq!(expired.order_book.limit[0].unwrap().bars_left, Some(1));
+        expired.step(nothing.clone());
+        assert_eq!(expired.order_book.resting(), 0);
+        expired.step(nothing);
+        assert_eq!(expired.holding(0), 0.0);
+        assert!(expired.filled_orders.is_empty());
+    }
+
+    #[test]
+    fn shorts_borrow_and_liquidate() {
+        let flat = (80.0, 80.5, 79.5, 80.0);
+        let margin = MarginConfig {
+            initial_margin: 0.5,
+            maintenance_margin: 0.3,
+            borrow_rate: 0.03,
+        };
+        let mut env = bar_env(
+            "shorts",
+            &[flat, flat, (60.0, 60.5, 59.5, 60.0), (100.0, 100.5, 99.5, 100.0)],
+            Some(margin),
+            None,
+            std::rc::Rc::new(ZeroCost),
+        );
+
+        // half of the 250 shares 10k can put up the initial margin for
+        env.step(vec![-0.5]);
+        assert_eq!(env.holding(0), -125.0);
+        assert_eq!(env.buy_price[0], 80.0);
+        assert_eq!(env.state[0], 20000.0);
+        assert_eq!(env.borrow_fees, vec![0.0]);
+
+        // the price dropped so the short is up, adding to it moves the average entry
+        env.step(vec![-0.2]);
+        let minute_fee = |short_value: f64| short_value * 0.03 * 60.0 / (365.0 * 24.0 * 60.0 * 60.0);
+        let entry = (125.0 * 80.0 + 58.0 * 60.0) / 183.0;
+        assert_eq!(env.holding(0), -183.0);
+        assert!((env.buy_price[0] - entry).abs() < 1e-9);
+        assert!((env.borrow_fees[1] - minute_fee(125.0 * 60.0)).abs() < 1e-12);
+        assert!(env.unrealized_pnl[0] > 0.0);
+        assert!((env.unrealized_pnl[0] - (entry - 60.0) * 183.0).abs() < 1e-9);
+
+        // at 100 equity is 5180 against the 5490 maintenance margin on 18300 of shorts, so everything is bought back
+        env.step(vec![0.0]);
+        let fees = minute_fee(125.0 * 60.0) + minute_fee(183.0 * 100.0);
+        assert_eq!(env.liquidations, 1);
+        assert_eq!(env.holding(0), 0.0);
+        assert_eq!(env.unrealized_pnl[0], 0.0);
+        assert!((env.borrow_fees[2] - minute_fee(183.0 * 100.0)).abs() < 1e-12);
+        assert!((env.state[0] - (5180.0 - fees)).abs() < 1e-9);
+        assert!((env.closed_trades[0] - (entry - 100.0) * 183.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn trades_pay_costs_in_cash() {
+        let flat = (80.0, 80.5, 79.5, 80.0);
+        let costs = StandardCostModel {
+            commission: Commission::Percent(0.001),
+            spread: Spread::Fixed(0.02),
+            slippage: Slippage::None,
+        };
+        let mut env = bar_env(
+            "costs",
+            &[flat, flat, (100.0, 100.5, 99.5, 100.0)],
+            None,
+            None,
+            std::rc::Rc::new(costs),
+        );
+
+        // 125 shares and their costs don't fit in 10k, 124 do
+        env.step(vec![1.0]);
+        assert_eq!(env.holding(0), 124.0);
+        assert!((env.cost_history[0].commission - 9.92).abs() < 1e-9);
+        assert!((env.cost_history[0].spread - 1.24).abs() < 1e-9);
+        assert!((env.state[0] - (10000.0 - 9920.0 - 11.16)).abs() < 1e-9);
+
+        env.step(vec![-1.0]);
+        assert_eq!(env.holding(0), 0.0);
+        assert!((env.cost_history[1].total() - 13.64).abs() < 1e-9);
+        assert!((env.closed_trades[0] - (20.0 * 124.0 - 13.64)).abs() < 1e-9);
+        assert!((env.state[0] - (10000.0 - 11.16 + 20.0 * 124.0 - 13.64)).abs() < 1e-9);
+        assert_eq!(env.traded_value, vec![9920.0, 12400.0]);
+    }
+
+    #[test]
+    fn backtest_report_metrics() {
+        let flat = (80.0, 80.5, 79.5, 80.0);
+        let mut env = bar_env("report", &[flat, flat], None, None, std::rc::Rc::new(ZeroCost));
+
+        // daily returns of 10%, -10%, 10% and 10%
+        let day = |day| {
+            polars::export::chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        env.timeline = (1..=5).map(day).collect();
+        env.total_asset = vec![100.0, 110.0, 99.0, 108.9, 119.79];
+        env.closed_trades = vec![10.0, -5.0, 3.0, 0.0];
+        env.traded_value = vec![100.0, 50.0, 0.0, 200.0];
+        env.gross_exposures = vec![1.0, 0.5, 0.5, 0.0];
+        env.cost_history = vec![];
+        env.borrow_fees = vec![0.5, 0.25];
+
+        let report = BacktestReport::from_env("hand made", &env);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9 * f64::max(b.abs(), 1.0);
+
+        // four returns in four days, mean 0.05 with a sample deviation of 0.1 and a downside deviation of 0.05
+        let periods_per_year = 365.25f64;
+        let annualized = 1.1979f64.powf(365.25 / 4.0) - 1.0;
+
+        assert_eq!(report.steps, 4);
+        assert!(close(report.total_return, 0.1979));
+        assert!(close(report.annualized_return, annualized));
+        assert!(close(report.sharpe, 0.5 * periods_per_year.sqrt()));
+        assert!(close(report.sortino, periods_per_year.sqrt()));
+        assert!(close(report.max_drawdown, 0.1));
+        assert!(close(report.calmar, annualized / 0.1));
+        assert_eq!(report.max_drawdown_duration, polars::export::chrono::Duration::days(2));
+        assert_eq!(report.trades, 4);
+        assert!(close(report.win_rate, 0.5));
+        assert!(close(report.turnover, 350.0 / (537.69 / 5.0)));
+        assert!(close(report.exposure, 0.5));
+        assert!(close(report.costs, 0.75));
+    }
 }
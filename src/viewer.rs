@@ -2,20 +2,192 @@
 
 extern crate glfw_bindgen;
 
-use crate::environment::Mujoco;
+use crate::environment::{Mujoco, Terminate, Trajectory};
+use crate::recording::{read_state, write_state, Recording};
 use crate::td3::TD3;
 
+// simulation speed multiplier bounds for the +/- keys
+const MIN_SPEED: f64 = 1f64 / 16f64;
+const MAX_SPEED: f64 = 16f64;
+
+// how many observation values the observation panel shows when no indices were picked
+const DEFAULT_OBSERVATION_COUNT: usize = 12;
+
+// how often watch mode looks for a newer checkpoint
+const CHECKPOINT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+// finds the newest ./models/{name}_{t}_steps.banan checkpoint written by run_td3
+pub struct CheckpointWatcher {
+    dir: std::path::PathBuf,
+    name: String,
+    loaded_step: Option<u32>,
+    last_poll: Option<std::time::Instant>,
+}
+
+impl CheckpointWatcher {
+    pub fn new(dir: &str, name: &str) -> Self {
+        CheckpointWatcher {
+            dir: std::path::PathBuf::from(dir),
+            name: String::from(name),
+            loaded_step: None,
+            last_poll: None,
+        }
+    }
+
+    pub fn loaded_step(&self) -> Option<u32> {
+        self.loaded_step
+    }
+
+    fn newest(&self) -> Option<(u32, std::path::PathBuf)> {
+        let prefix = format!("{}_", self.name);
+
+        std::fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                let step = file_name
+                    .strip_prefix(prefix.as_str())?
+                    .strip_suffix("_steps.banan")?
+                    .parse::<u32>()
+                    .ok()?;
+
+                Some((step, entry.path()))
+            })
+            .max_by_key(|(step, _)| *step)
+    }
+
+    // returns the newest checkpoint if it is newer than the loaded one, at most once per poll interval
+    pub fn poll(&mut self) -> Option<(u32, TD3)> {
+        if let Some(last_poll) = self.last_poll {
+            if last_poll.elapsed() < CHECKPOINT_POLL_INTERVAL {
+                return None;
+            }
+        }
+        self.last_poll = Some(std::time::Instant::now());
+
+        let (step, path) = self.newest()?;
+
+        if self.loaded_step.is_some_and(|loaded_step| loaded_step >= step) {
+            return None;
+        }
+
+        // run_td3 might still be writing the file, a half written checkpoint just gets picked up next poll
+        let data = std::fs::read_to_string(&path).ok()?;
+        let td3: TD3 = serde_json::from_str(data.as_str()).ok()?;
+
+        self.loaded_step = Some(step);
+        Some((step, td3))
+    }
+}
+
+// frames skipped by page up/page down while replaying
+const SEEK_JUMP: i64 = 100;
+
+// what moves the env forward every step
+enum Driver {
+    Policy(TD3),
+    // frame is the number of recorded steps that have been played back so far
+    Replay { recording: Recording, frame: usize },
+}
+
+// another policy running in its own copy of the env, rendered in its own viewport next to the main one
+struct Rival {
+    label: String,
+    env: Box<dyn Mujoco>,
+    td3: TD3,
+    cam: crate::wrappers::mujoco::mjvCamera,
+    episode_done: bool,
+    episode_return: f64,
+    last_episode_return: Option<f64>,
+}
+
+// everything the glfw callbacks touch, boxed so the window user pointer stays valid when the viewer moves
+struct ViewerInput {
+    paused: bool,
+    step_requested: bool,
+    reset_requested: bool,
+    // relative frame jump requested while replaying
+    seek: i64,
+    tracking: bool,
+    speed: f64,
+
+    show_info: bool,
+    show_rewards: bool,
+    show_actions: bool,
+    show_observations: bool,
+
+    button_left: bool,
+    button_middle: bool,
+    button_right: bool,
+    last_x: f64,
+    last_y: f64,
+
+    // (mjtMouse action, reldx, reldy) applied to the camera on the next frame
+    camera_moves: Vec<(libc::c_int, f64, f64)>,
+
+    // ctrl+click position as (relx, rely) from the bottom left, picked up by the next frame
+    perturb_select: Option<(f64, f64)>,
+    // mjtPertBit of the running ctrl+drag, 0 when nothing is being dragged
+    perturb_kind: libc::c_int,
+    // same as camera_moves but for the selected body
+    perturb_moves: Vec<(libc::c_int, f64, f64)>,
+}
+
+impl Default for ViewerInput {
+    fn default() -> Self {
+        ViewerInput {
+            paused: false,
+            step_requested: false,
+            reset_requested: false,
+            seek: 0,
+            tracking: true,
+            speed: 1f64,
+            show_info: true,
+            show_rewards: true,
+            show_actions: true,
+            show_observations: false,
+            button_left: false,
+            button_middle: false,
+            button_right: false,
+            last_x: 0f64,
+            last_y: 0f64,
+            camera_moves: Vec::new(),
+            perturb_select: None,
+            perturb_kind: 0,
+            perturb_moves: Vec::new(),
+        }
+    }
+}
+
 pub struct Viewer<'vw> {
     window: &'vw mut glfw_bindgen::GLFWwindow,
     scale: f64,
+    input: Box<ViewerInput>,
 
     cam: crate::wrappers::mujoco::mjvCamera,
     opt: crate::wrappers::mujoco::mjvOption,
+    pert: crate::wrappers::mujoco::mjvPerturb,
     scene: crate::wrappers::mujoco::mjvScene,
     context: crate::wrappers::mujoco::mjrContext,
 
     env: Box<dyn Mujoco>,
-    td3: TD3,
+    driver: Driver,
+    watcher: Option<CheckpointWatcher>,
+    label: String,
+    rivals: Vec<Rival>,
+
+    ts: Option<Box<dyn Trajectory>>,
+    episode_done: bool,
+    episode_num: u32,
+    episode_return: f64,
+    last_episode_return: Option<f64>,
+    last_action: Vec<f64>,
+
+    observation_indices: Option<Vec<usize>>,
+    action_figure: Box<crate::wrappers::mujoco::mjvFigure>,
+    frame_clock: std::time::Instant,
+    realtime_factor: f64,
 }
 
 impl Viewer<'_> {
@@ -24,6 +196,33 @@ impl Viewer<'_> {
         td3: TD3,
         width: Option<u32>,
         height: Option<u32>,
+    ) -> Self {
+        Self::with_driver(env, Driver::Policy(td3), width, height)
+    }
+
+    // plays a recorded episode back instead of running a policy, recording has to be checked against env
+    pub fn replay(
+        env: Box<dyn Mujoco>,
+        recording: Recording,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Self {
+        Self::with_driver(
+            env,
+            Driver::Replay {
+                recording,
+                frame: 0,
+            },
+            width,
+            height,
+        )
+    }
+
+    fn with_driver(
+        env: Box<dyn Mujoco>,
+        driver: Driver,
+        width: Option<u32>,
+        height: Option<u32>,
     ) -> Self {
         unsafe {
             assert_eq!(glfw_bindgen::glfwInit(), glfw_bindgen::GLFW_TRUE as i32);
@@ -42,7 +241,7 @@ impl Viewer<'_> {
             let window_raw = glfw_bindgen::glfwCreateWindow(
                 width as libc::c_int,
                 height as libc::c_int,
-                "Milkshake".as_ptr() as *const libc::c_char,
+                "Milkshake\0".as_ptr() as *const libc::c_char,
                 std::ptr::null_mut(),
                 std::ptr::null_mut(),
             );
@@ -67,32 +266,73 @@ impl Viewer<'_> {
 
             let scale = framebuffer_width as f64 * (1f64 / window_width as f64);
 
+            let mut input = Box::<ViewerInput>::default();
+            glfw_bindgen::glfwSetWindowUserPointer(
+                window,
+                input.as_mut() as *mut ViewerInput as *mut libc::c_void,
+            );
+
             glfw_bindgen::glfwSetKeyCallback(window, Some(Self::key_callback));
+            glfw_bindgen::glfwSetMouseButtonCallback(window, Some(Self::mouse_button_callback));
+            glfw_bindgen::glfwSetCursorPosCallback(window, Some(Self::cursor_pos_callback));
+            glfw_bindgen::glfwSetScrollCallback(window, Some(Self::scroll_callback));
 
             let mut cam_uninit = std::mem::MaybeUninit::uninit();
             let mut opt_uninit = std::mem::MaybeUninit::uninit();
+            let mut pert_uninit = std::mem::MaybeUninit::uninit();
             let mut scene_uninit = std::mem::MaybeUninit::uninit();
             let mut context_uninit = std::mem::MaybeUninit::uninit();
 
             crate::wrappers::mujoco::mjv_defaultCamera(cam_uninit.as_mut_ptr());
             crate::wrappers::mujoco::mjv_defaultOption(opt_uninit.as_mut_ptr());
+            crate::wrappers::mujoco::mjv_defaultPerturb(pert_uninit.as_mut_ptr());
             crate::wrappers::mujoco::mjv_defaultScene(scene_uninit.as_mut_ptr());
             crate::wrappers::mujoco::mjr_defaultContext(context_uninit.as_mut_ptr());
 
             let cam = cam_uninit.assume_init();
             let opt = opt_uninit.assume_init();
+            let pert = pert_uninit.assume_init();
             let scene = scene_uninit.assume_init();
             let context = context_uninit.assume_init();
 
+            // mjvFigure is close to a megabyte so keep it off the stack
+            let layout = std::alloc::Layout::new::<crate::wrappers::mujoco::mjvFigure>();
+            let ptr = std::alloc::alloc(layout) as *mut crate::wrappers::mujoco::mjvFigure;
+            crate::wrappers::mujoco::mjv_defaultFigure(ptr);
+
+            let mut action_figure = Box::from_raw(ptr);
+            write_c_str(&mut action_figure.title, "Action");
+            action_figure.flg_extend = 0;
+            action_figure.gridsize = [2, 3];
+            action_figure.figurergba[3] = 0.5;
+
             Viewer {
                 window,
                 scale,
+                input,
                 cam,
                 opt,
+                pert,
                 scene,
                 context,
                 env,
-                td3,
+                watcher: None,
+                label: match driver {
+                    Driver::Policy(_) => String::from("Policy"),
+                    Driver::Replay { .. } => String::from("Replay"),
+                },
+                driver,
+                rivals: Vec::new(),
+                ts: None,
+                episode_done: false,
+                episode_num: 0,
+                episode_return: 0f64,
+                last_episode_return: None,
+                last_action: Vec::new(),
+                observation_indices: None,
+                action_figure,
+                frame_clock: std::time::Instant::now(),
+                realtime_factor: 0f64,
             }
         }
     }
@@ -115,18 +355,43 @@ impl Viewer<'_> {
                 as f64
         };
 
+        self.reset_episode();
+        self.frame_clock = std::time::Instant::now();
+
         while unsafe { glfw_bindgen::glfwWindowShouldClose(self.window) == 0 } {
-            let obs = self.env.observation();
-            let action = self.td3.select_action(obs);
-            unsafe {
-                std::ptr::copy_nonoverlapping(action.as_ptr(), self.env.data().ctrl, action.len());
+            self.update_policy();
+
+            let frame_simstart = self.sim_time();
+
+            if self.input.reset_requested {
+                self.input.reset_requested = false;
+                self.reset_episode();
+            }
+
+            if self.input.seek != 0 {
+                self.seek(self.input.seek);
+                self.input.seek = 0;
             }
 
-            let simstart = self.env.data().time;
-            while self.env.data().time - simstart < 1f64 / refreshrate {
-                println!("Time: {:.3}", self.env.data().time);
-                unsafe { crate::wrappers::mujoco::mj_step(self.env.model(), self.env.data()) };
+            if !self.input.paused {
+                // run as many env steps as it takes to cover one frame of (scaled) wall clock time
+                let frame_time = self.input.speed / refreshrate;
+                let simstart = self.sim_time();
+
+                while self.sim_time() - simstart < frame_time {
+                    if self.step_episode() {
+                        break;
+                    }
+                }
+            } else if self.input.step_requested {
+                self.step_episode();
             }
+            self.input.step_requested = false;
+
+            self.update_realtime_factor(frame_simstart);
+            self.update_title();
+            self.update_camera();
+            self.update_perturbation();
 
             let mut viewport = crate::wrappers::mujoco::mjrRect {
                 left: 0,
@@ -141,11 +406,20 @@ impl Viewer<'_> {
                     &mut viewport.width,
                     &mut viewport.height,
                 );
+            };
+
+            // the main policy gets the leftmost slice, rivals go to its right
+            viewport.width /= 1 + self.rivals.len() as libc::c_int;
+
+            // rivals first so the scene still holds the main env afterwards for mjv_select
+            self.render_rivals(viewport);
+
+            unsafe {
                 crate::wrappers::mujoco::mjv_updateScene(
                     self.env.model(),
                     self.env.data(),
                     &self.opt,
-                    std::ptr::null_mut(),
+                    &self.pert,
                     &mut self.cam,
                     crate::wrappers::mujoco::mjtCatBit__mjCAT_ALL as libc::c_int,
                     &mut self.scene,
@@ -157,34 +431,790 @@ impl Viewer<'_> {
                     &mut self.scene,
                 );
                 crate::wrappers::mujoco::mjr_render(viewport, &mut self.scene, &self.context);
+            };
+
+            self.render_overlays(viewport);
+
+            unsafe {
                 glfw_bindgen::glfwSwapBuffers(self.window);
                 glfw_bindgen::glfwPollEvents();
             };
         }
+    }
+
+    // runs td3 in env next to the main policy, every episode starts from the main env's initial state
+    pub fn compare(&mut self, env: Box<dyn Mujoco>, td3: TD3, label: &str) {
+        assert!(
+            matches!(self.driver, Driver::Policy(_)),
+            "Policies can only be compared against a live policy"
+        );
+
+        self.rivals.push(Rival {
+            label: String::from(label),
+            env,
+            td3,
+            cam: self.cam,
+            episode_done: false,
+            episode_return: 0f64,
+            last_episode_return: None,
+        });
+    }
+
+    // name of the main policy when comparing
+    pub fn set_label(&mut self, label: &str) {
+        self.label = String::from(label);
+    }
 
-        unsafe { crate::wrappers::mujoco::mjv_freeScene(&mut self.scene) };
+    // keeps swapping in the newest checkpoint the watcher finds while rendering
+    pub fn watch(&mut self, watcher: CheckpointWatcher) {
+        self.watcher = Some(watcher);
+    }
+
+    // makes the episodes repeat across runs, rivals start from the main env's state either way
+    pub fn set_seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    // picks which observation values the observation panel shows
+    pub fn set_observation_indices(&mut self, indices: Vec<usize>) {
+        self.observation_indices = Some(indices);
+    }
+
+    fn reset_episode(&mut self) {
+        self.ts = Some(self.env.reset());
+        self.episode_done = false;
+        self.episode_return = 0f64;
+        self.last_action = Vec::new();
+
+        // every rival starts from exactly the state the main env was reset to
+        if !self.rivals.is_empty() {
+            let (qpos, qvel) = read_state(self.env.as_mut());
+            let time = self.env.data().time;
+
+            for rival in self.rivals.iter_mut() {
+                rival.env.reset();
+                write_state(rival.env.as_mut(), &qpos, &qvel, time);
+
+                rival.episode_done = false;
+                rival.episode_return = 0f64;
+            }
+        }
+
+        if let Driver::Replay { recording, frame } = &mut self.driver {
+            recording.restore_initial(self.env.as_mut());
+            *frame = 0;
+        }
+    }
+
+    // advances the env by one step, returns true if the episode (or the recording) ended
+    fn step_episode(&mut self) -> bool {
+        if let Driver::Replay { .. } = self.driver {
+            return self.step_replay();
+        }
+
+        if !self.episode_done {
+            let observation = match &self.ts {
+                Some(ts) => ts.observation(),
+                None => self.env.observation(),
+            };
+
+            let action = match &self.driver {
+                Driver::Policy(td3) => td3.select_action(observation),
+                Driver::Replay { .. } => unreachable!(),
+            };
+
+            self.apply_perturbation();
+            let ts = self.env.step(action.clone());
+            self.last_action = action;
+
+            self.episode_return += ts.reward().unwrap_or(0f64);
+            self.episode_done = ts.as_any().downcast_ref::<Terminate>().is_some();
+            self.ts = Some(ts);
+
+            if self.episode_done {
+                println!(
+                    "Episode Num: {} Return: {:.3}",
+                    self.episode_num + 1,
+                    self.episode_return
+                );
+
+                self.last_episode_return = Some(self.episode_return);
+            }
+        }
+
+        // finished policies wait for the rest so the next episode starts together
+        for rival in self.rivals.iter_mut().filter(|rival| !rival.episode_done) {
+            let action = rival.td3.select_action(rival.env.observation());
+            let ts = rival.env.step(action);
+
+            rival.episode_return += ts.reward().unwrap_or(0f64);
+            rival.episode_done = ts.as_any().downcast_ref::<Terminate>().is_some();
+
+            if rival.episode_done {
+                println!(
+                    "Episode Num: {} {} Return: {:.3}",
+                    self.episode_num + 1,
+                    rival.label,
+                    rival.episode_return
+                );
+
+                rival.last_episode_return = Some(rival.episode_return);
+            }
+        }
+
+        let done = self.episode_done && self.rivals.iter().all(|rival| rival.episode_done);
+
+        if done {
+            self.episode_num += 1;
+            self.reset_episode();
+        }
+
+        done
+    }
+
+    // all running envs have taken the same number of steps, so any of them tells the time
+    fn sim_time(&mut self) -> f64 {
+        if !self.episode_done {
+            return self.env.data().time;
+        }
+
+        match self.rivals.iter_mut().find(|rival| !rival.episode_done) {
+            Some(rival) => rival.env.data().time,
+            None => self.env.data().time,
+        }
+    }
+
+    fn render_rivals(&mut self, viewport: crate::wrappers::mujoco::mjrRect) {
+        for (idx, rival) in self.rivals.iter_mut().enumerate() {
+            let rival_viewport = crate::wrappers::mujoco::mjrRect {
+                left: viewport.width * (idx as libc::c_int + 1),
+                ..viewport
+            };
+
+            // same view as the main camera, a tracking camera follows the rival's own body
+            rival.cam = self.cam;
+
+            unsafe {
+                crate::wrappers::mujoco::mjv_updateScene(
+                    rival.env.model(),
+                    rival.env.data(),
+                    &self.opt,
+                    std::ptr::null(),
+                    &mut rival.cam,
+                    crate::wrappers::mujoco::mjtCatBit__mjCAT_ALL as libc::c_int,
+                    &mut self.scene,
+                );
+                crate::wrappers::mujoco::mjr_render(rival_viewport, &mut self.scene, &self.context);
+            };
+
+            let mut rows = vec![
+                (rival.label.clone(), String::new()),
+                (String::from("Return"), format!("{:.3}", rival.episode_return)),
+            ];
+
+            if let Some(last_episode_return) = rival.last_episode_return {
+                rows.push((String::from("Last Return"), format!("{:.3}", last_episode_return)));
+            }
+
+            if rival.episode_done {
+                rows.push((String::from("Status"), String::from("Done")));
+            }
+
+            Self::overlay(
+                &self.context,
+                crate::wrappers::mujoco::mjtGridPos__mjGRID_TOPLEFT,
+                rival_viewport,
+                rows,
+            );
+        }
+    }
+
+    // plays back one recorded step, recorded states are restored directly and actions are stepped again
+    fn step_replay(&mut self) -> bool {
+        let finished = match &self.driver {
+            Driver::Replay { recording, frame } => *frame >= recording.len(),
+            Driver::Policy(_) => unreachable!(),
+        };
+
+        // unpausing at the end starts the recording over
+        if finished {
+            self.reset_episode();
+        }
+
+        let (recording, frame) = match &mut self.driver {
+            Driver::Replay { recording, frame } => (recording, frame),
+            Driver::Policy(_) => unreachable!(),
+        };
+
+        if recording.has_states() {
+            recording.restore(self.env.as_mut(), *frame);
+        } else {
+            let ts = self.env.step(recording.actions[*frame].clone());
+            self.ts = Some(ts);
+        }
+
+        if let Some(action) = recording.actions.get(*frame) {
+            self.last_action = action.clone();
+        }
+
+        self.episode_return += match recording.reward(*frame) {
+            Some(reward) => reward,
+            None => match &self.ts {
+                Some(ts) => ts.reward().unwrap_or(0f64),
+                None => 0f64,
+            },
+        };
+
+        *frame += 1;
+
+        let done = *frame >= recording.len();
+        if done {
+            println!("Replay finished with Return: {:.3}", self.episode_return);
+            self.last_episode_return = Some(self.episode_return);
+            self.input.paused = true;
+        }
+
+        done
+    }
+
+    // jumps offset frames through the recording, replaying from the start when going backwards through actions
+    fn seek(&mut self, offset: i64) {
+        let (len, current) = match &self.driver {
+            Driver::Replay { recording, frame } => (recording.len(), *frame),
+            Driver::Policy(_) => return,
+        };
+
+        let target = (current as i64 + offset).clamp(0, len as i64) as usize;
+
+        let states = match &self.driver {
+            Driver::Replay { recording, .. } => recording.has_states(),
+            Driver::Policy(_) => unreachable!(),
+        };
+
+        if target < current || states {
+            self.reset_episode();
+        }
+
+        let paused = self.input.paused;
+
+        // restoring a state is cheap, so just walk to the target frame to keep the return right
+        while let Driver::Replay { frame, .. } = &self.driver {
+            if *frame >= target {
+                break;
+            }
+
+            self.step_replay();
+        }
+
+        self.input.paused = paused;
+    }
+
+    fn update_policy(&mut self) {
+        let checkpoint = match &mut self.watcher {
+            Some(watcher) => watcher.poll(),
+            None => None,
+        };
+
+        // restart the episode so every return belongs to a single checkpoint
+        if let Some((step, td3)) = checkpoint {
+            println!("Loaded checkpoint: {} steps", step);
+
+            self.driver = Driver::Policy(td3);
+            self.reset_episode();
+        }
+    }
+
+    fn checkpoint_step(&self) -> Option<u32> {
+        match &self.watcher {
+            Some(watcher) => watcher.loaded_step(),
+            None => None,
+        }
+    }
+
+    fn update_title(&mut self) {
+        let mut title = format!(
+            "Milkshake | Episode {} | Return {:.2}",
+            self.episode_num + 1,
+            self.episode_return
+        );
+
+        if let Some(step) = self.checkpoint_step() {
+            title.push_str(format!(" | Checkpoint {} steps", step).as_str());
+        }
+
+        if let Some(last_episode_return) = self.last_episode_return {
+            title.push_str(format!(" | Last Return {:.2}", last_episode_return).as_str());
+        }
+
+        if self.input.paused {
+            title.push_str(" | Paused");
+        }
+
+        let title = std::ffi::CString::new(title).expect("Window title contains a nul byte");
+        unsafe { glfw_bindgen::glfwSetWindowTitle(self.window, title.as_ptr()) };
+    }
+
+    // simulated seconds per wall clock second, smoothed over the last few frames
+    fn update_realtime_factor(&mut self, frame_simstart: f64) {
+        let wall_time = self.frame_clock.elapsed().as_secs_f64();
+        self.frame_clock = std::time::Instant::now();
+
+        let sim_time = self.sim_time() - frame_simstart;
+
+        // the sim clock jumps back to zero on resets, just skip that frame
+        if sim_time < 0f64 || wall_time <= 0f64 {
+            return;
+        }
+
+        self.realtime_factor = 0.9f64 * self.realtime_factor + 0.1f64 * (sim_time / wall_time);
+    }
+
+    fn render_overlays(&mut self, viewport: crate::wrappers::mujoco::mjrRect) {
+        if self.input.show_info {
+            let mut info = vec![
+                (self.label.clone(), String::new()),
+                (String::from("Episode"), format!("{}", self.episode_num + 1)),
+                (String::from("Return"), format!("{:.3}", self.episode_return)),
+                (
+                    String::from("Last Return"),
+                    match self.last_episode_return {
+                        None => String::from("-"),
+                        Some(last_episode_return) => format!("{:.3}", last_episode_return),
+                    },
+                ),
+                (String::from("Sim Time"), format!("{:.3}", self.env.data().time)),
+                (String::from("Speed"), format!("{}x", self.input.speed)),
+                (String::from("Realtime Factor"), format!("{:.2}", self.realtime_factor)),
+            ];
+
+            if let Some(step) = self.checkpoint_step() {
+                info.push((String::from("Checkpoint"), format!("{} steps", step)));
+            }
+
+            if let Driver::Replay { recording, frame } = &self.driver {
+                info.push((String::from("Frame"), format!("{} / {}", frame, recording.len())));
+            }
+
+            if self.episode_done {
+                info.push((String::from("Status"), String::from("Done")));
+            }
+
+            if self.input.paused {
+                info.push((String::from("Status"), String::from("Paused")));
+            }
+
+            Self::overlay(&self.context, crate::wrappers::mujoco::mjtGridPos__mjGRID_TOPLEFT, viewport, info);
+        }
+
+        if self.input.show_rewards {
+            let step_reward = match &self.ts {
+                Some(ts) => ts.reward(),
+                None => None,
+            };
+
+            let mut rewards = vec![(
+                String::from("Step Reward"),
+                match step_reward {
+                    None => String::from("-"),
+                    Some(step_reward) => format!("{:.3}", step_reward),
+                },
+            )];
+
+            for (name, value) in self.env.reward_info() {
+                rewards.push((name, format!("{:.3}", value)));
+            }
+
+            Self::overlay(&self.context, crate::wrappers::mujoco::mjtGridPos__mjGRID_TOPRIGHT, viewport, rewards);
+        }
+
+        if self.input.show_observations {
+            let observation = self.env.observation();
+            let indices = match &self.observation_indices {
+                Some(indices) => indices.clone(),
+                None => (0..usize::min(observation.len(), DEFAULT_OBSERVATION_COUNT)).collect(),
+            };
+
+            let observations = indices
+                .iter()
+                .filter(|idx| **idx < observation.len())
+                .map(|idx| (format!("obs[{}]", idx), format!("{:.3}", observation[*idx])))
+                .collect();
+
+            Self::overlay(&self.context, crate::wrappers::mujoco::mjtGridPos__mjGRID_BOTTOMLEFT, viewport, observations);
+        }
+
+        if self.input.show_actions && !self.last_action.is_empty() {
+            self.render_action_figure(viewport);
+        }
+    }
+
+    fn overlay(
+        context: &crate::wrappers::mujoco::mjrContext,
+        gridpos: crate::wrappers::mujoco::mjtGridPos,
+        viewport: crate::wrappers::mujoco::mjrRect,
+        rows: Vec<(String, String)>,
+    ) {
+        let (labels, values): (Vec<String>, Vec<String>) = rows.into_iter().unzip();
+
+        let labels = std::ffi::CString::new(labels.join("\n")).expect("Overlay label contains a nul byte");
+        let values = std::ffi::CString::new(values.join("\n")).expect("Overlay value contains a nul byte");
+
+        unsafe {
+            crate::wrappers::mujoco::mjr_overlay(
+                crate::wrappers::mujoco::mjtFont__mjFONT_NORMAL as libc::c_int,
+                gridpos as libc::c_int,
+                viewport,
+                labels.as_ptr(),
+                values.as_ptr(),
+                context,
+            );
+        }
+    }
+
+    // mjvFigure has no bar plots, each bar is drawn as a 4 point line like simulate's sensor figure
+    fn render_action_figure(&mut self, viewport: crate::wrappers::mujoco::mjrRect) {
+        let max_action = self.env.action_spec().max as f32;
+        let figure = self.action_figure.as_mut();
+
+        figure.linepnt[0] = (4 * self.last_action.len()) as libc::c_int;
+        figure.range = [
+            [0f32, self.last_action.len() as f32],
+            [-max_action, max_action],
+        ];
+
+        for (idx, action) in self.last_action.iter().enumerate() {
+            let left = idx as f32 + 0.1f32;
+            let right = idx as f32 + 0.9f32;
+            let points = [(left, 0f32), (left, *action as f32), (right, *action as f32), (right, 0f32)];
+
+            for (point_idx, (x, y)) in points.iter().enumerate() {
+                figure.linedata[0][2 * (4 * idx + point_idx)] = *x;
+                figure.linedata[0][2 * (4 * idx + point_idx) + 1] = *y;
+            }
+        }
+
+        let rect = crate::wrappers::mujoco::mjrRect {
+            left: viewport.width - viewport.width / 3,
+            bottom: 0,
+            width: viewport.width / 3,
+            height: viewport.height / 3,
+        };
+
+        unsafe { crate::wrappers::mujoco::mjr_figure(rect, figure, &self.context) };
+    }
+
+    // applies the camera type toggle and any mouse movement collected since the last frame
+    fn update_camera(&mut self) {
+        unsafe {
+            let tracking = self.cam.type_
+                == crate::wrappers::mujoco::mjtCamera__mjCAMERA_TRACKING as libc::c_int;
+
+            if self.input.tracking && !tracking {
+                self.cam.type_ = crate::wrappers::mujoco::mjtCamera__mjCAMERA_TRACKING as libc::c_int;
+                self.cam.trackbodyid = *self.env.model().cam_bodyid;
+            } else if !self.input.tracking && tracking {
+                // keep looking at the same spot, mjv_moveCamera handles the rest
+                self.cam.type_ = crate::wrappers::mujoco::mjtCamera__mjCAMERA_FREE as libc::c_int;
+            }
+
+            for (action, reldx, reldy) in self.input.camera_moves.drain(..) {
+                crate::wrappers::mujoco::mjv_moveCamera(
+                    self.env.model(),
+                    action,
+                    reldx,
+                    reldy,
+                    &self.scene,
+                    &mut self.cam,
+                );
+            }
+        }
+    }
+
+    // ctrl+click picks the body under the cursor, ctrl+drag then pulls it around like mujoco's simulate
+    fn update_perturbation(&mut self) {
+        unsafe {
+            if let Some((relx, rely)) = self.input.perturb_select.take() {
+                let mut width = 0;
+                let mut height = 0;
+                glfw_bindgen::glfwGetFramebufferSize(self.window, &mut width, &mut height);
+
+                let mut selpnt = [0f64; 3];
+                let mut geomid = [-1 as libc::c_int];
+                let mut flexid = [-1 as libc::c_int];
+                let mut skinid = [-1 as libc::c_int];
+
+                // only the main policy's viewport can be perturbed, rivals just run their policy
+                let viewports = 1f64 + self.rivals.len() as f64;
+                let relx = relx * viewports;
+
+                let selbody = match relx <= 1f64 {
+                    true => crate::wrappers::mujoco::mjv_select(
+                        self.env.model(),
+                        self.env.data(),
+                        &self.opt,
+                        width as f64 / viewports / height as f64,
+                        relx,
+                        rely,
+                        &self.scene,
+                        selpnt.as_mut_ptr(),
+                        geomid.as_mut_ptr(),
+                        flexid.as_mut_ptr(),
+                        skinid.as_mut_ptr(),
+                    ),
+                    false => -1,
+                };
+
+                // the world body can't be pushed around
+                if selbody > 0 {
+                    let data = self.env.data();
+                    let xpos = data.xpos.offset(3 * selbody as isize);
+                    let xmat = data.xmat.offset(9 * selbody as isize);
+
+                    // selection point in the body frame, so the force is applied where it was grabbed
+                    let offset = [
+                        selpnt[0] - *xpos.offset(0),
+                        selpnt[1] - *xpos.offset(1),
+                        selpnt[2] - *xpos.offset(2),
+                    ];
+                    crate::wrappers::mujoco::mju_mulMatTVec(
+                        self.pert.localpos.as_mut_ptr(),
+                        xmat,
+                        offset.as_ptr(),
+                        3,
+                        3,
+                    );
+
+                    self.pert.select = selbody;
+                    self.pert.flexselect = flexid[0];
+                    self.pert.skinselect = skinid[0];
+                } else {
+                    self.pert.select = 0;
+                    self.pert.flexselect = -1;
+                    self.pert.skinselect = -1;
+                }
+
+                self.pert.active = 0;
+            }
+
+            if self.input.perturb_kind == 0 || self.pert.select <= 0 {
+                self.pert.active = 0;
+                self.input.perturb_moves.clear();
+                return;
+            }
+
+            if self.pert.active != self.input.perturb_kind {
+                crate::wrappers::mujoco::mjv_initPerturb(
+                    self.env.model(),
+                    self.env.data(),
+                    &self.scene,
+                    &mut self.pert,
+                );
+                self.pert.active = self.input.perturb_kind;
+            }
+
+            for (action, reldx, reldy) in self.input.perturb_moves.drain(..) {
+                crate::wrappers::mujoco::mjv_movePerturb(
+                    self.env.model(),
+                    self.env.data(),
+                    action,
+                    reldx,
+                    reldy,
+                    &self.scene,
+                    &mut self.pert,
+                );
+            }
+        }
+    }
+
+    // xfrc_applied is never cleared by mj_step, so reset it every step and only add the active drag
+    fn apply_perturbation(&mut self) {
+        unsafe {
+            let nbody = self.env.model().nbody as usize;
+            std::ptr::write_bytes(self.env.data().xfrc_applied, 0, 6 * nbody);
+
+            crate::wrappers::mujoco::mjv_applyPerturbForce(
+                self.env.model(),
+                self.env.data(),
+                &self.pert,
+            );
+        }
+    }
+
+    unsafe fn input<'a>(window: *mut glfw_bindgen::GLFWwindow) -> &'a mut ViewerInput {
+        &mut *(glfw_bindgen::glfwGetWindowUserPointer(window) as *mut ViewerInput)
     }
 
     unsafe extern "C" fn key_callback(
-        _window: *mut glfw_bindgen::GLFWwindow,
+        window: *mut glfw_bindgen::GLFWwindow,
         key: libc::c_int,
         _scancode: libc::c_int,
-        _action: libc::c_int,
+        action: libc::c_int,
         _mods: libc::c_int,
     ) {
-        if key == glfw_bindgen::GLFW_KEY_ESCAPE as i32 {
-            std::process::exit(0);
+        if action == glfw_bindgen::GLFW_RELEASE as i32 {
+            return;
+        }
+
+        let input = Self::input(window);
+
+        match key as u32 {
+            // let render return so Drop gets to clean up the window and mujoco context
+            glfw_bindgen::GLFW_KEY_ESCAPE => {
+                glfw_bindgen::glfwSetWindowShouldClose(window, glfw_bindgen::GLFW_TRUE as i32)
+            }
+            glfw_bindgen::GLFW_KEY_SPACE => input.paused = !input.paused,
+            glfw_bindgen::GLFW_KEY_RIGHT => input.step_requested = input.paused,
+            glfw_bindgen::GLFW_KEY_LEFT => input.seek -= 1,
+            glfw_bindgen::GLFW_KEY_PAGE_UP => input.seek -= SEEK_JUMP,
+            glfw_bindgen::GLFW_KEY_PAGE_DOWN => input.seek += SEEK_JUMP,
+            glfw_bindgen::GLFW_KEY_HOME => input.reset_requested = true,
+            glfw_bindgen::GLFW_KEY_BACKSPACE => input.reset_requested = true,
+            glfw_bindgen::GLFW_KEY_EQUAL | glfw_bindgen::GLFW_KEY_KP_ADD => {
+                input.speed = f64::min(input.speed * 2f64, MAX_SPEED)
+            }
+            glfw_bindgen::GLFW_KEY_MINUS | glfw_bindgen::GLFW_KEY_KP_SUBTRACT => {
+                input.speed = f64::max(input.speed / 2f64, MIN_SPEED)
+            }
+            glfw_bindgen::GLFW_KEY_TAB => input.tracking = !input.tracking,
+            glfw_bindgen::GLFW_KEY_F1 => input.show_info = !input.show_info,
+            glfw_bindgen::GLFW_KEY_F2 => input.show_rewards = !input.show_rewards,
+            glfw_bindgen::GLFW_KEY_F3 => input.show_actions = !input.show_actions,
+            glfw_bindgen::GLFW_KEY_F4 => input.show_observations = !input.show_observations,
+            _ => {}
+        }
+    }
+
+    unsafe extern "C" fn mouse_button_callback(
+        window: *mut glfw_bindgen::GLFWwindow,
+        button: libc::c_int,
+        action: libc::c_int,
+        mods: libc::c_int,
+    ) {
+        let input = Self::input(window);
+        let ctrl = mods & glfw_bindgen::GLFW_MOD_CONTROL as i32 != 0;
+
+        if action == glfw_bindgen::GLFW_PRESS as i32 && ctrl {
+            let mut xpos = 0f64;
+            let mut ypos = 0f64;
+            glfw_bindgen::glfwGetCursorPos(window, &mut xpos, &mut ypos);
+
+            let mut width = 0;
+            let mut height = 0;
+            glfw_bindgen::glfwGetWindowSize(window, &mut width, &mut height);
+
+            input.perturb_select = Some((xpos / width as f64, (height as f64 - ypos) / height as f64));
+            input.perturb_kind = match button as u32 {
+                glfw_bindgen::GLFW_MOUSE_BUTTON_LEFT => {
+                    crate::wrappers::mujoco::mjtPertBit__mjPERT_ROTATE as libc::c_int
+                }
+                _ => crate::wrappers::mujoco::mjtPertBit__mjPERT_TRANSLATE as libc::c_int,
+            };
+        } else if action == glfw_bindgen::GLFW_RELEASE as i32 {
+            input.perturb_kind = 0;
         }
+
+        input.button_left = glfw_bindgen::glfwGetMouseButton(window, glfw_bindgen::GLFW_MOUSE_BUTTON_LEFT as i32)
+            == glfw_bindgen::GLFW_PRESS as i32;
+        input.button_middle = glfw_bindgen::glfwGetMouseButton(window, glfw_bindgen::GLFW_MOUSE_BUTTON_MIDDLE as i32)
+            == glfw_bindgen::GLFW_PRESS as i32;
+        input.button_right = glfw_bindgen::glfwGetMouseButton(window, glfw_bindgen::GLFW_MOUSE_BUTTON_RIGHT as i32)
+            == glfw_bindgen::GLFW_PRESS as i32;
+
+        glfw_bindgen::glfwGetCursorPos(window, &mut input.last_x, &mut input.last_y);
+    }
+
+    // same mapping as mujoco's simulate: left rotates, right pans, middle zooms, shift switches to the horizontal plane
+    unsafe extern "C" fn cursor_pos_callback(
+        window: *mut glfw_bindgen::GLFWwindow,
+        xpos: f64,
+        ypos: f64,
+    ) {
+        let input = Self::input(window);
+
+        let dx = xpos - input.last_x;
+        let dy = ypos - input.last_y;
+        input.last_x = xpos;
+        input.last_y = ypos;
+
+        if !input.button_left && !input.button_middle && !input.button_right {
+            return;
+        }
+
+        let mut width = 0;
+        let mut height = 0;
+        glfw_bindgen::glfwGetWindowSize(window, &mut width, &mut height);
+
+        let shift = glfw_bindgen::glfwGetKey(window, glfw_bindgen::GLFW_KEY_LEFT_SHIFT as i32)
+            == glfw_bindgen::GLFW_PRESS as i32
+            || glfw_bindgen::glfwGetKey(window, glfw_bindgen::GLFW_KEY_RIGHT_SHIFT as i32)
+                == glfw_bindgen::GLFW_PRESS as i32;
+
+        let ctrl = glfw_bindgen::glfwGetKey(window, glfw_bindgen::GLFW_KEY_LEFT_CONTROL as i32)
+            == glfw_bindgen::GLFW_PRESS as i32
+            || glfw_bindgen::glfwGetKey(window, glfw_bindgen::GLFW_KEY_RIGHT_CONTROL as i32)
+                == glfw_bindgen::GLFW_PRESS as i32;
+
+        let action = if input.button_right {
+            match shift {
+                true => crate::wrappers::mujoco::mjtMouse__mjMOUSE_MOVE_H,
+                false => crate::wrappers::mujoco::mjtMouse__mjMOUSE_MOVE_V,
+            }
+        } else if input.button_left {
+            match shift {
+                true => crate::wrappers::mujoco::mjtMouse__mjMOUSE_ROTATE_H,
+                false => crate::wrappers::mujoco::mjtMouse__mjMOUSE_ROTATE_V,
+            }
+        } else {
+            crate::wrappers::mujoco::mjtMouse__mjMOUSE_ZOOM
+        };
+
+        // with ctrl held the drag moves the perturbation instead of the camera
+        let moves = match ctrl && input.perturb_kind != 0 {
+            true => &mut input.perturb_moves,
+            false => &mut input.camera_moves,
+        };
+
+        moves.push((
+            action as libc::c_int,
+            dx / height as f64,
+            dy / height as f64,
+        ));
+    }
+
+    unsafe extern "C" fn scroll_callback(
+        window: *mut glfw_bindgen::GLFWwindow,
+        _xoffset: f64,
+        yoffset: f64,
+    ) {
+        let input = Self::input(window);
+
+        input.camera_moves.push((
+            crate::wrappers::mujoco::mjtMouse__mjMOUSE_ZOOM as libc::c_int,
+            0f64,
+            -0.05f64 * yoffset,
+        ));
     }
 }
 
 impl Drop for Viewer<'_> {
     fn drop(&mut self) {
         unsafe {
+            // the mujoco context has to go while its gl context is still alive
+            crate::wrappers::mujoco::mjv_freeScene(&mut self.scene);
+            crate::wrappers::mujoco::mjr_freeContext(&mut self.context);
+
             glfw_bindgen::glfwDestroyWindow(self.window);
             glfw_bindgen::glfwTerminate();
-
-            crate::wrappers::mujoco::mjr_freeContext(&mut self.context);
         }
     }
 }
+
+fn write_c_str(dst: &mut [libc::c_char], src: &str) {
+    let len = usize::min(src.len(), dst.len() - 1);
+
+    for (idx, byte) in src.as_bytes()[..len].iter().enumerate() {
+        dst[idx] = *byte as libc::c_char;
+    }
+
+    dst[len] = 0;
+}
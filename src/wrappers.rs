@@ -1,2 +1,3 @@
 pub mod mujoco;
+#[cfg(feature = "talib")]
 pub mod talib;